@@ -0,0 +1,521 @@
+use std::fmt;
+
+use crate::ast::AExp;
+use crate::ast::BExp;
+use crate::ast::Block;
+use crate::ast::Pgm;
+use crate::ast::Stmt;
+use crate::stack::run;
+use crate::stack::RUN_LIMIT;
+use crate::wp::annotated;
+use crate::wp::indent;
+
+// an abstract domain of integers, ordered by inclusion of the concrete values
+pub trait Domain: Clone + PartialEq + fmt::Display {
+    fn bottom() -> Self;
+    fn top() -> Self;
+    fn constant(i: i32) -> Self;
+    fn join(&self, other: &Self) -> Self;
+    // an upper bound of self and other that makes increasing chains finite
+    fn widen(&self, other: &Self) -> Self {
+        self.join(other)
+    }
+    fn plus(&self, other: &Self) -> Self;
+    fn divide(&self, other: &Self) -> Self;
+    // the values that are <= c, and >= c
+    fn at_most(&self, c: i32) -> Self;
+    fn at_least(&self, c: i32) -> Self;
+    fn contains(&self, i: i32) -> bool;
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Sign {
+    Bot,
+    Neg,
+    Zero,
+    Pos,
+    Top,
+}
+
+impl fmt::Display for Sign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Sign::Bot => "bot",
+            Sign::Neg => "-",
+            Sign::Zero => "0",
+            Sign::Pos => "+",
+            Sign::Top => "top",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl Sign {
+    fn meet(&self, other: &Sign) -> Sign {
+        match (self, other) {
+            (Sign::Top, s) | (s, Sign::Top) => *s,
+            (s1, s2) if s1 == s2 => *s1,
+            _ => Sign::Bot,
+        }
+    }
+}
+
+impl Domain for Sign {
+    fn bottom() -> Sign {
+        Sign::Bot
+    }
+    fn top() -> Sign {
+        Sign::Top
+    }
+    fn constant(i: i32) -> Sign {
+        match i {
+            i if i < 0 => Sign::Neg,
+            0 => Sign::Zero,
+            _ => Sign::Pos,
+        }
+    }
+    fn join(&self, other: &Sign) -> Sign {
+        match (self, other) {
+            (Sign::Bot, s) | (s, Sign::Bot) => *s,
+            (s1, s2) if s1 == s2 => *s1,
+            _ => Sign::Top,
+        }
+    }
+    fn plus(&self, other: &Sign) -> Sign {
+        match (self, other) {
+            (Sign::Bot, _s) | (_s, Sign::Bot) => Sign::Bot,
+            (Sign::Zero, s) | (s, Sign::Zero) => *s,
+            (s1, s2) if s1 == s2 => *s1,
+            _ => Sign::Top,
+        }
+    }
+    fn divide(&self, other: &Sign) -> Sign {
+        match (self, other) {
+            (Sign::Bot, _s) | (_s, Sign::Bot) | (_s, Sign::Zero) => Sign::Bot,
+            (Sign::Zero, _s) => Sign::Zero,
+            // integer division can round to 0, which has no sign of its own
+            _ => Sign::Top,
+        }
+    }
+    fn at_most(&self, c: i32) -> Sign {
+        match c {
+            c if c < 0 => self.meet(&Sign::Neg),
+            0 if *self == Sign::Pos => Sign::Bot,
+            _ => *self,
+        }
+    }
+    fn at_least(&self, c: i32) -> Sign {
+        match c {
+            c if c > 0 => self.meet(&Sign::Pos),
+            0 if *self == Sign::Neg => Sign::Bot,
+            _ => *self,
+        }
+    }
+    fn contains(&self, i: i32) -> bool {
+        self.join(&Sign::constant(i)) == *self
+    }
+}
+
+// [lo, hi], where a missing bound is infinite
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Interval {
+    Bot,
+    Iv(Option<i64>, Option<i64>),
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interval::Bot => write!(f, "bot"),
+            Interval::Iv(lo, hi) => {
+                let lo = lo.map_or("-inf".to_string(), |i| i.to_string());
+                let hi = hi.map_or("+inf".to_string(), |i| i.to_string());
+                write!(f, "[{}, {}]", lo, hi)
+            }
+        }
+    }
+}
+
+impl Interval {
+    fn create(lo: Option<i64>, hi: Option<i64>) -> Interval {
+        match (lo, hi) {
+            (Some(l), Some(h)) if l > h => Interval::Bot,
+            _ => Interval::Iv(lo, hi),
+        }
+    }
+}
+
+impl Domain for Interval {
+    fn bottom() -> Interval {
+        Interval::Bot
+    }
+    fn top() -> Interval {
+        Interval::Iv(None, None)
+    }
+    fn constant(i: i32) -> Interval {
+        Interval::Iv(Some(i as i64), Some(i as i64))
+    }
+    fn join(&self, other: &Interval) -> Interval {
+        match (self, other) {
+            (Interval::Bot, i) | (i, Interval::Bot) => *i,
+            (Interval::Iv(l1, h1), Interval::Iv(l2, h2)) => {
+                let lo = l1.and_then(|l1| Some(l1.min((*l2)?)));
+                let hi = h1.and_then(|h1| Some(h1.max((*h2)?)));
+                Interval::Iv(lo, hi)
+            }
+        }
+    }
+    // bounds that are still moving jump to infinity
+    fn widen(&self, other: &Interval) -> Interval {
+        match (self, other) {
+            (Interval::Bot, i) | (i, Interval::Bot) => *i,
+            (Interval::Iv(l1, h1), Interval::Iv(l2, h2)) => {
+                let lo = if l2.is_none() || l2 < l1 { None } else { *l1 };
+                let hi = match (h1, h2) {
+                    (Some(h1), Some(h2)) if h2 <= h1 => Some(*h1),
+                    _ => None,
+                };
+                Interval::Iv(lo, hi)
+            }
+        }
+    }
+    fn plus(&self, other: &Interval) -> Interval {
+        match (self, other) {
+            (Interval::Iv(l1, h1), Interval::Iv(l2, h2)) => Interval::Iv(
+                l1.and_then(|l1| Some(l1 + (*l2)?)),
+                h1.and_then(|h1| Some(h1 + (*h2)?)),
+            ),
+            _ => Interval::Bot,
+        }
+    }
+    fn divide(&self, other: &Interval) -> Interval {
+        match (self, other) {
+            (Interval::Bot, _i) | (_i, Interval::Bot) => Interval::Bot,
+            (_i, Interval::Iv(Some(0), Some(0))) => Interval::Bot,
+            // a divisor of fixed sign: the quotient is monotone in both arguments
+            (Interval::Iv(Some(l1), Some(h1)), Interval::Iv(Some(l2), Some(h2)))
+                if *l2 > 0 || *h2 < 0 =>
+            {
+                let qs = [l1 / l2, l1 / h2, h1 / l2, h1 / h2];
+                Interval::Iv(qs.iter().min().copied(), qs.iter().max().copied())
+            }
+            _ => Interval::top(),
+        }
+    }
+    fn at_most(&self, c: i32) -> Interval {
+        match self {
+            Interval::Bot => Interval::Bot,
+            Interval::Iv(lo, hi) => {
+                let hi = hi.map_or(c as i64, |h| h.min(c as i64));
+                Interval::create(*lo, Some(hi))
+            }
+        }
+    }
+    fn at_least(&self, c: i32) -> Interval {
+        match self {
+            Interval::Bot => Interval::Bot,
+            Interval::Iv(lo, hi) => {
+                let lo = lo.map_or(c as i64, |l| l.max(c as i64));
+                Interval::create(Some(lo), *hi)
+            }
+        }
+    }
+    fn contains(&self, i: i32) -> bool {
+        match self {
+            Interval::Bot => false,
+            Interval::Iv(lo, hi) => {
+                lo.is_none_or(|l| l <= i as i64) && hi.is_none_or(|h| i as i64 <= h)
+            }
+        }
+    }
+}
+
+// an abstract value for every variable, or None if the program point is unreachable
+#[derive(Clone, PartialEq, Debug)]
+pub struct AbsState<D>(Option<Vec<(String, D)>>);
+
+impl<D: Domain> fmt::Display for AbsState<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            None => write!(f, "unreachable"),
+            Some(env) => write!(
+                f,
+                "[{}]",
+                env.iter()
+                    .map(|(x, d)| format!("{} |-> {}", x, d))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+impl<D: Domain> AbsState<D> {
+    fn create_state(xl: &[String]) -> AbsState<D> {
+        AbsState(Some(
+            xl.iter().map(|x| (x.clone(), D::constant(0))).collect(),
+        ))
+    }
+    fn lookup(&self, x: &str) -> Option<D> {
+        let (_x, d) = self.0.as_ref()?.iter().find(|(y, _d)| y == x)?;
+        Some(d.clone())
+    }
+    // a variable that can only be bottom makes the whole point unreachable
+    fn update(&self, x: &str, d: D) -> AbsState<D> {
+        match &self.0 {
+            Some(_env) if d == D::bottom() => AbsState(None),
+            Some(env) => AbsState(Some(
+                env.iter()
+                    .map(|(y, e)| (y.clone(), if y == x { d.clone() } else { e.clone() }))
+                    .collect(),
+            )),
+            None => AbsState(None),
+        }
+    }
+    fn pointwise(&self, other: &AbsState<D>, f: impl Fn(&D, &D) -> D) -> AbsState<D> {
+        match (&self.0, &other.0) {
+            (None, _s) => other.clone(),
+            (_s, None) => self.clone(),
+            (Some(env1), Some(env2)) => AbsState(Some(
+                env1.iter()
+                    .zip(env2.iter())
+                    .map(|((x, d1), (_y, d2))| (x.clone(), f(d1, d2)))
+                    .collect(),
+            )),
+        }
+    }
+    pub fn join(&self, other: &AbsState<D>) -> AbsState<D> {
+        self.pointwise(other, D::join)
+    }
+    pub fn widen(&self, other: &AbsState<D>) -> AbsState<D> {
+        self.pointwise(other, D::widen)
+    }
+    pub fn contains(&self, sigma: &[(String, i32)]) -> bool {
+        sigma
+            .iter()
+            .all(|(x, i)| self.lookup(x).is_some_and(|d| d.contains(*i)))
+    }
+    fn eval(&self, a: &AExp) -> D {
+        match a {
+            AExp::Plus(a1, a2, _) => self.eval(a1).plus(&self.eval(a2)),
+            AExp::Divide(a1, a2, _) => self.eval(a1).divide(&self.eval(a2)),
+            AExp::Id(x, _) => self.lookup(x).unwrap_or_else(D::bottom),
+            AExp::Int(i, _) => D::constant(*i),
+            AExp::Deref(_a, _) => D::top(),
+        }
+    }
+    // the states in which B may evaluate to truth, refined on comparisons of variables and constants
+    fn assume(&self, b: &BExp, truth: bool) -> AbsState<D> {
+        match (b, truth) {
+            (BExp::Bool(v, _), truth) if *v != truth => AbsState(None),
+            (BExp::Negation(b, _), truth) => self.assume(b, !truth),
+            (BExp::And(b1, b2, _), true) => self.assume(b1, true).assume(b2, true),
+            (BExp::And(b1, b2, _), false) => self.assume(b1, false).join(&self.assume(b2, false)),
+            (BExp::LessThanEq(a1, a2, _), truth) => match (&**a1, &**a2, truth) {
+                (AExp::Id(x, _), AExp::Int(c, _), true) => self.refine(x, |d| d.at_most(*c)),
+                (AExp::Id(x, _), AExp::Int(c, _), false) => match c.checked_add(1) {
+                    Some(c) => self.refine(x, |d| d.at_least(c)),
+                    None => AbsState(None),
+                },
+                (AExp::Int(c, _), AExp::Id(x, _), true) => self.refine(x, |d| d.at_least(*c)),
+                (AExp::Int(c, _), AExp::Id(x, _), false) => match c.checked_sub(1) {
+                    Some(c) => self.refine(x, |d| d.at_most(c)),
+                    None => AbsState(None),
+                },
+                _ => self.clone(),
+            },
+            _ => self.clone(),
+        }
+    }
+    fn refine(&self, x: &str, f: impl Fn(&D) -> D) -> AbsState<D> {
+        match self.lookup(x) {
+            Some(d) => self.update(x, f(&d)),
+            None => self.clone(),
+        }
+    }
+}
+
+struct Analyser;
+
+impl Analyser {
+    // the abstract state after s and its lines annotated with the states in between
+    fn stmt<D: Domain>(
+        &self,
+        s: &Stmt,
+        pre: AbsState<D>,
+    ) -> Result<(AbsState<D>, Vec<String>), String> {
+        match s {
+            Stmt::StmtBlock(b, _) => {
+                let (post, lines) = self.block(b, pre)?;
+                let mut v = vec!["{".to_string()];
+                v.extend(indent(lines));
+                v.push("}".to_string());
+                Ok((post, v))
+            }
+            Stmt::Assign(x, a, _) => {
+                let d = pre.eval(a);
+                Ok((pre.update(x, d), vec![format!("{} = {} ;", x, a)]))
+            }
+            Stmt::Sequence(s1, s2, _) => {
+                let (mid, mut l1) = self.stmt(s1, pre)?;
+                let (post, l2) = self.stmt(s2, mid.clone())?;
+                l1.push(format!("{{{}}}", mid));
+                l1.extend(l2);
+                Ok((post, l1))
+            }
+            Stmt::IfThenElse(b, b1, b2, _) => {
+                let (pre1, pre2) = (pre.assume(b, true), pre.assume(b, false));
+                let (post1, l1) = self.block(b1, pre1.clone())?;
+                let (post2, l2) = self.block(b2, pre2.clone())?;
+                let mut v = vec![format!("if {} {{", b)];
+                v.extend(indent(annotated(&pre1, l1)));
+                v.push("} else {".to_string());
+                v.extend(indent(annotated(&pre2, l2)));
+                v.push("}".to_string());
+                Ok((post1.join(&post2), v))
+            }
+            Stmt::IfThen(b, b1, _) => {
+                let pre1 = pre.assume(b, true);
+                let (post1, l1) = self.block(b1, pre1.clone())?;
+                let mut v = vec![format!("if {} {{", b)];
+                v.extend(indent(annotated(&pre1, l1)));
+                v.push("}".to_string());
+                Ok((post1.join(&pre.assume(b, false)), v))
+            }
+            Stmt::Choice(b1, b2, _) => {
+                let (post1, l1) = self.block(b1, pre.clone())?;
+                let (post2, l2) = self.block(b2, pre)?;
+                let mut v = vec!["{".to_string()];
+                v.extend(indent(l1));
+                v.push("} [] {".to_string());
+                v.extend(indent(l2));
+                v.push("}".to_string());
+                Ok((post1.join(&post2), v))
+            }
+            Stmt::While(b, b1, _) => {
+                let mut inv = pre.clone();
+                loop {
+                    let (post1, l1) = self.block(b1, inv.assume(b, true))?;
+                    let next = inv.widen(&pre.join(&post1));
+                    if next == inv {
+                        let mut v = vec![format!("while {} {{  // invariant {}", b, inv)];
+                        v.extend(indent(annotated(&inv.assume(b, true), l1)));
+                        v.extend(indent(vec![format!("{{{}}}", post1)]));
+                        v.push("}".to_string());
+                        return Ok((inv.assume(b, false), v));
+                    }
+                    inv = next;
+                }
+            }
+            Stmt::DoWhile(b1, b, _) => {
+                let mut inv = pre.clone();
+                loop {
+                    let (post1, l1) = self.block(b1, inv.clone())?;
+                    let next = inv.widen(&pre.join(&post1.assume(b, true)));
+                    if next == inv {
+                        let mut v = vec![format!("do {{  // invariant {}", inv)];
+                        v.extend(indent(l1));
+                        v.extend(indent(vec![format!("{{{}}}", post1)]));
+                        v.push(format!("}} while {} ;", b));
+                        return Ok((post1.assume(b, false), v));
+                    }
+                    inv = next;
+                }
+            }
+            Stmt::For(init, b, update, b1, _) => {
+                let (start, li) = self.stmt(init, pre)?;
+                let mut inv = start.clone();
+                loop {
+                    let (post1, l1) = self.block(b1, inv.assume(b, true))?;
+                    let (post2, l2) = self.stmt(update, post1.clone())?;
+                    let next = inv.widen(&start.join(&post2));
+                    if next == inv {
+                        let mut v = li;
+                        v.push(format!("{{{}}}", start));
+                        v.push(format!("for (...; {}; ...) {{  // invariant {}", b, inv));
+                        let mut body = annotated(&inv.assume(b, true), l1);
+                        body.push(format!("{{{}}}", post1));
+                        body.extend(l2);
+                        body.push(format!("{{{}}}", post2));
+                        v.extend(indent(body));
+                        v.push("}".to_string());
+                        return Ok((inv.assume(b, false), v));
+                    }
+                    inv = next;
+                }
+            }
+            s => Err(format!(
+                "No abstract semantics for exceptions or pointers: {}",
+                s.to_string().trim()
+            )),
+        }
+    }
+    fn block<D: Domain>(
+        &self,
+        b: &Block,
+        pre: AbsState<D>,
+    ) -> Result<(AbsState<D>, Vec<String>), String> {
+        match b {
+            Block::EmptyBlock => Ok((pre, vec![])),
+            Block::BlockStmt(s, _) => self.stmt(s, pre),
+        }
+    }
+}
+
+// the program annotated with the abstract state at each program point,
+// and the state at its end
+pub fn analyse<D: Domain>(pgm: &Pgm) -> Result<(AbsState<D>, Vec<String>), String> {
+    let Pgm::Program(xl, _levels, s) = pgm;
+    let pre = AbsState::create_state(xl);
+    let (post, lines) = Analyser.stmt(s, pre.clone())?;
+    let mut v = annotated(&pre, lines);
+    v.push(format!("{{{}}}", post));
+    Ok((post, v))
+}
+
+// the annotated program followed by a comparison of its exit state with a concrete run
+pub fn report<D: Domain>(pgm: &Pgm) -> Result<String, String> {
+    let (post, lines) = analyse::<D>(pgm)?;
+    let comparison = match run(pgm) {
+        Some(sigma) => {
+            let sigma_str = sigma
+                .iter()
+                .map(|(x, i)| format!("{} |-> {}", x, i))
+                .collect::<Vec<String>>()
+                .join(", ");
+            let verdict = if post.contains(&sigma) {
+                "contained in the abstract exit state"
+            } else {
+                "NOT contained in the abstract exit state"
+            };
+            format!("concrete run ends in [{}], {}", sigma_str, verdict)
+        }
+        None => format!("concrete run does not finish in {} steps", RUN_LIMIT),
+    };
+    Ok(format!("{}\n\n{}", lines.join("\n"), comparison))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+
+    #[test]
+    fn test_sign() {
+        let pgm = parse("int x, y; x = 3; y = x / 2; while (y <= 10) { y = y + x; }".to_string())
+            .unwrap();
+        let (post, _lines) = analyse::<Sign>(&pgm).unwrap();
+        assert_eq!(post.lookup("x"), Some(Sign::Pos));
+        assert_eq!(post.lookup("y"), Some(Sign::Pos));
+        assert!(post.contains(&run(&pgm).unwrap()));
+    }
+    #[test]
+    fn test_interval() {
+        let pgm = parse("int x, y; while (x <= 9) { x = x + 1; y = 10 / x; }".to_string()).unwrap();
+        let (post, _lines) = analyse::<Interval>(&pgm).unwrap();
+        assert_eq!(post.lookup("x"), Some(Interval::Iv(Some(10), None)));
+        let sigma = run(&pgm).unwrap();
+        assert_eq!(sigma, vec![("x".to_string(), 10), ("y".to_string(), 1)]);
+        assert!(post.contains(&sigma));
+    }
+}
@@ -1,5 +1,47 @@
+use crate::absint::report;
+use crate::absint::Interval;
+use crate::absint::Sign;
+use crate::ast::BExp;
+use crate::ast::Block;
+use crate::ast::Pgm;
 use crate::ast::Rule;
-use crate::ast::Stack;
+use crate::ast::Span;
+use crate::ast::Stmt;
+use crate::bmc;
+use crate::cfg::Cfg;
+use crate::cfg::Node;
+use crate::cost;
+use crate::coverage;
+use crate::dataflow;
+use crate::derivation::Derivation;
+use crate::derivation::InferenceRule;
+use crate::equiv;
+use crate::hoare::HoareJudgment;
+use crate::hoare::HoareRule;
+use crate::lambda::ast::LambdaRule;
+use crate::lambda::ast::Strategy;
+use crate::ltl::check;
+use crate::ltl::Counterexample;
+use crate::optimize::optimize;
+use crate::parse::parse;
+use crate::parse::parse_bexp;
+use crate::parse::parse_ltl;
+use crate::pretty::pretty;
+use crate::security;
+use crate::security::noninterference;
+use crate::security::SecJudgment;
+use crate::security::SecRule;
+use crate::security::TRIALS;
+use crate::slice;
+use crate::stack::RewriteRule;
+use crate::stack::Stack;
+use crate::stack::RUN_LIMIT;
+use crate::symbolic::SymRule;
+use crate::typing::TypeJudgment;
+use crate::typing::TypeRule;
+use crate::vm::show_memory;
+use crate::vm::Vm;
+use crate::wp::wp;
 
 use eframe::{egui, epi};
 
@@ -16,10 +58,100 @@ pub struct TemplateApp {
     // this how you opt-out of serialization of a member
     #[cfg_attr(feature = "persistence", serde(skip))]
     my_enum: Rule,
-    current_display_text: String,
-    stack: Stack,
+    stack: Stack<Rule>,
     start_program: String,
+    // the text the stepper's program was parsed from, its spans only fit that text
+    loaded_program: String,
     error_message: String,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    mode: Mode,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    lambda_rule: LambdaRule,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    lambda_stack: Stack<LambdaRule>,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    strategy: Strategy,
+    lambda_program: String,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    type_rule: TypeRule,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    typing: Derivation<TypeRule>,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    hoare_rule: HoareRule,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    hoare: Derivation<HoareRule>,
+    precondition: String,
+    postcondition: String,
+    first_assertion: String,
+    second_assertion: String,
+    invariants: String,
+    annotated_program: String,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    sym_rule: SymRule,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    sym_stack: Stack<SymRule>,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    domain: AbstractDomain,
+    analysis: String,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    vm: Option<Vm>,
+    formula: String,
+    verdict: String,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    counterexample: Option<Counterexample>,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    cfg: Option<Cfg>,
+    dataflow: String,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    sec_rule: SecRule,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    security: Derivation<SecRule>,
+    interference: String,
+    other_program: String,
+    comparison: String,
+    optimized: String,
+    rewrites: String,
+    cost_variable: String,
+    growth: String,
+    corpus: String,
+    coverage: String,
+    bmc_assertion: String,
+    bmc_depth: usize,
+    bmc_verdict: String,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    bmc_counterexample: Option<bmc::Counterexample>,
+    slice_variable: String,
+    slice_point: usize,
+    #[cfg_attr(feature = "persistence", serde(skip))]
+    slice: Option<slice::Slice>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Mode {
+    Semantics,
+    Typing,
+    Lambda,
+    Hoare,
+    Wp,
+    Symbolic,
+    Abstract,
+    Compiler,
+    ModelCheck,
+    ControlFlow,
+    Dataflow,
+    Security,
+    Equivalence,
+    Optimizer,
+    Cost,
+    Coverage,
+    Bounded,
+    Slice,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum AbstractDomain {
+    Sign,
+    Interval,
 }
 
 impl Default for TemplateApp {
@@ -27,14 +159,300 @@ impl Default for TemplateApp {
         Self {
             // Example stuff:
             my_enum: Rule::NoOp,
-            current_display_text: "".to_string(),
             stack: Stack::new(),
             start_program: "int x, y;\n  x = x + 1;".to_string(),
+            loaded_program: "".to_string(),
             error_message: "".to_string(),
+            mode: Mode::Semantics,
+            lambda_rule: LambdaRule::CbvBeta,
+            lambda_stack: Stack::<LambdaRule>::create_from_string(
+                "(\\x. x x) (\\y. y)".to_string(),
+            )
+            .expect("the default lambda term parses"),
+            strategy: Strategy::CallByValue,
+            lambda_program: "(\\x. x x) (\\y. y)".to_string(),
+            type_rule: TypeRule::Program,
+            typing: Derivation::create(TypeJudgment::PgmOk(
+                parse("int x, y;\n  x = x + 1;".to_string()).expect("the default program parses"),
+            )),
+            hoare_rule: HoareRule::Assignment,
+            hoare: Derivation::create(HoareJudgment::Triple(
+                BExp::Bool(true, Span::default()),
+                Stmt::StmtBlock(Box::new(Block::EmptyBlock), Span::default()),
+                BExp::Bool(true, Span::default()),
+            )),
+            precondition: "true".to_string(),
+            postcondition: "true".to_string(),
+            first_assertion: "".to_string(),
+            second_assertion: "".to_string(),
+            invariants: "".to_string(),
+            annotated_program: "".to_string(),
+            sym_rule: SymRule::Top,
+            sym_stack: Stack::<SymRule>::create_from_string("int x, y;\n  x = x + 1;".to_string())
+                .expect("the default program parses"),
+            domain: AbstractDomain::Interval,
+            analysis: "".to_string(),
+            vm: None,
+            formula: "[] (x <= 10)".to_string(),
+            verdict: "".to_string(),
+            counterexample: None,
+            cfg: None,
+            dataflow: "".to_string(),
+            sec_rule: SecRule::Program,
+            security: Derivation::create(SecJudgment::PgmSecure(
+                parse("int x, y;\n  x = x + 1;".to_string()).expect("the default program parses"),
+            )),
+            interference: "".to_string(),
+            other_program: "int x, y;\n  y = 1 + x;".to_string(),
+            comparison: "".to_string(),
+            optimized: "".to_string(),
+            rewrites: "".to_string(),
+            cost_variable: "y".to_string(),
+            growth: "".to_string(),
+            corpus: "programs".to_string(),
+            coverage: "".to_string(),
+            bmc_assertion: "x <= y".to_string(),
+            bmc_depth: bmc::DEPTH,
+            bmc_verdict: "".to_string(),
+            bmc_counterexample: None,
+            slice_variable: "x".to_string(),
+            slice_point: 0,
+            slice: None,
         }
     }
 }
 
+// the rules that can be applied to the top of the stack, and the full list of rules
+fn rule_panel<R: RewriteRule>(
+    ui: &mut egui::Ui,
+    stack: &mut Stack<R>,
+    selected: &mut R,
+    rules: Vec<R>,
+) {
+    ui.label("Applicable Rules:");
+
+    egui::Grid::new("some_unique_id").show(ui, |ui| {
+        let applicable_rules = rules
+            .clone()
+            .into_iter()
+            .filter(|rule| stack.can_apply_rule(rule.clone()));
+        for rule in applicable_rules {
+            let label = rule.get_label();
+            ui.label(label);
+            let description = rule.get_description();
+            ui.radio_value(selected, rule, description);
+            ui.end_row();
+        }
+    });
+    ui.horizontal_wrapped(|ui| {
+        if ui.button("Apply").clicked() {
+            stack.apply_rule(selected.clone());
+        }
+        if ui.button("Step").clicked() {
+            stack.step(&rules);
+        }
+        if ui.button("Run").clicked() {
+            stack.run(&rules, RUN_LIMIT);
+        }
+    });
+
+    for rule in rules {
+        let label = rule.get_label();
+        CollapsingHeader::new(label)
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.label(rule.get_description());
+            });
+    }
+}
+
+// the rules that conclude the current goal of a derivation,
+// instantiate fills in the parameters of a rule and fails if they are missing
+fn inference_panel<R: InferenceRule>(
+    ui: &mut egui::Ui,
+    derivation: &mut Derivation<R>,
+    selected: &mut R,
+    instantiate: &dyn Fn(R) -> Option<R>,
+) {
+    ui.label("Applicable Rules:");
+
+    egui::Grid::new("inference_rules").show(ui, |ui| {
+        let applicable_rules = R::list_of_rules().into_iter().filter(|rule| {
+            instantiate(rule.clone()).is_some_and(|rule| derivation.can_apply_rule(rule))
+        });
+        for rule in applicable_rules {
+            let label = rule.get_label();
+            ui.label(label);
+            let description = rule.get_description();
+            ui.radio_value(selected, rule, description);
+            ui.end_row();
+        }
+    });
+    ui.horizontal_wrapped(|ui| {
+        if ui.button("Apply").clicked() {
+            if let Some(rule) = instantiate(selected.clone()) {
+                derivation.apply_rule(rule);
+            }
+        }
+        if ui.button("Undo").clicked() {
+            derivation.undo();
+        }
+    });
+
+    for rule in R::list_of_rules() {
+        let label = rule.get_label();
+        CollapsingHeader::new(label)
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.label(rule.get_description());
+            });
+    }
+}
+
+// the graph drawn in rows by distance from the entry, with the highlighted nodes filled in
+fn cfg_panel(ui: &mut egui::Ui, cfg: &Cfg, highlight: &[usize]) {
+    let layers = cfg.layers();
+    let rows = layers.iter().max().map_or(0, |l| l + 1);
+    let (gap_x, gap_y) = (30.0, 50.0);
+    let color = ui.visuals().text_color();
+    let galleys = cfg
+        .nodes
+        .iter()
+        .map(|n| {
+            ui.fonts()
+                .layout_no_wrap(n.label(), egui::TextStyle::Monospace, color)
+        })
+        .collect::<Vec<_>>();
+    let heights = (0..rows)
+        .map(|row| {
+            (0..cfg.nodes.len())
+                .filter(|n| layers[*n] == row)
+                .map(|n| galleys[n].size().y + 10.0)
+                .fold(0.0, f32::max)
+        })
+        .collect::<Vec<f32>>();
+    let width = (0..rows)
+        .map(|row| {
+            (0..cfg.nodes.len())
+                .filter(|n| layers[*n] == row)
+                .map(|n| galleys[n].size().x + 10.0 + gap_x)
+                .sum::<f32>()
+        })
+        .fold(0.0, f32::max);
+    let height = heights.iter().map(|h| h + gap_y).sum::<f32>();
+    let (response, painter) = ui.allocate_painter(egui::vec2(width, height), egui::Sense::hover());
+    let origin = response.rect.min;
+    let mut rects = vec![egui::Rect::NOTHING; cfg.nodes.len()];
+    let mut y = origin.y;
+    for (row, row_height) in heights.iter().enumerate() {
+        let mut x = origin.x;
+        for n in (0..cfg.nodes.len()).filter(|n| layers[*n] == row) {
+            let size = galleys[n].size() + egui::vec2(10.0, 10.0);
+            rects[n] = egui::Rect::from_min_size(egui::pos2(x, y), size);
+            x += size.x + gap_x;
+        }
+        y += row_height + gap_y;
+    }
+    let stroke = egui::Stroke::new(1.0, color);
+    for (source, target, label) in &cfg.edges {
+        let (from, to) = (rects[*source].center_bottom(), rects[*target].center_top());
+        // back edges leave from the side of the node and enter the side of the loop head
+        let (from, to) = if layers[*target] <= layers[*source] {
+            (rects[*source].right_center(), rects[*target].right_center())
+        } else {
+            (from, to)
+        };
+        painter.arrow(from, to - from, stroke);
+        painter.text(
+            from + (to - from) / 2.0,
+            egui::Align2::LEFT_CENTER,
+            label,
+            egui::TextStyle::Small,
+            color,
+        );
+    }
+    for (n, (node, galley)) in cfg.nodes.iter().zip(galleys).enumerate() {
+        let corner = match node {
+            Node::Entry | Node::Exit => 10.0,
+            _ => 0.0,
+        };
+        if highlight.contains(&n) {
+            painter.rect(rects[n], corner, egui::Color32::YELLOW, stroke);
+            painter.galley_with_color(
+                rects[n].min + egui::vec2(5.0, 5.0),
+                galley,
+                egui::Color32::BLACK,
+            );
+        } else {
+            painter.rect(rects[n], corner, ui.visuals().extreme_bg_color, stroke);
+            painter.galley(rects[n].min + egui::vec2(5.0, 5.0), galley);
+        }
+    }
+}
+
+fn stack_panel<R: RewriteRule>(ui: &mut egui::Ui, stack: &mut Stack<R>) {
+    ui.horizontal_wrapped(|ui| {
+        if ui.button("Undo").clicked() {
+            stack.undo();
+        }
+        if ui.button("Redo").clicked() {
+            stack.redo();
+        }
+    });
+
+    ui.horizontal_wrapped(|ui| {
+        if ui.button("Pop Top").clicked() {
+            stack.pop();
+        }
+        if ui.button("Clear stack").clicked() {
+            stack.clear();
+        }
+    });
+    if let Some((first, again)) = stack.cycle() {
+        ui.colored_label(
+            egui::Color32::YELLOW,
+            format!(
+                "Warning: step {} re-enters the configuration first reached at step {}, so this run does not terminate",
+                again, first
+            ),
+        );
+    }
+    ui.label(format!("{}", stack));
+}
+
+// the program editor with the term the stepper is about to reduce picked out
+fn program_editor(ui: &mut egui::Ui, program: &mut String, executing: Option<Span>) {
+    let mut layouter = |ui: &egui::Ui, text: &str, wrap_width: f32| {
+        let format = |background| egui::text::TextFormat {
+            style: egui::TextStyle::Body,
+            color: ui.visuals().text_color(),
+            background,
+            ..Default::default()
+        };
+        let mut job = egui::text::LayoutJob::default();
+        match executing.and_then(|span| Some((span, text.get(span.start..span.end)?))) {
+            Some((span, current)) => {
+                job.append(&text[..span.start], 0.0, format(egui::Color32::TRANSPARENT));
+                job.append(current, 0.0, format(egui::Color32::from_rgb(90, 80, 20)));
+                job.append(&text[span.end..], 0.0, format(egui::Color32::TRANSPARENT));
+            }
+            None => job.append(text, 0.0, format(egui::Color32::TRANSPARENT)),
+        }
+        job.wrap_width = wrap_width;
+        ui.fonts().layout_job(job)
+    };
+    ui.add(egui::TextEdit::multiline(program).layouter(&mut layouter));
+}
+
+// parse errors point into the program with a caret, which only lines up in monospace
+fn error_panel(ui: &mut egui::Ui, message: &str) {
+    ui.add(
+        egui::Label::new(message)
+            .monospace()
+            .text_color(egui::Color32::RED),
+    );
+}
+
 impl epi::App for TemplateApp {
     fn name(&self) -> &str {
         "eframe template"
@@ -67,12 +485,78 @@ impl epi::App for TemplateApp {
     fn update(&mut self, ctx: &egui::CtxRef, _frame: &mut epi::Frame<'_>) {
         let Self {
             my_enum,
-            current_display_text: _,
             stack,
             start_program,
+            loaded_program,
             error_message,
+            mode,
+            lambda_rule,
+            lambda_stack,
+            strategy,
+            lambda_program,
+            type_rule,
+            typing,
+            hoare_rule,
+            hoare,
+            precondition,
+            postcondition,
+            first_assertion,
+            second_assertion,
+            invariants,
+            annotated_program,
+            sym_rule,
+            sym_stack,
+            domain,
+            analysis,
+            vm,
+            formula,
+            verdict,
+            counterexample,
+            cfg,
+            dataflow,
+            sec_rule,
+            security,
+            interference,
+            other_program,
+            comparison,
+            optimized,
+            rewrites,
+            cost_variable,
+            growth,
+            corpus,
+            coverage,
+            bmc_assertion,
+            bmc_depth,
+            bmc_verdict,
+            bmc_counterexample,
+            slice_variable,
+            slice_point,
+            slice,
         } = self;
 
+        egui::TopBottomPanel::top("mode_panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.radio_value(mode, Mode::Semantics, "IMP semantics");
+                ui.radio_value(mode, Mode::Typing, "IMP typing");
+                ui.radio_value(mode, Mode::Lambda, "Lambda calculus");
+                ui.radio_value(mode, Mode::Hoare, "Hoare logic");
+                ui.radio_value(mode, Mode::Wp, "Weakest preconditions");
+                ui.radio_value(mode, Mode::Symbolic, "Symbolic execution");
+                ui.radio_value(mode, Mode::Abstract, "Abstract interpretation");
+                ui.radio_value(mode, Mode::Compiler, "Stack machine");
+                ui.radio_value(mode, Mode::ModelCheck, "LTL model checking");
+                ui.radio_value(mode, Mode::ControlFlow, "Control flow");
+                ui.radio_value(mode, Mode::Dataflow, "Dataflow analysis");
+                ui.radio_value(mode, Mode::Security, "Information flow");
+                ui.radio_value(mode, Mode::Equivalence, "Program equivalence");
+                ui.radio_value(mode, Mode::Optimizer, "Optimizer");
+                ui.radio_value(mode, Mode::Cost, "Execution cost");
+                ui.radio_value(mode, Mode::Coverage, "Rule coverage");
+                ui.radio_value(mode, Mode::Bounded, "Bounded checking");
+                ui.radio_value(mode, Mode::Slice, "Slicing");
+            });
+        });
+
         egui::SidePanel::left("side_panel")
             .min_width(500.0)
             .show(ctx, |ui| {
@@ -80,22 +564,119 @@ impl epi::App for TemplateApp {
                     ui.spacing_mut().item_spacing.x = 50.0;
                 });
 
-                ui.label("Applicable Rules:");
-
-                egui::Grid::new("some_unique_id").show(ui, |ui| {
-                    let applicable_rules = Rule::list_of_rules()
-                        .into_iter()
-                        .filter(|rule| stack.can_apply_rule(rule.clone()));
-                    for rule in applicable_rules {
-                        let label = rule.get_label();
-                        ui.label(label);
-                        let description = rule.get_description();
-                        ui.radio_value(my_enum, rule, description);
-                        ui.end_row();
+                match mode {
+                    Mode::Semantics
+                    | Mode::Compiler
+                    | Mode::ModelCheck
+                    | Mode::ControlFlow
+                    | Mode::Cost => {
+                        rule_panel(ui, stack, my_enum, Rule::list_of_rules())
+                    }
+                    Mode::Typing => {
+                        inference_panel(ui, typing, type_rule, &Some);
+                        if ui.button("Check").clicked() {
+                            typing.auto();
+                        }
+                    }
+                    Mode::Security => {
+                        inference_panel(ui, security, sec_rule, &Some);
+                        if ui.button("Check").clicked() {
+                            security.auto();
+                        }
+                    }
+                    Mode::Hoare => {
+                        ui.horizontal(|ui| {
+                            ui.label("First assertion:");
+                            ui.text_edit_singleline(first_assertion);
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Second assertion:");
+                            ui.text_edit_singleline(second_assertion);
+                        });
+                        let a1 = parse_bexp(first_assertion.to_string());
+                        let a2 = parse_bexp(second_assertion.to_string());
+                        inference_panel(ui, hoare, hoare_rule, &|rule: HoareRule| {
+                            rule.instantiate(a1.clone(), a2.clone())
+                        });
+                    }
+                    Mode::Wp => {
+                        ui.horizontal(|ui| {
+                            ui.label("Postcondition:");
+                            ui.text_edit_singleline(postcondition);
+                        });
+                        ui.label("Loop invariants, one per line in program order:");
+                        ui.add(egui::TextEdit::multiline(invariants));
+                    }
+                    Mode::Symbolic => rule_panel(ui, sym_stack, sym_rule, SymRule::list_of_rules()),
+                    Mode::Equivalence => {
+                        ui.label(format!(
+                            "Both programs run from every state with values in {:?} when there are at most {} variables, then from {} random states",
+                            equiv::RANGE,
+                            equiv::EXHAUSTIVE_VARS,
+                            equiv::TRIALS
+                        ));
+                    }
+                    Mode::Bounded => {
+                        ui.label(format!(
+                            "The program is unrolled into a formula over {}-bit integers, which a SAT solver searches for an initial state from which the program finishes in a state where the assertion fails",
+                            bmc::WIDTH
+                        ));
+                        ui.label("Runs that go round a loop more often than the unrolling depth are not checked");
+                    }
+                    Mode::Slice => {
+                        ui.label("The slice keeps the statements that may affect the value of the variable when the point is reached, and the tests deciding whether they run or the point is reached");
+                        ui.horizontal(|ui| {
+                            ui.label("Variable:");
+                            ui.text_edit_singleline(slice_variable);
+                        });
+                        ui.label("Point, before the statement:");
+                        match parse(start_program.to_string()) {
+                            Ok(pgm) => {
+                                egui::ScrollArea::vertical().show(ui, |ui| {
+                                    for (i, point) in slice::points(&pgm).into_iter().enumerate() {
+                                        ui.radio_value(slice_point, i, point);
+                                    }
+                                });
+                            }
+                            Err(_e) => {
+                                ui.label("Unable to parse");
+                            }
+                        }
+                    }
+                    Mode::Coverage => {
+                        ui.label("Every program of the directory is run along all its choices, and each rule used in a step is counted, congruences included");
+                    }
+                    Mode::Optimizer => {
+                        ui.label("copy propagation: a variable holding a constant or another variable is replaced by it until either is assigned");
+                        ui.label("constant folding: operations on constants are evaluated, unless they would divide by 0 or overflow");
+                        ui.label("dead branches: a conditional on a constant keeps only the branch taken and a loop on false is dropped");
+                        ui.label(format!(
+                            "The passes repeat until nothing changes or for {} rounds, then both programs run from the states of the equivalence checker",
+                            crate::optimize::ROUNDS
+                        ));
+                    }
+                    Mode::Dataflow => {
+                        ui.label("live: the variables that may be read after the statement before they are assigned again");
+                        ui.label("reaching: the lines of the assignments whose value may still be held when the statement starts");
+                    }
+                    Mode::Abstract => {
+                        ui.horizontal(|ui| {
+                            ui.radio_value(domain, AbstractDomain::Sign, "Signs");
+                            ui.radio_value(domain, AbstractDomain::Interval, "Intervals");
+                        });
+                    }
+                    Mode::Lambda => {
+                        ui.horizontal(|ui| {
+                            ui.radio_value(strategy, Strategy::CallByValue, "Call by value");
+                            ui.radio_value(strategy, Strategy::CallByName, "Call by name");
+                        });
+                        rule_panel(
+                            ui,
+                            lambda_stack,
+                            lambda_rule,
+                            LambdaRule::rules_for(*strategy),
+                        )
                     }
-                });
-                if ui.button("Apply").clicked() {
-                    stack.apply_rule(my_enum.clone());
                 }
 
                 //     let mut fonts = FontDefinitions::default();
@@ -108,52 +689,548 @@ impl epi::App for TemplateApp {
 
                 //     ctx.set_fonts(fonts);
 
-                for rule in Rule::list_of_rules() {
-                    let label = rule.get_label();
-                    CollapsingHeader::new(label)
-                        .default_open(false)
-                        .show(ui, |ui| {
-                            ui.label(rule.get_description());
-                        });
-                }
                 ctx.set_fonts(FontDefinitions::default());
             });
         // egui::TopBottomPanel::bottom("hi").show(ctx, |ui| {
 
         // });
-        egui::CentralPanel::default().show(ctx, |ui| {
-            ui.horizontal_wrapped(|ui| {
-                if ui.button("Undo").clicked() {
-                    stack.undo();
+        egui::CentralPanel::default().show(ctx, |ui| match mode {
+            Mode::Semantics => {
+                stack_panel(ui, stack);
+
+                let executing = if start_program == loaded_program {
+                    stack.top().executing()
+                } else {
+                    None
+                };
+                program_editor(ui, start_program, executing);
+                if ui.button("Use new program").clicked() {
+                    match Stack::<Rule>::create_from_string(start_program.to_string()) {
+                        Ok(s) => {
+                            println!("parsed as {:?}", s);
+                            *stack = s;
+                            *loaded_program = start_program.clone();
+                            *error_message = "".to_string();
+                        }
+                        Err(e) => {
+                            println!("parse failed");
+                            *error_message = e;
+                        }
+                    }
+                    println!("{:?}", stack);
                 }
-                if ui.button("Redo").clicked() {
-                    stack.redo();
+                error_panel(ui, error_message);
+            }
+            Mode::Typing => {
+                ui.label(format!("{}", typing));
+
+                let _response = ui.add(egui::TextEdit::multiline(start_program));
+                if ui.button("Use new program").clicked() {
+                    match parse(start_program.to_string()) {
+                        Ok(pgm) => {
+                            *typing = Derivation::create(TypeJudgment::PgmOk(pgm));
+                            *error_message = "".to_string();
+                        }
+                        Err(e) => *error_message = e.to_string(),
+                    }
                 }
-            });
+                error_panel(ui, error_message);
+            }
+            Mode::Security => {
+                ui.label(format!("{}", security));
 
-            ui.horizontal_wrapped(|ui| {
-                if ui.button("Pop Top").clicked() {
-                    stack.pop();
+                let _response = ui.add(egui::TextEdit::multiline(start_program));
+                if ui.button("Use new program").clicked() {
+                    match parse(start_program.to_string()) {
+                        Ok(pgm) => {
+                            *security = Derivation::create(SecJudgment::PgmSecure(pgm));
+                            *interference = "".to_string();
+                            *error_message = "".to_string();
+                        }
+                        Err(e) => *error_message = e.to_string(),
+                    }
                 }
-                if ui.button("Clear stack").clicked() {
-                    stack.clear();
+                if ui.button("Test noninterference").clicked() {
+                    match parse(start_program.to_string()) {
+                        Ok(pgm) => {
+                            let verdict = match security::check(&pgm) {
+                                Ok(_derivation) => {
+                                    "the type system accepts the program".to_string()
+                                }
+                                Err(e) => e,
+                            };
+                            let test = match noninterference(&pgm, TRIALS) {
+                                Ok(agreed) => format!(
+                                    "{} of {} pairs of runs finished with the same low values",
+                                    agreed, TRIALS
+                                ),
+                                Err(leak) => format!("interference found:\n{}", leak),
+                            };
+                            *interference = format!("{}\n{}", verdict, test);
+                            *error_message = "".to_string();
+                        }
+                        Err(e) => *error_message = e.to_string(),
+                    }
                 }
-            });
-            ui.label(format!("{}", stack));
-
-            let _response = ui.add(egui::TextEdit::multiline(start_program));
-            if ui.button("Use new program").clicked() {
-                if let Some(s) = Stack::create_from_string(start_program.to_string()) {
-                    println!("parsed as {:?}", s);
-                    *stack = s;
-                    *error_message = "".to_string();
-                } else {
-                    println!("parse failed");
-                    *error_message = "Unable to parse".to_string();
+                error_panel(ui, error_message);
+                ui.label(interference.as_str());
+            }
+            Mode::Lambda => {
+                stack_panel(ui, lambda_stack);
+
+                let _response = ui.add(egui::TextEdit::multiline(lambda_program));
+                if ui.button("Use new term").clicked() {
+                    if let Some(s) =
+                        Stack::<LambdaRule>::create_from_string(lambda_program.to_string())
+                    {
+                        *lambda_stack = s;
+                        *error_message = "".to_string();
+                    } else {
+                        *error_message = "Unable to parse".to_string();
+                    }
+                }
+                error_panel(ui, error_message);
+            }
+            Mode::Hoare => {
+                ui.label(format!("{}", hoare));
+
+                ui.horizontal(|ui| {
+                    ui.label("Precondition:");
+                    ui.text_edit_singleline(precondition);
+                });
+                let _response = ui.add(egui::TextEdit::multiline(start_program));
+                ui.horizontal(|ui| {
+                    ui.label("Postcondition:");
+                    ui.text_edit_singleline(postcondition);
+                });
+                if ui.button("Start proof").clicked() {
+                    match (
+                        parse_bexp(precondition.to_string()),
+                        parse(start_program.to_string()),
+                        parse_bexp(postcondition.to_string()),
+                    ) {
+                        (Some(p), Ok(Pgm::Program(_xl, _levels, s)), Some(q)) => {
+                            *hoare = Derivation::create(HoareJudgment::Triple(p, s, q));
+                            *error_message = "".to_string();
+                        }
+                        (_p, Err(e), _q) => *error_message = e.to_string(),
+                        _ => *error_message = "Unable to parse".to_string(),
+                    }
+                }
+                error_panel(ui, error_message);
+            }
+            Mode::Wp => {
+                if ui.button("Compute wp").clicked() {
+                    let invs: Option<Vec<BExp>> = invariants
+                        .lines()
+                        .filter(|l| !l.trim().is_empty())
+                        .map(|l| parse_bexp(l.to_string()))
+                        .collect();
+                    match (
+                        parse(start_program.to_string()),
+                        parse_bexp(postcondition.to_string()),
+                        invs,
+                    ) {
+                        (Ok(Pgm::Program(_xl, _levels, s)), Some(q), Some(invs)) => {
+                            match wp(&s, &q, &invs) {
+                                Ok(w) => {
+                                    *annotated_program = w.to_string();
+                                    *error_message = "".to_string();
+                                }
+                                Err(e) => *error_message = e,
+                            }
+                        }
+                        (Err(e), _q, _invs) => *error_message = e.to_string(),
+                        _ => *error_message = "Unable to parse".to_string(),
+                    }
+                }
+                error_panel(ui, error_message);
+                ui.columns(2, |columns| {
+                    columns[0].add(egui::TextEdit::multiline(start_program));
+                    columns[1].label(annotated_program.as_str());
+                });
+            }
+            Mode::Compiler => {
+                let _response = ui.add(egui::TextEdit::multiline(start_program));
+                if ui.button("Compile program").clicked() {
+                    let result = Stack::<Rule>::create_from_string(start_program.to_string())
+                        .and_then(|s| {
+                            let pgm = parse(start_program.to_string()).expect("already parsed");
+                            Ok((s, Vm::compile(&pgm)?))
+                        });
+                    match result {
+                        Ok((s, v)) => {
+                            *stack = s;
+                            *vm = Some(v);
+                            *error_message = "".to_string();
+                        }
+                        Err(e) => *error_message = e,
+                    }
+                }
+                error_panel(ui, error_message);
+                ui.columns(2, |columns| {
+                    stack_panel(&mut columns[0], stack);
+                    if let Some(vm) = vm {
+                        columns[1].horizontal_wrapped(|ui| {
+                            if ui.button("Step").clicked() {
+                                vm.step();
+                            }
+                            if ui.button("Run").clicked() {
+                                vm.run(RUN_LIMIT);
+                            }
+                            if ui.button("Reset").clicked() {
+                                vm.reset();
+                            }
+                        });
+                        columns[1].label(format!("{}", vm));
+                    }
+                });
+                if let (Some(vm), Some(sigma)) = (vm, stack.top().final_state()) {
+                    if vm.is_halted() {
+                        let verdict = if vm.memory() == sigma.as_slice() {
+                            "agree"
+                        } else {
+                            "DISAGREE"
+                        };
+                        ui.label(format!(
+                            "final states {}: {} and {}",
+                            verdict,
+                            show_memory(&sigma),
+                            show_memory(vm.memory())
+                        ));
+                    }
+                }
+            }
+            Mode::ControlFlow => {
+                ui.columns(2, |columns| {
+                    stack_panel(&mut columns[0], stack);
+                    columns[1].add(egui::TextEdit::multiline(start_program));
+                    if columns[1].button("Use new program").clicked() {
+                        match Stack::<Rule>::create_from_string(start_program.to_string()) {
+                            Ok(s) => {
+                                let Pgm::Program(_xl, _levels, s0) =
+                                    parse(start_program.to_string()).expect("already parsed");
+                                *stack = s;
+                                *cfg = Some(Cfg::build(&s0));
+                                *error_message = "".to_string();
+                            }
+                            Err(e) => *error_message = e,
+                        }
+                    }
+                    error_panel(&mut columns[1], error_message);
+                });
+                if let Some(cfg) = cfg {
+                    let highlight = cfg.current(stack.top());
+                    egui::ScrollArea::both().show(ui, |ui| {
+                        cfg_panel(ui, cfg, &highlight);
+                        CollapsingHeader::new("DOT")
+                            .default_open(false)
+                            .show(ui, |ui| {
+                                ui.label(cfg.to_dot(&highlight));
+                            });
+                    });
+                }
+            }
+            Mode::ModelCheck => {
+                ui.horizontal(|ui| {
+                    ui.label("Formula:");
+                    ui.text_edit_singleline(formula);
+                });
+                if ui.button("Check formula").clicked() {
+                    let result = parse(start_program.to_string())
+                        .map_err(|e| e.to_string())
+                        .and_then(|pgm| {
+                            let phi = parse_ltl(formula.to_string())
+                                .ok_or_else(|| "Unable to parse formula".to_string())?;
+                            check(&pgm, &phi)
+                        });
+                    match result {
+                        Ok(cex) => {
+                            *counterexample = cex;
+                            *verdict = match counterexample {
+                                Some(cex) => format!("counterexample:\n{}", cex),
+                                None => "the formula holds on every run".to_string(),
+                            };
+                            *error_message = "".to_string();
+                        }
+                        Err(e) => *error_message = e,
+                    }
+                }
+                error_panel(ui, error_message);
+                if let Some(cex) = counterexample {
+                    if ui.button("Load counterexample into stepper").clicked() {
+                        *stack = cex.to_stack();
+                        *loaded_program = start_program.clone();
+                        *mode = Mode::Semantics;
+                    }
+                }
+                ui.columns(2, |columns| {
+                    columns[0].add(egui::TextEdit::multiline(start_program));
+                    columns[1].label(verdict.as_str());
+                });
+            }
+            Mode::Equivalence => {
+                if ui.button("Compare").clicked() {
+                    let result = match (
+                        parse(start_program.to_string()),
+                        parse(other_program.to_string()),
+                    ) {
+                        (Ok(p1), Ok(p2)) => equiv::check(&p1, &p2),
+                        (Err(e), _) | (_, Err(e)) => Err(e.to_string()),
+                    };
+                    match result {
+                        Ok(Some(difference)) => {
+                            *comparison = difference.to_string();
+                            *error_message = "".to_string();
+                        }
+                        Ok(None) => {
+                            *comparison = "the programs agree on every state tried".to_string();
+                            *error_message = "".to_string();
+                        }
+                        Err(e) => *error_message = e,
+                    }
+                }
+                error_panel(ui, error_message);
+                ui.columns(2, |columns| {
+                    columns[0].add(egui::TextEdit::multiline(start_program));
+                    columns[1].add(egui::TextEdit::multiline(other_program));
+                });
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.label(comparison.as_str());
+                });
+            }
+            Mode::Bounded => {
+                ui.horizontal(|ui| {
+                    ui.label("Assertion:");
+                    ui.text_edit_singleline(bmc_assertion);
+                    ui.add(egui::Slider::new(bmc_depth, 0..=20).text("unrolling depth"));
+                });
+                if ui.button("Check").clicked() {
+                    let result = parse(start_program.to_string())
+                        .map_err(|e| e.to_string())
+                        .and_then(|pgm| {
+                            let assertion = parse_bexp(bmc_assertion.to_string())
+                                .ok_or_else(|| "Unable to parse assertion".to_string())?;
+                            bmc::check(&pgm, &assertion, *bmc_depth)
+                        });
+                    match result {
+                        Ok((cex, vars)) => {
+                            *bmc_verdict = match &cex {
+                                Some(cex) => format!("counterexample:\n{}", cex),
+                                None => format!(
+                                    "the assertion holds on every run within {} unrollings",
+                                    bmc_depth
+                                ),
+                            };
+                            *bmc_verdict += &format!("\n\n({} propositional variables)", vars);
+                            *bmc_counterexample = cex;
+                            *error_message = "".to_string();
+                        }
+                        Err(e) => *error_message = e,
+                    }
+                }
+                error_panel(ui, error_message);
+                if let Some(cex) = bmc_counterexample {
+                    if ui.button("Load counterexample into stepper").clicked() {
+                        *stack = cex.to_stack();
+                        *loaded_program = start_program.clone();
+                        *mode = Mode::Semantics;
+                    }
+                }
+                ui.columns(2, |columns| {
+                    columns[0].add(egui::TextEdit::multiline(start_program));
+                    columns[1].label(bmc_verdict.as_str());
+                });
+            }
+            Mode::Slice => {
+                if ui.button("Slice").clicked() {
+                    let result = parse(start_program.to_string())
+                        .map_err(|e| e.to_string())
+                        .and_then(|pgm| slice::slice(&pgm, slice_variable, *slice_point));
+                    match result {
+                        Ok(s) => {
+                            *slice = Some(s);
+                            *error_message = "".to_string();
+                        }
+                        Err(e) => *error_message = e,
+                    }
+                }
+                error_panel(ui, error_message);
+                if let Some(s) = slice {
+                    if ui.button("Load slice into stepper").clicked() {
+                        // parsed back from the printed text so that its spans point into it,
+                        // and checked like any other program the stepper is given
+                        let printed = pretty(&s.pgm);
+                        match Stack::<Rule>::create_from_string(printed.clone()) {
+                            Ok(loaded) => {
+                                *stack = loaded;
+                                *start_program = printed;
+                                *loaded_program = start_program.clone();
+                                *error_message = "".to_string();
+                                *mode = Mode::Semantics;
+                            }
+                            Err(e) => *error_message = e,
+                        }
+                    }
+                }
+                ui.columns(2, |columns| {
+                    columns[0].add(egui::TextEdit::multiline(start_program));
+                    if let Some(s) = slice {
+                        egui::ScrollArea::vertical().show(&mut columns[1], |ui| {
+                            for (i, (depth, label, kept)) in s.listing.iter().enumerate() {
+                                if i == s.point {
+                                    ui.colored_label(egui::Color32::YELLOW, "--> criterion");
+                                }
+                                let line = format!("{}{}", "    ".repeat(*depth), label);
+                                if *kept {
+                                    ui.add(egui::Label::new(line).monospace().strong());
+                                } else {
+                                    ui.add(egui::Label::new(line).monospace().weak());
+                                }
+                            }
+                            if s.point == s.listing.len() {
+                                ui.colored_label(egui::Color32::YELLOW, "--> criterion");
+                            }
+                        });
+                    }
+                });
+            }
+            Mode::Coverage => {
+                ui.horizontal(|ui| {
+                    ui.label("Directory of .imp programs:");
+                    ui.text_edit_singleline(corpus);
+                    if ui.button("Report").clicked() {
+                        match coverage::corpus(std::path::Path::new(corpus.as_str())) {
+                            Ok(programs) => {
+                                *coverage = coverage::coverage(&programs).to_string();
+                                *error_message = "".to_string();
+                            }
+                            Err(e) => *error_message = e,
+                        }
+                    }
+                });
+                error_panel(ui, error_message);
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.monospace(coverage.as_str());
+                });
+            }
+            Mode::Cost => {
+                ui.label("The steps taken so far on the program of the semantics, by the axiom that completed them and the statement they belong to");
+                ui.horizontal(|ui| {
+                    ui.label("Variable:");
+                    ui.text_edit_singleline(cost_variable);
+                    // the variable starts with each value, which also replaces the constants assigned to it
+                    if ui.button("Cost by value").clicked() {
+                        *growth = cost::growth(&cost::program(stack), cost_variable)
+                            .into_iter()
+                            .map(|(i, steps)| match steps {
+                                Some(n) => format!("{} = {}: {} steps", cost_variable, i, n),
+                                None => format!("{} = {}: does not finish", cost_variable, i),
+                            })
+                            .collect::<Vec<String>>()
+                            .join("\n");
+                    }
+                });
+                ui.columns(2, |columns| {
+                    egui::ScrollArea::vertical().show(&mut columns[0], |ui| {
+                            ui.monospace(cost::profile(stack).to_string());
+                        });
+                    columns[1].monospace(growth.as_str());
+                });
+            }
+            Mode::Optimizer => {
+                if ui.button("Optimize").clicked() {
+                    let result = parse(start_program.to_string())
+                        .map_err(|e| e.to_string())
+                        .and_then(|pgm| {
+                            let (opt, log) = optimize(&pgm);
+                            let verdict = match equiv::check(&pgm, &opt)? {
+                                Some(difference) => difference.to_string(),
+                                None => "the optimized program agrees with the original on every state tried".to_string(),
+                            };
+                            Ok((opt, log, verdict))
+                        });
+                    match result {
+                        Ok((opt, log, verdict)) => {
+                            *optimized = pretty(&opt);
+                            let mut lines = log.iter().map(|r| r.to_string()).collect::<Vec<String>>();
+                            if lines.is_empty() {
+                                lines.push("nothing to rewrite".to_string());
+                            }
+                            lines.push("".to_string());
+                            lines.push(verdict);
+                            *rewrites = lines.join("\n");
+                            *error_message = "".to_string();
+                        }
+                        Err(e) => *error_message = e,
+                    }
+                }
+                error_panel(ui, error_message);
+                ui.columns(2, |columns| {
+                    columns[0].add(egui::TextEdit::multiline(start_program));
+                    columns[1].monospace(optimized.as_str());
+                    // the optimized program is printed in the syntax of the editor
+                    if !optimized.is_empty() && columns[1].button("Edit optimized program").clicked() {
+                        *start_program = optimized.clone();
+                    }
+                });
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.monospace(rewrites.as_str());
+                });
+            }
+            Mode::Dataflow => {
+                if ui.button("Analyse").clicked() {
+                    match parse(start_program.to_string()) {
+                        Ok(pgm) => {
+                            *dataflow = dataflow::report(&pgm);
+                            *error_message = "".to_string();
+                        }
+                        Err(e) => *error_message = e.to_string(),
+                    }
+                }
+                error_panel(ui, error_message);
+                ui.columns(2, |columns| {
+                    columns[0].add(egui::TextEdit::multiline(start_program));
+                    columns[1].monospace(dataflow.as_str());
+                });
+            }
+            Mode::Abstract => {
+                if ui.button("Analyse").clicked() {
+                    let result = parse(start_program.to_string())
+                        .map_err(|e| e.to_string())
+                        .and_then(|pgm| match domain {
+                            AbstractDomain::Sign => report::<Sign>(&pgm),
+                            AbstractDomain::Interval => report::<Interval>(&pgm),
+                        });
+                    match result {
+                        Ok(r) => {
+                            *analysis = r;
+                            *error_message = "".to_string();
+                        }
+                        Err(e) => *error_message = e,
+                    }
+                }
+                error_panel(ui, error_message);
+                ui.columns(2, |columns| {
+                    columns[0].add(egui::TextEdit::multiline(start_program));
+                    columns[1].label(analysis.as_str());
+                });
+            }
+            Mode::Symbolic => {
+                stack_panel(ui, sym_stack);
+
+                let _response = ui.add(egui::TextEdit::multiline(start_program));
+                if ui.button("Use new program").clicked() {
+                    match Stack::<SymRule>::create_from_string(start_program.to_string()) {
+                        Ok(s) => {
+                            *sym_stack = s;
+                            *error_message = "".to_string();
+                        }
+                        Err(e) => *error_message = e,
+                    }
                 }
-                println!("{:?}", stack);
+                error_panel(ui, error_message);
             }
-            ui.label(error_message);
         });
     }
 
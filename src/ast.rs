@@ -1,18 +1,29 @@
 use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
 
 use crate::parse::parse;
-#[derive(Clone, Debug)]
-struct State(Vec<(String, i32)>);
+use crate::stack::RewriteRule;
+use crate::stack::Stack;
+use crate::typing::check;
+// the environment maps names to locations, the store maps locations to values
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct State(Vec<(String, i32)>, Vec<(i32, i32)>);
 
 impl fmt::Display for State {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            State(v) => {
+            State(env, store) => {
                 write!(
                     f,
-                    "[{}]",
-                    v.iter()
-                        .map(|(k, v)| format!("{} |-> {}", k, v))
+                    "[{}], [{}]",
+                    env.iter()
+                        .map(|(k, l)| format!("{} |-> {}", k, l))
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    store
+                        .iter()
+                        .map(|(l, v)| format!("{} |-> {}", l, v))
                         .collect::<Vec<String>>()
                         .join(", ")
                 )
@@ -22,44 +33,98 @@ impl fmt::Display for State {
 }
 
 impl State {
-    fn substitute(&self, s: String, i: i32) -> State {
-        let State(v) = self;
-        let new_vec = v
+    fn location(&self, x: &str) -> Option<i32> {
+        let State(env, _store) = self;
+        env.iter().find(|(k, _l)| k == x).map(|(_k, l)| *l)
+    }
+    fn read(&self, l: i32) -> Option<i32> {
+        let State(_env, store) = self;
+        store.iter().find(|(k, _v)| *k == l).map(|(_k, v)| *v)
+    }
+    fn lookup(&self, x: &str) -> Option<i32> {
+        self.read(self.location(x)?)
+    }
+    fn update(&self, l: i32, i: i32) -> State {
+        let State(env, store) = self;
+        let new_store = store
             .iter()
-            .map(|(k, v)| {
-                if k == &s {
-                    (k.clone(), i)
-                } else {
-                    (k.clone(), *v)
-                }
-            })
+            .map(|(k, v)| if *k == l { (*k, i) } else { (*k, *v) })
             .collect();
-        State(new_vec)
+        State(env.clone(), new_store)
+    }
+    // returns a fresh location holding 0 together with the extended store
+    fn alloc(&self) -> (i32, State) {
+        let State(env, store) = self;
+        let l = store.iter().map(|(k, _v)| k + 1).max().unwrap_or(0);
+        let mut new_store = store.clone();
+        new_store.push((l, 0));
+        (l, State(env.clone(), new_store))
+    }
+    // the value of every declared variable
+    pub fn bindings(&self) -> Vec<(String, i32)> {
+        self.0
+            .iter()
+            .filter_map(|(x, _l)| Some((x.clone(), self.lookup(x)?)))
+            .collect()
+    }
+    // a state holding the given values, with the variables at consecutive locations
+    pub fn from_bindings(bindings: &[(String, i32)]) -> State {
+        let env: Vec<(String, i32)> = bindings.iter().map(|(x, _i)| x.clone()).zip(0..).collect();
+        let store = bindings.iter().map(|(_x, i)| *i).enumerate();
+        State(env, store.map(|(l, i)| (l as i32, i)).collect())
     }
     fn create_state(v: Vec<String>) -> State {
-        State(v.into_iter().map(|x| (x, 0)).collect())
+        let env: Vec<(String, i32)> = v.into_iter().zip(0..).collect();
+        let store = env.iter().map(|(_k, l)| (*l, 0)).collect();
+        State(env, store)
+    }
+}
+// the security label of a declared variable, low unless declared high
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Level {
+    Low,
+    High,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Level::Low => write!(f, "low"),
+            Level::High => write!(f, "high"),
+        }
     }
 }
-#[derive(Clone, Debug)]
+
+// the declared variables with their labels, and the body
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub enum Pgm {
-    Program(Vec<String>, Stmt),
+    Program(Vec<String>, Vec<Level>, Stmt),
 }
 
 impl fmt::Display for Pgm {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Pgm::Program(vars, s) => {
-                write!(f, "int {} ; \n{}", vars.join(", "), s.to_string())
+            Pgm::Program(vars, levels, s) => {
+                let decls = vars
+                    .iter()
+                    .zip(levels)
+                    .map(|(x, level)| match level {
+                        Level::Low => x.clone(),
+                        Level::High => format!("high {}", x),
+                    })
+                    .collect::<Vec<String>>();
+                write!(f, "int {} ; \n{}", decls.join(", "), s)
             }
         }
     }
 }
-#[derive(Clone, Debug)]
-enum Configuration {
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Configuration {
     AExpConf(Box<AExp>, State),
     BExpConf(Box<BExp>, State),
     StmtConf(Box<Stmt>, State),
     PgmConf(Box<Pgm>),
+    UncaughtConf(i32, State),
     Dummy, //top level, meaning that it is an unconditional rewrite
 }
 
@@ -67,16 +132,19 @@ impl fmt::Display for Configuration {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Configuration::AExpConf(a, s) => {
-                write!(f, "< {}, {} >", a.to_string(), s.to_string())
+                write!(f, "< {}, {} >", a, s)
             }
             Configuration::BExpConf(a, s) => {
-                write!(f, "< {}, {} >", a.to_string(), s.to_string())
+                write!(f, "< {}, {} >", a, s)
             }
             Configuration::StmtConf(a, s) => {
-                write!(f, "< {}, {} >", a.to_string(), s.to_string())
+                write!(f, "< {}, {} >", a, s)
             }
             Configuration::PgmConf(a) => {
-                write!(f, "< {} >", a.to_string())
+                write!(f, "< {} >", a)
+            }
+            Configuration::UncaughtConf(i, s) => {
+                write!(f, "< uncaught {}, {} >", i, s)
             }
             Configuration::Dummy => {
                 write!(f, "Error this shouldn't be here")
@@ -85,102 +153,366 @@ impl fmt::Display for Configuration {
     }
 }
 
-#[derive(Clone, Debug)]
+impl Configuration {
+    // the values of the variables, a program is about to start with all of them at 0
+    pub fn bindings(&self) -> Option<Vec<(String, i32)>> {
+        match self {
+            Configuration::AExpConf(_, sigma)
+            | Configuration::BExpConf(_, sigma)
+            | Configuration::StmtConf(_, sigma)
+            | Configuration::UncaughtConf(_, sigma) => Some(sigma.bindings()),
+            Configuration::PgmConf(p) => match &**p {
+                Pgm::Program(xl, _levels, _s) => Some(State::create_state(xl.clone()).bindings()),
+            },
+            Configuration::Dummy => None,
+        }
+    }
+    // where in the source the term about to be reduced was written, if it came from there
+    pub fn executing(&self) -> Option<Span> {
+        let span = match self {
+            Configuration::AExpConf(a, _sigma) => a.span(),
+            Configuration::BExpConf(b, _sigma) => b.span(),
+            Configuration::StmtConf(s, _sigma) => s.current().span(),
+            Configuration::PgmConf(p) => match &**p {
+                Pgm::Program(_xl, _levels, s) => s.current().span(),
+            },
+            _ => return None,
+        };
+        Some(span).filter(|span| !span.is_empty())
+    }
+    // the values of the variables once the program has finished
+    pub fn final_state(&self) -> Option<Vec<(String, i32)>> {
+        match self {
+            Configuration::StmtConf(s, sigma) => match &**s {
+                Stmt::StmtBlock(b, _) if **b == Block::EmptyBlock => Some(sigma.bindings()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+// the bytes of the source a node was parsed from, empty for nodes built otherwise;
+// positions never take part in comparisons, so trees differing only in them are equal
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+    // whether two spans cover the same bytes, which == does not look at
+    pub fn same(&self, other: &Span) -> bool {
+        self.start == other.start && self.end == other.end
+    }
+    // from the start of one node to the end of another
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+        }
+    }
+}
+
+impl PartialEq for Span {
+    fn eq(&self, _other: &Span) -> bool {
+        true
+    }
+}
+
+impl Eq for Span {}
+
+impl Hash for Span {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub enum AExp {
-    Plus(Box<AExp>, Box<AExp>),
-    Divide(Box<AExp>, Box<AExp>),
-    Id(String),
-    Int(i32),
+    Plus(Box<AExp>, Box<AExp>, Span),
+    Divide(Box<AExp>, Box<AExp>, Span),
+    Id(String, Span),
+    Int(i32, Span),
+    Deref(Box<AExp>, Span),
 }
 
 impl fmt::Display for AExp {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            AExp::Plus(a1, a2) => {
-                write!(f, "({} + {})", a1.to_string(), a2.to_string())
+            AExp::Plus(a1, a2, _) => {
+                write!(f, "({} + {})", a1, a2)
+            }
+            AExp::Divide(a1, a2, _) => {
+                write!(f, "({} / {})", a1, a2)
             }
-            AExp::Divide(a1, a2) => {
-                write!(f, "({} / {})", a1.to_string(), a2.to_string())
+            AExp::Id(s, _) => {
+                write!(f, "{}", s)
             }
-            AExp::Id(s) => {
-                write!(f, "{}", s.to_string())
+            AExp::Int(s, _) => {
+                write!(f, "{}", s)
             }
-            AExp::Int(s) => {
-                write!(f, "{}", s.to_string())
+            AExp::Deref(a, _) => {
+                write!(f, "*{}", a)
             }
         }
     }
 }
-#[derive(Clone, Debug)]
+impl AExp {
+    // A[a / x]
+    pub fn substitute(&self, x: &str, a: &AExp) -> AExp {
+        match self {
+            AExp::Plus(a1, a2, span) => AExp::Plus(
+                Box::new(a1.substitute(x, a)),
+                Box::new(a2.substitute(x, a)),
+                *span,
+            ),
+            AExp::Divide(a1, a2, span) => AExp::Divide(
+                Box::new(a1.substitute(x, a)),
+                Box::new(a2.substitute(x, a)),
+                *span,
+            ),
+            AExp::Id(y, _) if y == x => a.clone(),
+            AExp::Id(_, _) | AExp::Int(_, _) => self.clone(),
+            AExp::Deref(a1, span) => AExp::Deref(Box::new(a1.substitute(x, a)), *span),
+        }
+    }
+    pub fn span(&self) -> Span {
+        match self {
+            AExp::Plus(_, _, span)
+            | AExp::Divide(_, _, span)
+            | AExp::Id(_, span)
+            | AExp::Int(_, span)
+            | AExp::Deref(_, span) => *span,
+        }
+    }
+    pub fn vars(&self) -> Vec<String> {
+        match self {
+            AExp::Plus(a1, a2, _) | AExp::Divide(a1, a2, _) => {
+                let mut v = a1.vars();
+                v.extend(
+                    a2.vars()
+                        .into_iter()
+                        .filter(|y| !v.contains(y))
+                        .collect::<Vec<_>>(),
+                );
+                v
+            }
+            AExp::Id(x, _) => vec![x.clone()],
+            AExp::Int(_i, _) => vec![],
+            AExp::Deref(a, _) => a.vars(),
+        }
+    }
+    // whether the value depends on the store behind a pointer as well as on the variables
+    pub fn derefs(&self) -> bool {
+        match self {
+            AExp::Plus(a1, a2, _) | AExp::Divide(a1, a2, _) => a1.derefs() || a2.derefs(),
+            AExp::Deref(_a, _) => true,
+            AExp::Id(_, _) | AExp::Int(_, _) => false,
+        }
+    }
+    // None on division by zero, unbound variables or dereferences, which need a store
+    pub fn evaluate(&self, sigma: &[(String, i32)]) -> Option<i32> {
+        match self {
+            AExp::Plus(a1, a2, _) => a1.evaluate(sigma)?.checked_add(a2.evaluate(sigma)?),
+            AExp::Divide(a1, a2, _) => a1.evaluate(sigma)?.checked_div(a2.evaluate(sigma)?),
+            AExp::Id(x, _) => sigma.iter().find(|(k, _v)| k == x).map(|(_k, v)| *v),
+            AExp::Int(i, _) => Some(*i),
+            AExp::Deref(_a, _) => None,
+        }
+    }
+    // constant folding and the unit laws of + and /, a folded constant standing where its expression was
+    pub fn simplify(&self) -> AExp {
+        match self {
+            AExp::Plus(a1, a2, span) => match (a1.simplify(), a2.simplify()) {
+                (AExp::Int(i1, _), AExp::Int(i2, _)) if i1.checked_add(i2).is_some() => {
+                    AExp::Int(i1 + i2, *span)
+                }
+                (AExp::Int(0, _), a) | (a, AExp::Int(0, _)) => a,
+                (a1, a2) => AExp::Plus(Box::new(a1), Box::new(a2), *span),
+            },
+            AExp::Divide(a1, a2, span) => match (a1.simplify(), a2.simplify()) {
+                (AExp::Int(i1, _), AExp::Int(i2, _)) if i1.checked_div(i2).is_some() => {
+                    AExp::Int(i1 / i2, *span)
+                }
+                (a, AExp::Int(1, _)) => a,
+                (a1, a2) => AExp::Divide(Box::new(a1), Box::new(a2), *span),
+            },
+            AExp::Deref(a, span) => AExp::Deref(Box::new(a.simplify()), *span),
+            a => a.clone(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub enum BExp {
-    LessThanEq(Box<AExp>, Box<AExp>),
-    Negation(Box<BExp>),
-    And(Box<BExp>, Box<BExp>),
-    Bool(bool),
+    LessThanEq(Box<AExp>, Box<AExp>, Span),
+    Negation(Box<BExp>, Span),
+    And(Box<BExp>, Box<BExp>, Span),
+    Bool(bool, Span),
 }
 
 impl fmt::Display for BExp {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            BExp::LessThanEq(a, s) => {
-                write!(f, "({} <= {})", a.to_string(), s.to_string())
+            BExp::LessThanEq(a, s, _) => {
+                write!(f, "({} <= {})", a, s)
             }
-            BExp::Negation(a) => {
-                write!(f, "!({})", a.to_string())
+            BExp::Negation(a, _) => {
+                write!(f, "!({})", a)
             }
-            BExp::And(a, s) => {
-                write!(f, "({} && {})", a.to_string(), s.to_string())
+            BExp::And(a, s, _) => {
+                write!(f, "({} && {})", a, s)
             }
-            BExp::Bool(a) => {
+            BExp::Bool(a, _) => {
                 write!(f, "{}", a)
             }
         }
     }
 }
 
-#[derive(Clone, Debug)]
+impl BExp {
+    // B[a / x]
+    pub fn substitute(&self, x: &str, a: &AExp) -> BExp {
+        match self {
+            BExp::LessThanEq(a1, a2, span) => BExp::LessThanEq(
+                Box::new(a1.substitute(x, a)),
+                Box::new(a2.substitute(x, a)),
+                *span,
+            ),
+            BExp::Negation(b, span) => BExp::Negation(Box::new(b.substitute(x, a)), *span),
+            BExp::And(b1, b2, span) => BExp::And(
+                Box::new(b1.substitute(x, a)),
+                Box::new(b2.substitute(x, a)),
+                *span,
+            ),
+            BExp::Bool(_, _) => self.clone(),
+        }
+    }
+    pub fn span(&self) -> Span {
+        match self {
+            BExp::LessThanEq(_, _, span)
+            | BExp::Negation(_, span)
+            | BExp::And(_, _, span)
+            | BExp::Bool(_, span) => *span,
+        }
+    }
+    pub fn vars(&self) -> Vec<String> {
+        let (mut v, w) = match self {
+            BExp::LessThanEq(a1, a2, _) => (a1.vars(), a2.vars()),
+            BExp::Negation(b, _) => (b.vars(), vec![]),
+            BExp::And(b1, b2, _) => (b1.vars(), b2.vars()),
+            BExp::Bool(_b, _) => (vec![], vec![]),
+        };
+        v.extend(w.into_iter().filter(|y| !v.contains(y)).collect::<Vec<_>>());
+        v
+    }
+    pub fn derefs(&self) -> bool {
+        match self {
+            BExp::LessThanEq(a1, a2, _) => a1.derefs() || a2.derefs(),
+            BExp::Negation(b, _) => b.derefs(),
+            BExp::And(b1, b2, _) => b1.derefs() || b2.derefs(),
+            BExp::Bool(_, _) => false,
+        }
+    }
+    pub fn evaluate(&self, sigma: &[(String, i32)]) -> Option<bool> {
+        match self {
+            BExp::LessThanEq(a1, a2, _) => Some(a1.evaluate(sigma)? <= a2.evaluate(sigma)?),
+            BExp::Negation(b, _) => Some(!b.evaluate(sigma)?),
+            BExp::And(b1, b2, _) => Some(b1.evaluate(sigma)? && b2.evaluate(sigma)?),
+            BExp::Bool(b, _) => Some(*b),
+        }
+    }
+    // constant folding, double negation and the unit laws of &&,
+    // a conjunct is only dropped when it is a constant so undefinedness is preserved
+    pub fn simplify(&self) -> BExp {
+        match self {
+            BExp::LessThanEq(a1, a2, span) => match (a1.simplify(), a2.simplify()) {
+                (AExp::Int(i1, _), AExp::Int(i2, _)) => BExp::Bool(i1 <= i2, *span),
+                (a1, a2) => BExp::LessThanEq(Box::new(a1), Box::new(a2), *span),
+            },
+            BExp::Negation(b, span) => match b.simplify() {
+                BExp::Bool(b, _) => BExp::Bool(!b, *span),
+                BExp::Negation(b, _) => *b,
+                b => BExp::Negation(Box::new(b), *span),
+            },
+            BExp::And(b1, b2, span) => match (b1.simplify(), b2.simplify()) {
+                (BExp::Bool(true, _), b) | (b, BExp::Bool(true, _)) => b,
+                (BExp::Bool(false, _), _b) => BExp::Bool(false, *span),
+                (b1, b2) if b1 == b2 => b1,
+                (b1, b2) => BExp::And(Box::new(b1), Box::new(b2), *span),
+            },
+            BExp::Bool(_, _) => self.clone(),
+        }
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub enum Stmt {
-    StmtBlock(Box<Block>),
-    Assign(String, Box<AExp>),
-    Sequence(Box<Stmt>, Box<Stmt>),
-    IfThenElse(Box<BExp>, Box<Block>, Box<Block>),
-    While(Box<BExp>, Box<Block>),
+    StmtBlock(Box<Block>, Span),
+    Assign(String, Box<AExp>, Span),
+    Sequence(Box<Stmt>, Box<Stmt>, Span),
+    IfThenElse(Box<BExp>, Box<Block>, Box<Block>, Span),
+    While(Box<BExp>, Box<Block>, Span),
+    Choice(Box<Block>, Box<Block>, Span),
+    Throw(Box<AExp>, Span),
+    TryCatch(Box<Block>, String, Box<Block>, Span),
+    New(String, Span),
+    AssignDeref(Box<AExp>, Box<AExp>, Span),
+    IfThen(Box<BExp>, Box<Block>, Span),
+    DoWhile(Box<Block>, Box<BExp>, Span),
+    For(Box<Stmt>, Box<BExp>, Box<Stmt>, Box<Block>, Span),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub enum Block {
     EmptyBlock,
-    BlockStmt(Box<Stmt>),
+    BlockStmt(Box<Stmt>, Span),
 }
 
 impl fmt::Display for Stmt {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Stmt::StmtBlock(x) => {
-                write!(f, "{}", x.to_string())
+            Stmt::StmtBlock(x, _) => {
+                write!(f, "{}", x)
             }
-            Stmt::Assign(s, a) => {
-                write!(f, "{} = {} ; ", s.to_string(), a.to_string())
+            Stmt::Assign(s, a, _) => {
+                write!(f, "{} = {} ; ", s, a)
             }
-            Stmt::Sequence(s1, s2) => {
-                write!(f, "{}\n{}", s1.to_string(), s2.to_string())
+            Stmt::Sequence(s1, s2, _) => {
+                write!(f, "{}\n{}", s1, s2)
             }
-            Stmt::IfThenElse(b, b1, b2) => {
-                write!(
-                    f,
-                    "if {} then\n {} \n else {} \n end",
-                    b.to_string(),
-                    b1.to_string(),
-                    b2.to_string()
-                )
+            Stmt::IfThenElse(b, b1, b2, _) => {
+                write!(f, "if {} then\n {} \n else {} \n end", b, b1, b2)
             }
-            Stmt::While(b, block) => {
-                write!(
-                    f,
-                    "while {} do \n {} \n end",
-                    b.to_string(),
-                    block.to_string()
-                )
+            Stmt::While(b, block, _) => {
+                write!(f, "while {} do \n {} \n end", b, block)
+            }
+            Stmt::Choice(b1, b2, _) => {
+                write!(f, "{{ {} }} [] {{ {} }}", b1, b2)
+            }
+            Stmt::Throw(a, _) => {
+                write!(f, "throw {} ; ", a)
+            }
+            Stmt::TryCatch(b1, x, b2, _) => {
+                write!(f, "try {{ {} }} catch ({}) {{ {} }}", b1, x, b2)
+            }
+            Stmt::New(x, _) => {
+                write!(f, "{} = new ; ", x)
+            }
+            Stmt::AssignDeref(a1, a2, _) => {
+                write!(f, "*{} = {} ; ", a1, a2)
+            }
+            Stmt::IfThen(b, block, _) => {
+                write!(f, "if {} then\n {} \n end", b, block)
+            }
+            Stmt::DoWhile(block, b, _) => {
+                write!(f, "do \n {} \n while {} ; ", block, b)
+            }
+            Stmt::For(s1, b, s2, block, _) => {
+                write!(f, "for ({}{} ; {}) do \n {} \n end", s1, b, s2, block)
             }
         }
     }
@@ -191,52 +523,72 @@ impl fmt::Display for Block {
             Block::EmptyBlock => {
                 write!(f, "{{}}")
             }
-            Block::BlockStmt(x) => {
-                write!(f, "{}", x.to_string())
+            Block::BlockStmt(x, _) => {
+                write!(f, "{}", x)
             }
         }
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct Stack {
-    stack: Vec<Configuration>,
-    rules: Vec<Rule>,
-    prev: Option<Box<Stack>>,
-    next: Option<Box<Stack>>,
-}
-
-impl fmt::Display for Stack {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            self.stack
-                .iter()
-                .rev()
-                .map(|x| x.to_string())
-                .collect::<Vec<String>>()
-                .join("\n---\n")
-        )
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::StmtBlock(_, span)
+            | Stmt::Assign(_, _, span)
+            | Stmt::Sequence(_, _, span)
+            | Stmt::IfThenElse(_, _, _, span)
+            | Stmt::While(_, _, span)
+            | Stmt::Choice(_, _, span)
+            | Stmt::Throw(_, span)
+            | Stmt::TryCatch(_, _, _, span)
+            | Stmt::New(_, span)
+            | Stmt::AssignDeref(_, _, span)
+            | Stmt::IfThen(_, _, span)
+            | Stmt::DoWhile(_, _, span)
+            | Stmt::For(_, _, _, _, span) => *span,
+        }
+    }
+    // the statement the next step belongs to: the first of a sequence, looking inside blocks
+    // and the body of a try
+    pub fn current(&self) -> &Stmt {
+        match self {
+            Stmt::Sequence(s1, _, _) => s1.current(),
+            Stmt::StmtBlock(b, _) | Stmt::TryCatch(b, _, _, _) => match &**b {
+                Block::BlockStmt(s, _) => s.current(),
+                Block::EmptyBlock => self,
+            },
+            _ => self,
+        }
     }
 }
-impl Stack {
-    pub fn create_from_string(s: String) -> Option<Stack> {
-        let pgm = parse(s)?;
-        let s = Stack {
-            stack: vec![Configuration::PgmConf(Box::new(pgm))],
-            rules: vec![],
-            prev: None,
-            next: None,
-        };
-        Some(s)
+
+impl Stack<Rule> {
+    pub fn create_from_string(s: String) -> Result<Stack<Rule>, String> {
+        let pgm = parse(s).map_err(|e| e.to_string())?;
+        check(&pgm)?;
+        Ok(Stack::create(Configuration::PgmConf(Box::new(pgm))))
     }
-    pub fn clear(&mut self) {
-        self.rules.clear();
-        self.stack.drain(1..self.stack.len());
+    // starts the body of a program from the given values rather than with every variable at 0,
+    // the variables laid out in the order the program declares them
+    pub fn create_from_state(pgm: &Pgm, bindings: &[(String, i32)]) -> Stack<Rule> {
+        let Pgm::Program(xl, _levels, s) = pgm;
+        let values = xl
+            .iter()
+            .map(|x| {
+                let value = bindings
+                    .iter()
+                    .find(|(y, _i)| y == x)
+                    .map_or(0, |(_y, i)| *i);
+                (x.clone(), value)
+            })
+            .collect::<Vec<_>>();
+        Stack::create(Configuration::StmtConf(
+            Box::new(s.clone()),
+            State::from_bindings(&values),
+        ))
     }
 
-    pub fn new() -> Stack {
+    pub fn new() -> Stack<Rule> {
         // let variables = vec!["x".to_string(), "y".to_string()];
         // let assign_x = Stmt::Assign("x".to_string(), Box::new(AExp::Int(5)));
         // let assign_y = Stmt::Assign("y".to_string(), Box::new(AExp::Int(7)));
@@ -254,154 +606,43 @@ impl Stack {
         //     rules: vec![],
         // }
         let variables = vec!["x".to_string(), "y".to_string()];
-        let assign_x = Stmt::Assign("x".to_string(), Box::new(AExp::Int(5)));
-        let assign_y = Stmt::Assign("y".to_string(), Box::new(AExp::Int(7)));
-        let evaluate_x = AExp::Id("x".to_string());
-        let evaluate_y = AExp::Id("y".to_string());
-        let add_to_x = AExp::Plus(Box::new(evaluate_x.clone()), Box::new(AExp::Int(1)));
-        let add_to_x2 = Stmt::Assign("x".to_string(), Box::new(add_to_x));
-        let less_xy = BExp::LessThanEq(Box::new(evaluate_x), Box::new(evaluate_y));
+        let assign_x = Stmt::Assign(
+            "x".to_string(),
+            Box::new(AExp::Int(5, Span::default())),
+            Span::default(),
+        );
+        let assign_y = Stmt::Assign(
+            "y".to_string(),
+            Box::new(AExp::Int(7, Span::default())),
+            Span::default(),
+        );
+        let evaluate_x = AExp::Id("x".to_string(), Span::default());
+        let evaluate_y = AExp::Id("y".to_string(), Span::default());
+        let add_to_x = AExp::Plus(
+            Box::new(evaluate_x.clone()),
+            Box::new(AExp::Int(1, Span::default())),
+            Span::default(),
+        );
+        let add_to_x2 = Stmt::Assign("x".to_string(), Box::new(add_to_x), Span::default());
+        let less_xy = BExp::LessThanEq(Box::new(evaluate_x), Box::new(evaluate_y), Span::default());
         let while_xy = Stmt::While(
             Box::new(less_xy),
-            Box::new(Block::BlockStmt(Box::new(add_to_x2))),
+            Box::new(Block::BlockStmt(Box::new(add_to_x2), Span::default())),
+            Span::default(),
         );
         let program = Stmt::Sequence(
             assign_x.into(),
-            Box::new(Stmt::Sequence(Box::new(assign_y), Box::new(while_xy))),
+            Box::new(Stmt::Sequence(
+                Box::new(assign_y),
+                Box::new(while_xy),
+                Span::default(),
+            )),
+            Span::default(),
         );
-        Stack {
-            stack: vec![Configuration::PgmConf(Box::new(Pgm::Program(
-                variables, program,
-            )))],
-            rules: vec![],
-            prev: None,
-            next: None,
-        }
-    }
-    pub fn can_apply_rule(&self, rule: Rule) -> bool {
-        let last = self.stack.last().expect("oops");
-        let next_configuration = rule.get_next_configuration(last.clone());
-        println!("{:?}", next_configuration);
-        match next_configuration {
-            Some(Configuration::Dummy) => {
-                let top_conf = Configuration::Dummy;
-                let bottom_conf = last;
-                println!("{:?}", self);
-                match rule.reduce_down(bottom_conf.clone(), top_conf) {
-                    None => false,
-                    Some(_x) => true,
-                }
-            }
-            Some(_conf) => true,
-            None => false,
-        }
-    }
-    // true means a sucess apply, false failed to apply rule
-    pub fn apply_rule(&mut self, rule: Rule) -> bool {
-        let old_rules = self.rules.clone();
-        let old_stack = self.stack.clone();
-        println!("{:?}", self);
-        self.rules.push(rule.clone());
-        println!("{:?}", self);
-        let last = self.stack.last().expect("oops");
-        let next_configuration = rule.get_next_configuration(last.clone());
-        println!("{:?}", next_configuration);
-        match next_configuration {
-            Some(Configuration::Dummy) => {
-                let mut top_conf = Configuration::Dummy;
-                while !self.rules.is_empty() {
-                    let rule = self.rules.pop().expect("failed to pop rule stack");
-                    let bottom_conf = self.stack.pop().expect("failed to pop stack");
-                    println!("{:?}", self);
-                    match rule.reduce_down(bottom_conf.clone(), top_conf) {
-                        None => {
-                            self.stack.push(bottom_conf); //restore the stack
-                            self.rules.pop(); // remove bad rule
-                            return false;
-                        }
-                        Some(x) => top_conf = x,
-                    }
-
-                    println!("{:?}", self);
-                }
-                self.stack.push(top_conf);
-                println!("{:?}", self);
-
-                self.prev = Some(Box::new(Stack {
-                    stack: old_stack,
-                    rules: old_rules,
-                    prev: self.prev.clone(),
-                    next: None,
-                }))
-            }
-            Some(conf) => {
-                self.stack.push(conf);
-
-                self.prev = Some(Box::new(Stack {
-                    stack: old_stack,
-                    rules: old_rules,
-                    prev: self.prev.clone(),
-                    next: None,
-                }))
-            }
-            None => {
-                self.rules.pop();
-                return false;
-            }
-        }
-        true
-    }
-    pub fn pop(&mut self) {
-        if self.stack.len() > 1 {
-            self.stack.pop();
-            self.rules.pop();
-        }
-    }
-    pub fn create_from_prev(stack: &mut Stack, prev_stack: Stack) -> Stack {
-        Stack {
-            stack: prev_stack.stack.clone(),
-            rules: prev_stack.rules.clone(),
-            prev: prev_stack.prev,
-            next: Some(Box::new(stack.clone())),
-        }
-    }
-    pub fn create_from_next(stack: &mut Stack, next_stack: Stack) -> Stack {
-        Stack {
-            stack: next_stack.stack.clone(),
-            rules: next_stack.rules.clone(),
-            prev: Some(Box::new(stack.clone())),
-            next: next_stack.next,
-        }
-    }
-    pub fn undo(&mut self) {
-        let prev = self.clone();
-        match prev.prev {
-            None => (),
-            Some(b) => match *b {
-                pstack => {
-                    let ns = Stack::create_from_prev(self, pstack);
-                    self.stack = ns.stack.clone();
-                    self.rules = ns.rules.clone();
-                    self.prev = ns.prev.clone();
-                    self.next = ns.next;
-                }
-            },
-        }
-    }
-    pub fn redo(&mut self) {
-        let next = self.clone();
-        match next.next {
-            None => (),
-            Some(box1) => match *box1 {
-                nstack => {
-                    let ns = Stack::create_from_next(self, nstack);
-                    self.stack = ns.stack.clone();
-                    self.rules = ns.rules.clone();
-                    self.prev = ns.prev.clone();
-                    self.next = ns.next;
-                }
-            },
-        }
+        let levels = variables.iter().map(|_x| Level::Low).collect();
+        Stack::create(Configuration::PgmConf(Box::new(Pgm::Program(
+            variables, levels, program,
+        ))))
     }
 }
 
@@ -457,6 +698,40 @@ pub enum Rule {
     RewriteConditionalFalse,
     // rl o < while (B) S,Sigma > => < if (B) {S while (B) S} else {},Sigma > .
     RewriteLoop,
+    // rl o < S1 [] S2,Sigma > => < S1,Sigma > .
+    RewriteChoiceLeft,
+    // rl o < S1 [] S2,Sigma > => < S2,Sigma > .
+    RewriteChoiceRight,
+    // crl o < throw A ;,Sigma > => < throw A' ;,Sigma > if o < A,Sigma > => < A',Sigma > .
+    RewriteThrowArith,
+    // rl o < throw I ; S2,Sigma > => < throw I ;,Sigma > .
+    RewriteSequenceThrow,
+    // crl o < try S1 catch (X) S2,Sigma > => < try S1' catch (X) S2,Sigma' > if o < S1,Sigma > => < S1',Sigma' > .
+    RewriteTry,
+    // rl o < try {} catch (X) S2,Sigma > => < {},Sigma > .
+    RewriteTryEmpty,
+    // crl o < try throw I ; catch (X) S2,Sigma > => < S2,Sigma[I / X] > if Sigma(X) =/=Bool undefined .
+    RewriteTryCatch,
+    // rl o < throw I ;,Sigma > => < uncaught I,Sigma > .
+    RewriteUncaught,
+    // crl o < * A,Sigma > => < * A',Sigma > if o < A,Sigma > => < A',Sigma > .
+    RewriteDerefArith,
+    // crl o < * L,Sigma > => < Sigma(L),Sigma > if Sigma(L) =/=Bool undefined .
+    RewriteDeref,
+    // crl o < X = new ;,Sigma > => < {},Sigma[L |-> 0][L / X] > if L := fresh(Sigma) /\ Sigma(X) =/=Bool undefined .
+    RewriteNew,
+    // crl o < * A1 = A2 ;,Sigma > => < * A1' = A2 ;,Sigma > if o < A1,Sigma > => < A1',Sigma > .
+    RewriteAssignDerefLeft,
+    // crl o < * A1 = A2 ;,Sigma > => < * A1 = A2' ;,Sigma > if o < A2,Sigma > => < A2',Sigma > .
+    RewriteAssignDerefRight,
+    // crl o < * L = I ;,Sigma > => < {},Sigma[I / L] > if Sigma(L) =/=Bool undefined .
+    RewriteAssignDerefInt,
+    // rl o < if (B) S,Sigma > => < if (B) S else {},Sigma > .
+    RewriteIfThen,
+    // rl o < do S while (B) ;,Sigma > => < {S while (B) S},Sigma > .
+    RewriteDoWhile,
+    // rl o < for (S1 B ; S2) S,Sigma > => < {S1 while (B) {S S2}},Sigma > .
+    RewriteFor,
 
     // Enum::Fifth => " o < int Xl ; S > => < S,(Xl |-> 0) > .".to_string(),
     RewriteTop,
@@ -464,8 +739,25 @@ pub enum Rule {
     NoOp,
 }
 
-impl Rule {
-    pub fn list_of_rules() -> Vec<Rule> {
+impl RewriteRule for Rule {
+    type Conf = Configuration;
+
+    fn dummy() -> Configuration {
+        Configuration::Dummy
+    }
+
+    fn is_dummy(conf: &Configuration) -> bool {
+        matches!(conf, Configuration::Dummy)
+    }
+
+    fn charged(conf: &Configuration) -> bool {
+        matches!(
+            conf,
+            Configuration::StmtConf(..) | Configuration::PgmConf(_)
+        )
+    }
+
+    fn list_of_rules() -> Vec<Rule> {
         vec![
             Rule::RewriteVariableLookup,
             Rule::RewritePlusLeft,
@@ -489,10 +781,27 @@ impl Rule {
             Rule::RewriteConditionalTrue,
             Rule::RewriteConditionalFalse,
             Rule::RewriteLoop,
+            Rule::RewriteChoiceLeft,
+            Rule::RewriteChoiceRight,
+            Rule::RewriteThrowArith,
+            Rule::RewriteSequenceThrow,
+            Rule::RewriteTry,
+            Rule::RewriteTryEmpty,
+            Rule::RewriteTryCatch,
+            Rule::RewriteUncaught,
+            Rule::RewriteDerefArith,
+            Rule::RewriteDeref,
+            Rule::RewriteNew,
+            Rule::RewriteAssignDerefLeft,
+            Rule::RewriteAssignDerefRight,
+            Rule::RewriteAssignDerefInt,
+            Rule::RewriteIfThen,
+            Rule::RewriteDoWhile,
+            Rule::RewriteFor,
             Rule::RewriteTop,
         ]
     }
-    pub fn get_description(&self) -> String {
+    fn get_description(&self) -> String {
         match self {
             Rule::RewriteVariableLookup => "crl o < X,Sigma > => < Sigma(X),Sigma > if Sigma(X) =/=Bool undefined".to_string(),
             Rule::RewritePlusLeft => "crl o < A1 + A2,Sigma > => < A1' + A2,Sigma > if o < A1,Sigma > => < A1',Sigma >".to_string(),
@@ -517,12 +826,29 @@ impl Rule {
             Rule::RewriteConditionalFalse => "rl o < if (false) S1 else S2,Sigma > => < S2,Sigma >".to_string(),
 
             Rule::RewriteLoop => "rl o < while (B) S,Sigma > => < if (B) {S while (B) S} else {},Sigma >".to_string(),
+            Rule::RewriteChoiceLeft => "rl o < S1 [] S2,Sigma > => < S1,Sigma >".to_string(),
+            Rule::RewriteChoiceRight => "rl o < S1 [] S2,Sigma > => < S2,Sigma >".to_string(),
+            Rule::RewriteThrowArith => "crl o < throw A ;,Sigma > => < throw A' ;,Sigma > if o < A,Sigma > => < A',Sigma >".to_string(),
+            Rule::RewriteSequenceThrow => "rl o < throw I ; S2,Sigma > => < throw I ;,Sigma >".to_string(),
+            Rule::RewriteTry => "crl o < try S1 catch (X) S2,Sigma > => < try S1' catch (X) S2,Sigma' > if o < S1,Sigma > => < S1',Sigma' >".to_string(),
+            Rule::RewriteTryEmpty => "rl o < try {} catch (X) S2,Sigma > => < {},Sigma >".to_string(),
+            Rule::RewriteTryCatch => "crl o < try throw I ; catch (X) S2,Sigma > => < S2,Sigma[I / X] > if Sigma(X) =/=Bool undefined".to_string(),
+            Rule::RewriteUncaught => "rl o < throw I ;,Sigma > => < uncaught I,Sigma >".to_string(),
+            Rule::RewriteDerefArith => "crl o < * A,Sigma > => < * A',Sigma > if o < A,Sigma > => < A',Sigma >".to_string(),
+            Rule::RewriteDeref => "crl o < * L,Sigma > => < Sigma(L),Sigma > if Sigma(L) =/=Bool undefined".to_string(),
+            Rule::RewriteNew => "crl o < X = new ;,Sigma > => < {},Sigma[L |-> 0][L / X] > if L := fresh(Sigma) /\\ Sigma(X) =/=Bool undefined".to_string(),
+            Rule::RewriteAssignDerefLeft => "crl o < * A1 = A2 ;,Sigma > => < * A1' = A2 ;,Sigma > if o < A1,Sigma > => < A1',Sigma >".to_string(),
+            Rule::RewriteAssignDerefRight => "crl o < * A1 = A2 ;,Sigma > => < * A1 = A2' ;,Sigma > if o < A2,Sigma > => < A2',Sigma >".to_string(),
+            Rule::RewriteAssignDerefInt => "crl o < * L = I ;,Sigma > => < {},Sigma[I / L] > if Sigma(L) =/=Bool undefined".to_string(),
+            Rule::RewriteIfThen => "rl o < if (B) S,Sigma > => < if (B) S else {},Sigma >".to_string(),
+            Rule::RewriteDoWhile => "rl o < do S while (B) ;,Sigma > => < {S while (B) S},Sigma >".to_string(),
+            Rule::RewriteFor => "rl o < for (S1 B ; S2) S,Sigma > => < {S1 while (B) {S S2}},Sigma >".to_string(),
             Rule::RewriteTop  => "rl o < int Xl ; S > => < S,(Xl |-> 0) >".to_string(),
             Rule::NoOp  => "This was not supposed to be available".to_string(),
         }
     }
 
-    pub fn get_label(&self) -> String {
+    fn get_label(&self) -> String {
         match self {
             Rule::RewriteVariableLookup => "Variable Lookup".to_string(),
             Rule::RewritePlusLeft => "Plus Left".to_string(),
@@ -547,6 +873,23 @@ impl Rule {
             Rule::RewriteConditionalFalse => "Conditional False".to_string(),
 
             Rule::RewriteLoop => "Loop".to_string(),
+            Rule::RewriteChoiceLeft => "Choice Left".to_string(),
+            Rule::RewriteChoiceRight => "Choice Right".to_string(),
+            Rule::RewriteThrowArith => "Throw Arithmetic".to_string(),
+            Rule::RewriteSequenceThrow => "Sequence Throw".to_string(),
+            Rule::RewriteTry => "Try".to_string(),
+            Rule::RewriteTryEmpty => "Try Empty".to_string(),
+            Rule::RewriteTryCatch => "Try Catch".to_string(),
+            Rule::RewriteUncaught => "Uncaught".to_string(),
+            Rule::RewriteDerefArith => "Dereference Arithmetic".to_string(),
+            Rule::RewriteDeref => "Dereference".to_string(),
+            Rule::RewriteNew => "New".to_string(),
+            Rule::RewriteAssignDerefLeft => "Pointer Assignment Left".to_string(),
+            Rule::RewriteAssignDerefRight => "Pointer Assignment Right".to_string(),
+            Rule::RewriteAssignDerefInt => "Pointer Assignment Integer".to_string(),
+            Rule::RewriteIfThen => "If Then".to_string(),
+            Rule::RewriteDoWhile => "Do While".to_string(),
+            Rule::RewriteFor => "For".to_string(),
             Rule::RewriteTop => "Top".to_string(),
             Rule::NoOp => "This was not supposed to be available!!!".to_string(),
         }
@@ -560,7 +903,7 @@ impl Rule {
 
                 match conf {
                     Configuration::AExpConf(x, sigma) => match *x {
-                        AExp::Plus(a1, _a2) => Configuration::AExpConf(a1, sigma),
+                        AExp::Plus(a1, _a2, _) => Configuration::AExpConf(a1, sigma),
                         _ => return None,
                     },
                     _ => return None,
@@ -571,13 +914,10 @@ impl Rule {
 
                 match conf {
                     Configuration::AExpConf(x, sigma) => match *x {
-                        AExp::Id(x) => {
-                            let State(v) = sigma;
-                            match v.iter().find(|(k, _v)| k == &x) {
-                                Some((_k, _v)) => Configuration::Dummy,
-                                _ => return None,
-                            }
-                        }
+                        AExp::Id(x, _) => match sigma.lookup(&x) {
+                            Some(_v) => Configuration::Dummy,
+                            None => return None,
+                        },
                         _ => return None,
                     },
                     _ => return None,
@@ -588,7 +928,7 @@ impl Rule {
 
                 match conf {
                     Configuration::AExpConf(x, sigma) => match *x {
-                        AExp::Plus(_a1, a2) => Configuration::AExpConf(a2, sigma),
+                        AExp::Plus(_a1, a2, _) => Configuration::AExpConf(a2, sigma),
                         _ => return None,
                     },
                     _ => return None,
@@ -603,7 +943,7 @@ impl Rule {
                 // crl o < A1 / A2,Sigma > => < A1' / A2,Sigma > if o < A1,Sigma > => < A1',Sigma >
                 match conf {
                     Configuration::AExpConf(x, sigma) => match *x {
-                        AExp::Divide(a1, _a2) => Configuration::AExpConf(a1, sigma),
+                        AExp::Divide(a1, _a2, _) => Configuration::AExpConf(a1, sigma),
                         _ => return None,
                     },
                     _ => return None,
@@ -613,7 +953,7 @@ impl Rule {
                 // crl o < A1 / A2,Sigma > => < A1 / A2',Sigma > if o < A2,Sigma > => < A2',Sigma > .
                 match conf {
                     Configuration::AExpConf(x, sigma) => match *x {
-                        AExp::Divide(_a1, a2) => Configuration::AExpConf(a2, sigma),
+                        AExp::Divide(_a1, a2, _) => Configuration::AExpConf(a2, sigma),
                         _ => return None,
                     },
                     _ => return None,
@@ -622,7 +962,7 @@ impl Rule {
             // crl o < A1 <= A2,Sigma > => < A1' <= A2,Sigma > if o < A1,Sigma > => < A1',Sigma > .
             Rule::RewriteLessThanLeft => match conf {
                 Configuration::BExpConf(x, sigma) => match *x {
-                    BExp::LessThanEq(a1, _a2) => Configuration::AExpConf(a1, sigma),
+                    BExp::LessThanEq(a1, _a2, _) => Configuration::AExpConf(a1, sigma),
                     _ => return None,
                 },
                 _ => return None,
@@ -630,7 +970,7 @@ impl Rule {
             // crl o < I1 <= A2,Sigma > => < I1 <= A2',Sigma > if o < A2,Sigma > => < A2',Sigma > .
             Rule::RewriteLessThanRight => match conf {
                 Configuration::BExpConf(x, sigma) => match *x {
-                    BExp::LessThanEq(_a1, a2) => Configuration::AExpConf(a2, sigma),
+                    BExp::LessThanEq(_a1, a2, _) => Configuration::AExpConf(a2, sigma),
                     _ => return None,
                 },
                 _ => return None,
@@ -640,7 +980,7 @@ impl Rule {
             // crl o < ! B,Sigma > => < ! B',Sigma > if o < B,Sigma > => < B',Sigma > .
             Rule::RewriteNegate => match conf {
                 Configuration::BExpConf(x, sigma) => match *x {
-                    BExp::Negation(b) => Configuration::BExpConf(b, sigma),
+                    BExp::Negation(b, _) => Configuration::BExpConf(b, sigma),
                     _ => return None,
                 },
                 _ => return None,
@@ -656,7 +996,7 @@ impl Rule {
                 // "crl o < S1 S2,Sigma > => < S1' S2,Sigma' > if o < S1,Sigma > => < S1',Sigma' > ."
                 match conf {
                     Configuration::StmtConf(x, sigma) => match *x {
-                        Stmt::Sequence(s1, _) => Configuration::StmtConf(s1, sigma),
+                        Stmt::Sequence(s1, _, _) => Configuration::StmtConf(s1, sigma),
                         _ => return None,
                     },
                     _ => return None,
@@ -667,7 +1007,7 @@ impl Rule {
                 // "crl o < X = A ;,Sigma > => < X = A' ;,Sigma > if o < A,Sigma > => < A',Sigma > ."
                 match conf {
                     Configuration::StmtConf(x, sigma) => match *x {
-                        Stmt::Assign(_x, a) => Configuration::AExpConf(a, sigma),
+                        Stmt::Assign(_x, a, _) => Configuration::AExpConf(a, sigma),
                         _ => return None,
                     },
                     _ => return None,
@@ -687,7 +1027,7 @@ impl Rule {
                 // crl o < if (B) S1 else S2,Sigma > => < if (B') S1 else S2,Sigma > if o < B,Sigma > => < B',Sigma  > .
                 match conf {
                     Configuration::StmtConf(s, sigm) => match *s {
-                        Stmt::IfThenElse(b_ptr, _s1_ptr, _s2_ptr) => {
+                        Stmt::IfThenElse(b_ptr, _s1_ptr, _s2_ptr, _) => {
                             Configuration::BExpConf(b_ptr, sigm)
                         }
                         _ => return None,
@@ -701,6 +1041,73 @@ impl Rule {
             Rule::RewriteConditionalFalse => Configuration::Dummy,
             // rl o < while (B) S,Sigma > => < if (B) {S while (B) S} else {},Sigma > .
             Rule::RewriteLoop => Configuration::Dummy,
+            // rl o < S1 [] S2,Sigma > => < S1,Sigma > .
+            Rule::RewriteChoiceLeft => Configuration::Dummy,
+            // rl o < S1 [] S2,Sigma > => < S2,Sigma > .
+            Rule::RewriteChoiceRight => Configuration::Dummy,
+            // crl o < throw A ;,Sigma > => < throw A' ;,Sigma > if o < A,Sigma > => < A',Sigma > .
+            Rule::RewriteThrowArith => match conf {
+                Configuration::StmtConf(x, sigma) => match *x {
+                    Stmt::Throw(a, _) => Configuration::AExpConf(a, sigma),
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // rl o < throw I ; S2,Sigma > => < throw I ;,Sigma > .
+            Rule::RewriteSequenceThrow => Configuration::Dummy,
+            // crl o < try S1 catch (X) S2,Sigma > => < try S1' catch (X) S2,Sigma' > if o < S1,Sigma > => < S1',Sigma' > .
+            Rule::RewriteTry => match conf {
+                Configuration::StmtConf(x, sigma) => match *x {
+                    Stmt::TryCatch(b, _x, _s2, _) => match *b {
+                        Block::BlockStmt(s1, _) => Configuration::StmtConf(s1, sigma),
+                        Block::EmptyBlock => return None,
+                    },
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // rl o < try {} catch (X) S2,Sigma > => < {},Sigma > .
+            Rule::RewriteTryEmpty => Configuration::Dummy,
+            // crl o < try throw I ; catch (X) S2,Sigma > => < S2,Sigma[I / X] > if Sigma(X) =/=Bool undefined .
+            Rule::RewriteTryCatch => Configuration::Dummy,
+            // rl o < throw I ;,Sigma > => < uncaught I,Sigma > .
+            Rule::RewriteUncaught => Configuration::Dummy,
+            // crl o < * A,Sigma > => < * A',Sigma > if o < A,Sigma > => < A',Sigma > .
+            Rule::RewriteDerefArith => match conf {
+                Configuration::AExpConf(x, sigma) => match *x {
+                    AExp::Deref(a, _) => Configuration::AExpConf(a, sigma),
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // crl o < * L,Sigma > => < Sigma(L),Sigma > if Sigma(L) =/=Bool undefined .
+            Rule::RewriteDeref => Configuration::Dummy,
+            // crl o < X = new ;,Sigma > => < {},Sigma[L |-> 0][L / X] > if L := fresh(Sigma) /\ Sigma(X) =/=Bool undefined .
+            Rule::RewriteNew => Configuration::Dummy,
+            // crl o < * A1 = A2 ;,Sigma > => < * A1' = A2 ;,Sigma > if o < A1,Sigma > => < A1',Sigma > .
+            Rule::RewriteAssignDerefLeft => match conf {
+                Configuration::StmtConf(x, sigma) => match *x {
+                    Stmt::AssignDeref(a1, _a2, _) => Configuration::AExpConf(a1, sigma),
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // crl o < * A1 = A2 ;,Sigma > => < * A1 = A2' ;,Sigma > if o < A2,Sigma > => < A2',Sigma > .
+            Rule::RewriteAssignDerefRight => match conf {
+                Configuration::StmtConf(x, sigma) => match *x {
+                    Stmt::AssignDeref(_a1, a2, _) => Configuration::AExpConf(a2, sigma),
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // crl o < * L = I ;,Sigma > => < {},Sigma[I / L] > if Sigma(L) =/=Bool undefined .
+            Rule::RewriteAssignDerefInt => Configuration::Dummy,
+            // rl o < if (B) S,Sigma > => < if (B) S else {},Sigma > .
+            Rule::RewriteIfThen => Configuration::Dummy,
+            // rl o < do S while (B) ;,Sigma > => < {S while (B) S},Sigma > .
+            Rule::RewriteDoWhile => Configuration::Dummy,
+            // rl o < for (S1 B ; S2) S,Sigma > => < {S1 while (B) {S S2}},Sigma > .
+            Rule::RewriteFor => Configuration::Dummy,
             Rule::NoOp => return None,
         };
         Some(ret)
@@ -715,15 +1122,10 @@ impl Rule {
 
                 match bottom {
                     Configuration::AExpConf(x, sigma) => match *x {
-                        AExp::Id(x) => {
-                            let State(v) = sigma.clone();
-                            match v.iter().find(|(k, _v)| k == &x) {
-                                Some((_k, v)) => {
-                                    Configuration::AExpConf(Box::new(AExp::Int(*v)), sigma)
-                                }
-                                _ => return None,
-                            }
-                        }
+                        AExp::Id(x, span) => match sigma.lookup(&x) {
+                            Some(v) => Configuration::AExpConf(Box::new(AExp::Int(v, span)), sigma),
+                            None => return None,
+                        },
                         _ => return None,
                     },
                     _ => return None,
@@ -737,9 +1139,10 @@ impl Rule {
                 };
                 match bottom {
                     Configuration::AExpConf(x, sigma) => match *x {
-                        AExp::Plus(_box1, box2) => {
-                            Configuration::AExpConf(Box::new(AExp::Plus(new_arith, box2)), sigma)
-                        }
+                        AExp::Plus(_box1, box2, span) => Configuration::AExpConf(
+                            Box::new(AExp::Plus(new_arith, box2, span)),
+                            sigma,
+                        ),
                         _ => return None,
                     },
                     _ => return None,
@@ -754,9 +1157,10 @@ impl Rule {
                 };
                 match bottom {
                     Configuration::AExpConf(x, sigma) => match *x {
-                        AExp::Plus(box1, _box2) => {
-                            Configuration::AExpConf(Box::new(AExp::Plus(box1, new_arith)), sigma)
-                        }
+                        AExp::Plus(box1, _box2, span) => Configuration::AExpConf(
+                            Box::new(AExp::Plus(box1, new_arith, span)),
+                            sigma,
+                        ),
                         _ => return None,
                     },
                     _ => return None,
@@ -765,11 +1169,13 @@ impl Rule {
 
             Rule::RewritePlus => match bottom {
                 Configuration::AExpConf(x, sigma) => match *x {
-                    AExp::Plus(box1, box2) => match *box1 {
-                        AExp::Int(n1) => match *box2 {
-                            AExp::Int(n2) => {
-                                Configuration::AExpConf(Box::new(AExp::Int(n1 + n2)), sigma)
-                            }
+                    AExp::Plus(box1, box2, span) => match *box1 {
+                        AExp::Int(n1, _) => match *box2 {
+                            // stuck on overflow, like a division by 0
+                            AExp::Int(n2, _) => Configuration::AExpConf(
+                                Box::new(AExp::Int(n1.checked_add(n2)?, span)),
+                                sigma,
+                            ),
                             _ => return None,
                         },
                         _ => return None,
@@ -786,9 +1192,10 @@ impl Rule {
                 };
                 match bottom {
                     Configuration::AExpConf(x, sigma) => match *x {
-                        AExp::Divide(box1, _box2) => {
-                            Configuration::AExpConf(Box::new(AExp::Divide(box1, new_arith)), sigma)
-                        }
+                        AExp::Divide(_box1, box2, span) => Configuration::AExpConf(
+                            Box::new(AExp::Divide(new_arith, box2, span)),
+                            sigma,
+                        ),
                         _ => return None,
                     },
                     _ => return None,
@@ -802,22 +1209,25 @@ impl Rule {
                 };
                 match bottom {
                     Configuration::AExpConf(x, sigma) => match *x {
-                        AExp::Divide(_box1, box2) => {
-                            Configuration::AExpConf(Box::new(AExp::Divide(box2, new_arith)), sigma)
-                        }
+                        AExp::Divide(box1, _box2, span) => Configuration::AExpConf(
+                            Box::new(AExp::Divide(box1, new_arith, span)),
+                            sigma,
+                        ),
                         _ => return None,
                     },
                     _ => return None,
                 }
             }
             Rule::RewriteDivide => match bottom {
-                Configuration::AExpConf(x, sigma) => match *x.clone() {
-                    AExp::Divide(box1, box2) => match *box1 {
-                        AExp::Int(n1) => match *box2 {
-                            AExp::Int(0) => Configuration::AExpConf(x, sigma),
-                            AExp::Int(n2) => {
-                                Configuration::AExpConf(Box::new(AExp::Int(n1 / n2)), sigma)
-                            }
+                Configuration::AExpConf(x, sigma) => match *x {
+                    AExp::Divide(box1, box2, span) => match *box1 {
+                        AExp::Int(n1, _) => match *box2 {
+                            AExp::Int(n2, _) => match n1.checked_div(n2) {
+                                Some(i) => {
+                                    Configuration::AExpConf(Box::new(AExp::Int(i, span)), sigma)
+                                }
+                                None => return None,
+                            },
                             _ => return None,
                         },
                         _ => return None,
@@ -835,8 +1245,8 @@ impl Rule {
                 };
                 match bottom {
                     Configuration::BExpConf(x, sigma) => match *x {
-                        BExp::LessThanEq(_box1, box2) => Configuration::BExpConf(
-                            Box::new(BExp::LessThanEq(new_arith, box2)),
+                        BExp::LessThanEq(_box1, box2, span) => Configuration::BExpConf(
+                            Box::new(BExp::LessThanEq(new_arith, box2, span)),
                             sigma,
                         ),
                         _ => return None,
@@ -852,8 +1262,8 @@ impl Rule {
                 };
                 match bottom {
                     Configuration::BExpConf(x, sigma) => match *x {
-                        BExp::LessThanEq(box1, _box2) => Configuration::BExpConf(
-                            Box::new(BExp::LessThanEq(box1, new_arith)),
+                        BExp::LessThanEq(box1, _box2, span) => Configuration::BExpConf(
+                            Box::new(BExp::LessThanEq(box1, new_arith, span)),
                             sigma,
                         ),
                         _ => return None,
@@ -864,13 +1274,19 @@ impl Rule {
 
             Rule::RewriteLessThan => match bottom {
                 Configuration::BExpConf(_x, _sigma) => match *_x {
-                    BExp::LessThanEq(box1, box2) => match *box1 {
-                        AExp::Int(i1) => match *box2 {
-                            AExp::Int(i2) => {
-                                if i2 > i1 {
-                                    Configuration::BExpConf(Box::new(BExp::Bool(true)), _sigma)
+                    BExp::LessThanEq(box1, box2, span) => match *box1 {
+                        AExp::Int(i1, _) => match *box2 {
+                            AExp::Int(i2, _) => {
+                                if i1 <= i2 {
+                                    Configuration::BExpConf(
+                                        Box::new(BExp::Bool(true, span)),
+                                        _sigma,
+                                    )
                                 } else {
-                                    Configuration::BExpConf(Box::new(BExp::Bool(false)), _sigma)
+                                    Configuration::BExpConf(
+                                        Box::new(BExp::Bool(false, span)),
+                                        _sigma,
+                                    )
                                 }
                             }
                             _ => return None,
@@ -890,7 +1306,9 @@ impl Rule {
                 };
                 match bottom {
                     Configuration::BExpConf(x, sigma) => match *x {
-                        BExp::Negation(_box1) => Configuration::BExpConf(new_bool, sigma),
+                        BExp::Negation(_box1, span) => {
+                            Configuration::BExpConf(Box::new(BExp::Negation(new_bool, span)), sigma)
+                        }
                         _ => return None,
                     },
                     _ => return None,
@@ -900,9 +1318,9 @@ impl Rule {
             // rl o < ! true,Sigma > => < false,Sigma > .
             Rule::RewriteNegateTrue => match bottom {
                 Configuration::BExpConf(x, sigma) => match *x {
-                    BExp::Negation(box1) => match *box1 {
-                        BExp::Bool(true) => {
-                            Configuration::BExpConf(Box::new(BExp::Bool(false)), sigma)
+                    BExp::Negation(box1, span) => match *box1 {
+                        BExp::Bool(true, _) => {
+                            Configuration::BExpConf(Box::new(BExp::Bool(false, span)), sigma)
                         }
                         _ => return None,
                     },
@@ -914,9 +1332,9 @@ impl Rule {
             // rl o < ! false,Sigma > => < true,Sigma > .
             Rule::RewriteNegateFalse => match bottom {
                 Configuration::BExpConf(x, sigma) => match *x {
-                    BExp::Negation(box1) => match *box1 {
-                        BExp::Bool(false) => {
-                            Configuration::BExpConf(Box::new(BExp::Bool(true)), sigma)
+                    BExp::Negation(box1, span) => match *box1 {
+                        BExp::Bool(false, _) => {
+                            Configuration::BExpConf(Box::new(BExp::Bool(true, span)), sigma)
                         }
                         _ => return None,
                     },
@@ -929,8 +1347,8 @@ impl Rule {
                 // rl o < {S},Sigma > => < S,Sigma > .
                 match bottom {
                     Configuration::StmtConf(x, sigma) => match *x {
-                        Stmt::StmtBlock(s) => match *s {
-                            Block::BlockStmt(s) => Configuration::StmtConf(s, sigma),
+                        Stmt::StmtBlock(s, _) => match *s {
+                            Block::BlockStmt(s, _) => Configuration::StmtConf(s, sigma),
                             _ => return None,
                         },
 
@@ -946,10 +1364,11 @@ impl Rule {
 
                 match bottom {
                     Configuration::StmtConf(x, _sigma) => match *x {
-                        Stmt::Assign(x, _a) => match top {
-                            Configuration::AExpConf(a_prime, sigma) => {
-                                Configuration::StmtConf(Stmt::Assign(x, a_prime).into(), sigma)
-                            }
+                        Stmt::Assign(x, _a, span) => match top {
+                            Configuration::AExpConf(a_prime, sigma) => Configuration::StmtConf(
+                                Stmt::Assign(x, a_prime, span).into(),
+                                sigma,
+                            ),
                             _ => return None,
                         },
 
@@ -964,10 +1383,10 @@ impl Rule {
                 // "crl o < X = I ;,Sigma > => < {},Sigma[I / X] > if Sigma(X) =/=Bool undefined ."
                 match bottom {
                     Configuration::StmtConf(x, sigma) => match *x {
-                        Stmt::Assign(x, a) => match *a {
-                            AExp::Int(i) => Configuration::StmtConf(
-                                Stmt::StmtBlock(Block::EmptyBlock.into()).into(),
-                                sigma.substitute(x, i),
+                        Stmt::Assign(x, a, span) => match (*a, sigma.location(&x)) {
+                            (AExp::Int(i, _), Some(l)) => Configuration::StmtConf(
+                                Stmt::StmtBlock(Block::EmptyBlock.into(), span).into(),
+                                sigma.update(l, i),
                             ),
                             _ => return None,
                         },
@@ -981,10 +1400,10 @@ impl Rule {
                 // "crl o < S1 S2,Sigma > => < S1' S2,Sigma' > if o < S1,Sigma > => < S1',Sigma' > ."
                 match bottom {
                     Configuration::StmtConf(x, _sigma) => match *x {
-                        Stmt::Sequence(_s1, s2) => match top {
+                        Stmt::Sequence(_s1, s2, span) => match top {
                             Configuration::StmtConf(s1_prime, sigma_prime) => {
                                 Configuration::StmtConf(
-                                    Box::new(Stmt::Sequence(s1_prime, s2)),
+                                    Box::new(Stmt::Sequence(s1_prime, s2, span)),
                                     sigma_prime,
                                 )
                             }
@@ -1002,7 +1421,7 @@ impl Rule {
 
                 match bottom {
                     Configuration::PgmConf(p) => match *p {
-                        Pgm::Program(xl, s) => {
+                        Pgm::Program(xl, _levels, s) => {
                             Configuration::StmtConf(s.into(), State::create_state(xl))
                         }
                     },
@@ -1014,8 +1433,8 @@ impl Rule {
                 // rl o < {} S2,Sigma > => < S2,Sigma > .
                 match bottom {
                     Configuration::StmtConf(s, sigma) => match *s {
-                        Stmt::Sequence(s1, s2) => match *s1 {
-                            Stmt::StmtBlock(b) => match *b {
+                        Stmt::Sequence(s1, s2, _) => match *s1 {
+                            Stmt::StmtBlock(b, _) => match *b {
                                 Block::EmptyBlock => Configuration::StmtConf(s2, sigma),
                                 _ => return None,
                             },
@@ -1034,8 +1453,8 @@ impl Rule {
                 };
                 match bottom {
                     Configuration::StmtConf(s, sigm) => match *s {
-                        Stmt::IfThenElse(_b_ptr, s1_ptr, s2_ptr) => Configuration::StmtConf(
-                            Stmt::IfThenElse(new_bool, s1_ptr, s2_ptr).into(),
+                        Stmt::IfThenElse(_b_ptr, s1_ptr, s2_ptr, span) => Configuration::StmtConf(
+                            Stmt::IfThenElse(new_bool, s1_ptr, s2_ptr, span).into(),
                             sigm,
                         ),
                         _ => return None,
@@ -1047,10 +1466,11 @@ impl Rule {
                 // rl o < if (true) S1 else S2,Sigma > => < S1,Sigma > .
                 match bottom {
                     Configuration::StmtConf(s, sigm) => match *s {
-                        Stmt::IfThenElse(b_ptr, s1_ptr, _s2_ptr) => match *b_ptr {
-                            BExp::Bool(true) => {
-                                Configuration::StmtConf(Box::new(Stmt::StmtBlock(s1_ptr)), sigm)
-                            }
+                        Stmt::IfThenElse(b_ptr, s1_ptr, _s2_ptr, span) => match *b_ptr {
+                            BExp::Bool(true, _) => Configuration::StmtConf(
+                                Box::new(Stmt::StmtBlock(s1_ptr, span)),
+                                sigm,
+                            ),
                             _ => return None,
                         },
                         _ => return None,
@@ -1061,9 +1481,9 @@ impl Rule {
             // rl o < if (false) S1 else S2,Sigma > => < S2,Sigma > .
             Rule::RewriteConditionalFalse => match bottom {
                 Configuration::StmtConf(s, sigm) => match *s {
-                    Stmt::IfThenElse(b_ptr, _s1_ptr, s2_ptr) => match *b_ptr {
-                        BExp::Bool(false) => {
-                            Configuration::StmtConf(Stmt::StmtBlock(s2_ptr).into(), sigm)
+                    Stmt::IfThenElse(b_ptr, _s1_ptr, s2_ptr, span) => match *b_ptr {
+                        BExp::Bool(false, _) => {
+                            Configuration::StmtConf(Stmt::StmtBlock(s2_ptr, span).into(), sigm)
                         }
                         _ => return None,
                     },
@@ -1074,18 +1494,307 @@ impl Rule {
             // rl o < while (B) S,Sigma > => < if (B) {S while (B) S} else {},Sigma > .
             Rule::RewriteLoop => match bottom {
                 Configuration::StmtConf(s, sigm) => match *s {
-                    Stmt::While(b_ptr, s_ptr) => Configuration::StmtConf(
+                    Stmt::While(b_ptr, s_ptr, span) => Configuration::StmtConf(
                         Stmt::IfThenElse(
                             b_ptr.clone(),
                             Block::BlockStmt(
                                 Stmt::Sequence(
-                                    Stmt::StmtBlock(s_ptr.clone()).into(),
-                                    Stmt::While(b_ptr, s_ptr).into(),
+                                    Stmt::StmtBlock(s_ptr.clone(), span).into(),
+                                    Stmt::While(b_ptr, s_ptr, span).into(),
+                                    span,
                                 )
                                 .into(),
+                                span,
                             )
                             .into(),
                             Block::EmptyBlock.into(),
+                            span,
+                        )
+                        .into(),
+                        sigm,
+                    ),
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // rl o < S1 [] S2,Sigma > => < S1,Sigma > .
+            Rule::RewriteChoiceLeft => match bottom {
+                Configuration::StmtConf(s, sigm) => match *s {
+                    Stmt::Choice(s1_ptr, _s2_ptr, span) => {
+                        Configuration::StmtConf(Stmt::StmtBlock(s1_ptr, span).into(), sigm)
+                    }
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // rl o < S1 [] S2,Sigma > => < S2,Sigma > .
+            Rule::RewriteChoiceRight => match bottom {
+                Configuration::StmtConf(s, sigm) => match *s {
+                    Stmt::Choice(_s1_ptr, s2_ptr, span) => {
+                        Configuration::StmtConf(Stmt::StmtBlock(s2_ptr, span).into(), sigm)
+                    }
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // crl o < throw A ;,Sigma > => < throw A' ;,Sigma > if o < A,Sigma > => < A',Sigma > .
+            Rule::RewriteThrowArith => match bottom {
+                Configuration::StmtConf(x, _sigma) => match *x {
+                    Stmt::Throw(_a, span) => match top {
+                        Configuration::AExpConf(a_prime, sigma) => {
+                            Configuration::StmtConf(Stmt::Throw(a_prime, span).into(), sigma)
+                        }
+                        _ => return None,
+                    },
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // rl o < throw I ; S2,Sigma > => < throw I ;,Sigma > .
+            Rule::RewriteSequenceThrow => match bottom {
+                Configuration::StmtConf(s, sigma) => match *s {
+                    Stmt::Sequence(s1, _s2, _) => match *s1 {
+                        Stmt::Throw(a, throw_span) => match *a {
+                            AExp::Int(i, int_span) => Configuration::StmtConf(
+                                Stmt::Throw(AExp::Int(i, int_span).into(), throw_span).into(),
+                                sigma,
+                            ),
+                            _ => return None,
+                        },
+                        _ => return None,
+                    },
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // crl o < try S1 catch (X) S2,Sigma > => < try S1' catch (X) S2,Sigma' > if o < S1,Sigma > => < S1',Sigma' > .
+            Rule::RewriteTry => match bottom {
+                Configuration::StmtConf(s, _sigma) => match *s {
+                    Stmt::TryCatch(_s1, x, s2, span) => match top {
+                        Configuration::StmtConf(s1_prime, sigma_prime) => {
+                            // keep the body a block so that {} is recognised by RewriteTryEmpty
+                            let s1_prime = match *s1_prime {
+                                Stmt::StmtBlock(b, _) => b,
+                                s1_prime => {
+                                    let body = s1_prime.span();
+                                    Block::BlockStmt(s1_prime.into(), body).into()
+                                }
+                            };
+                            Configuration::StmtConf(
+                                Stmt::TryCatch(s1_prime, x, s2, span).into(),
+                                sigma_prime,
+                            )
+                        }
+                        _ => return None,
+                    },
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // rl o < try {} catch (X) S2,Sigma > => < {},Sigma > .
+            Rule::RewriteTryEmpty => match bottom {
+                Configuration::StmtConf(s, sigma) => match *s {
+                    Stmt::TryCatch(s1, _x, _s2, span) => match *s1 {
+                        Block::EmptyBlock => Configuration::StmtConf(
+                            Stmt::StmtBlock(Block::EmptyBlock.into(), span).into(),
+                            sigma,
+                        ),
+                        _ => return None,
+                    },
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // crl o < try throw I ; catch (X) S2,Sigma > => < S2,Sigma[I / X] > if Sigma(X) =/=Bool undefined .
+            Rule::RewriteTryCatch => match bottom {
+                Configuration::StmtConf(s, sigma) => match *s {
+                    Stmt::TryCatch(s1, x, s2, span) => match *s1 {
+                        Block::BlockStmt(s1, _) => match *s1 {
+                            Stmt::Throw(a, _) => match *a {
+                                AExp::Int(i, _) => match sigma.location(&x) {
+                                    Some(l) => Configuration::StmtConf(
+                                        Stmt::StmtBlock(s2, span).into(),
+                                        sigma.update(l, i),
+                                    ),
+                                    None => return None,
+                                },
+                                _ => return None,
+                            },
+                            _ => return None,
+                        },
+                        _ => return None,
+                    },
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // rl o < throw I ;,Sigma > => < uncaught I,Sigma > .
+            Rule::RewriteUncaught => match bottom {
+                Configuration::StmtConf(s, sigma) => match *s {
+                    Stmt::Throw(a, _) => match *a {
+                        AExp::Int(i, _) => Configuration::UncaughtConf(i, sigma),
+                        _ => return None,
+                    },
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // crl o < * A,Sigma > => < * A',Sigma > if o < A,Sigma > => < A',Sigma > .
+            Rule::RewriteDerefArith => {
+                let new_arith = match top {
+                    Configuration::AExpConf(b, _sigma) => b,
+                    _ => return None,
+                };
+                match bottom {
+                    Configuration::AExpConf(x, sigma) => match *x {
+                        AExp::Deref(_a, span) => {
+                            Configuration::AExpConf(Box::new(AExp::Deref(new_arith, span)), sigma)
+                        }
+                        _ => return None,
+                    },
+                    _ => return None,
+                }
+            }
+            // crl o < * L,Sigma > => < Sigma(L),Sigma > if Sigma(L) =/=Bool undefined .
+            Rule::RewriteDeref => match bottom {
+                Configuration::AExpConf(x, sigma) => match *x {
+                    AExp::Deref(a, span) => match *a {
+                        AExp::Int(l, _) => match sigma.read(l) {
+                            Some(v) => Configuration::AExpConf(Box::new(AExp::Int(v, span)), sigma),
+                            None => return None,
+                        },
+                        _ => return None,
+                    },
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // crl o < X = new ;,Sigma > => < {},Sigma[L |-> 0][L / X] > if L := fresh(Sigma) /\ Sigma(X) =/=Bool undefined .
+            Rule::RewriteNew => match bottom {
+                Configuration::StmtConf(s, sigma) => match *s {
+                    Stmt::New(x, span) => match sigma.location(&x) {
+                        Some(lx) => {
+                            let (l, sigma) = sigma.alloc();
+                            Configuration::StmtConf(
+                                Stmt::StmtBlock(Block::EmptyBlock.into(), span).into(),
+                                sigma.update(lx, l),
+                            )
+                        }
+                        None => return None,
+                    },
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // crl o < * A1 = A2 ;,Sigma > => < * A1' = A2 ;,Sigma > if o < A1,Sigma > => < A1',Sigma > .
+            Rule::RewriteAssignDerefLeft => match bottom {
+                Configuration::StmtConf(x, _sigma) => match *x {
+                    Stmt::AssignDeref(_a1, a2, span) => match top {
+                        Configuration::AExpConf(a_prime, sigma) => Configuration::StmtConf(
+                            Stmt::AssignDeref(a_prime, a2, span).into(),
+                            sigma,
+                        ),
+                        _ => return None,
+                    },
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // crl o < * A1 = A2 ;,Sigma > => < * A1 = A2' ;,Sigma > if o < A2,Sigma > => < A2',Sigma > .
+            Rule::RewriteAssignDerefRight => match bottom {
+                Configuration::StmtConf(x, _sigma) => match *x {
+                    Stmt::AssignDeref(a1, _a2, span) => match top {
+                        Configuration::AExpConf(a_prime, sigma) => Configuration::StmtConf(
+                            Stmt::AssignDeref(a1, a_prime, span).into(),
+                            sigma,
+                        ),
+                        _ => return None,
+                    },
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // crl o < * L = I ;,Sigma > => < {},Sigma[I / L] > if Sigma(L) =/=Bool undefined .
+            Rule::RewriteAssignDerefInt => match bottom {
+                Configuration::StmtConf(x, sigma) => match *x {
+                    Stmt::AssignDeref(a1, a2, span) => match (*a1, *a2) {
+                        (AExp::Int(l, _), AExp::Int(i, _)) if sigma.read(l).is_some() => {
+                            Configuration::StmtConf(
+                                Stmt::StmtBlock(Block::EmptyBlock.into(), span).into(),
+                                sigma.update(l, i),
+                            )
+                        }
+                        _ => return None,
+                    },
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // rl o < if (B) S,Sigma > => < if (B) S else {},Sigma > .
+            Rule::RewriteIfThen => match bottom {
+                Configuration::StmtConf(s, sigm) => match *s {
+                    Stmt::IfThen(b_ptr, s_ptr, span) => Configuration::StmtConf(
+                        Stmt::IfThenElse(b_ptr, s_ptr, Block::EmptyBlock.into(), span).into(),
+                        sigm,
+                    ),
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // rl o < do S while (B) ;,Sigma > => < {S while (B) S},Sigma > .
+            Rule::RewriteDoWhile => match bottom {
+                Configuration::StmtConf(s, sigm) => match *s {
+                    Stmt::DoWhile(s_ptr, b_ptr, span) => Configuration::StmtConf(
+                        Stmt::StmtBlock(
+                            Block::BlockStmt(
+                                Stmt::Sequence(
+                                    Stmt::StmtBlock(s_ptr.clone(), span).into(),
+                                    Stmt::While(b_ptr, s_ptr, span).into(),
+                                    span,
+                                )
+                                .into(),
+                                span,
+                            )
+                            .into(),
+                            span,
+                        )
+                        .into(),
+                        sigm,
+                    ),
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // rl o < for (S1 B ; S2) S,Sigma > => < {S1 while (B) {S S2}},Sigma > .
+            Rule::RewriteFor => match bottom {
+                Configuration::StmtConf(s, sigm) => match *s {
+                    Stmt::For(s1_ptr, b_ptr, s2_ptr, s_ptr, span) => Configuration::StmtConf(
+                        Stmt::StmtBlock(
+                            Block::BlockStmt(
+                                Stmt::Sequence(
+                                    s1_ptr,
+                                    Stmt::While(
+                                        b_ptr,
+                                        Block::BlockStmt(
+                                            Stmt::Sequence(
+                                                Stmt::StmtBlock(s_ptr, span).into(),
+                                                s2_ptr,
+                                                span,
+                                            )
+                                            .into(),
+                                            span,
+                                        )
+                                        .into(),
+                                        span,
+                                    )
+                                    .into(),
+                                    span,
+                                )
+                                .into(),
+                                span,
+                            )
+                            .into(),
+                            span,
                         )
                         .into(),
                         sigm,
@@ -1177,3 +1886,158 @@ user selects  rl o < I1 + I2,Sigma > => < I1 +Int I2,Sigma > .
 < x = 4 , (x -> 0) >
 
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the stepper after running a program with the given rules until it stops
+    fn stepped(program: &str, rules: &[Rule]) -> Stack<Rule> {
+        let mut stack = Stack::<Rule>::create_from_string(program.to_string()).unwrap();
+        stack.run(rules, 1000);
+        stack
+    }
+
+    // the values a program finishes with
+    fn run(program: &str, rules: &[Rule]) -> Option<Vec<(String, i32)>> {
+        stepped(program, rules).top().final_state()
+    }
+
+    fn values(bindings: &[(&str, i32)]) -> Option<Vec<(String, i32)>> {
+        Some(bindings.iter().map(|(x, i)| (x.to_string(), *i)).collect())
+    }
+
+    fn without(rule: Rule) -> Vec<Rule> {
+        Rule::list_of_rules()
+            .into_iter()
+            .filter(|r| *r != rule)
+            .collect()
+    }
+
+    #[test]
+    fn test_choice() {
+        let program = "int x; { x = 1; } [] { x = 2; } x = x + 1;";
+        assert_eq!(
+            run(program, &without(Rule::RewriteChoiceRight)),
+            values(&[("x", 2)])
+        );
+        assert_eq!(
+            run(program, &without(Rule::RewriteChoiceLeft)),
+            values(&[("x", 3)])
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let rules = Rule::list_of_rules();
+        // operands stay on their side of a division and a comparison
+        assert_eq!(run("int x; x = 8 / 2;", &rules), values(&[("x", 4)]));
+        assert_eq!(
+            run("int x; x = (4 + 4) / (1 + 1);", &rules),
+            values(&[("x", 4)])
+        );
+        assert_eq!(
+            run("int x; if (1 <= 2) { x = 1; } else { x = 2; }", &rules),
+            values(&[("x", 1)])
+        );
+        assert_eq!(
+            run("int x; if (2 <= 1) { x = 1; } else { x = 2; }", &rules),
+            values(&[("x", 2)])
+        );
+        // a negation is kept around its reduced operand before it is evaluated
+        assert_eq!(
+            run(
+                "int x; if (!(1 <= 0 + 2)) { x = 1; } else { x = 2; }",
+                &rules
+            ),
+            values(&[("x", 2)])
+        );
+        assert_eq!(
+            run("int x; if (!true) { x = 1; } else { x = 2; }", &rules),
+            values(&[("x", 2)])
+        );
+        // division by zero is stuck
+        assert_eq!(run("int x; x = 1 / 0;", &rules), None);
+        // and so is an addition that overflows
+        assert_eq!(run("int x; x = 2147483647 + 1;", &rules), None);
+        assert_eq!(
+            run("int x; x = 2147483646 + 1;", &rules),
+            values(&[("x", 2147483647)])
+        );
+    }
+
+    #[test]
+    fn test_exceptions() {
+        let rules = Rule::list_of_rules();
+        // the rest of the sequence after a throw is skipped and the handler binds the value
+        assert_eq!(
+            run(
+                "int x, e; try { x = 1; throw x + 1; x = 5; } catch (e) { x = e + 1; }",
+                &rules
+            ),
+            values(&[("x", 3), ("e", 2)])
+        );
+        assert_eq!(
+            run("int x, e; try { x = 1; } catch (e) { x = 5; }", &rules),
+            values(&[("x", 1), ("e", 0)])
+        );
+        let stack = stepped("int x; x = 1; throw x + 1; x = 5;", &rules);
+        match stack.top() {
+            Configuration::UncaughtConf(2, sigma) => {
+                assert_eq!(sigma.bindings(), vec![("x".to_string(), 1)])
+            }
+            conf => panic!("{} should be an uncaught exception", conf),
+        }
+    }
+
+    #[test]
+    fn test_pointers() {
+        let state = run(
+            "int p, q, x; p = new; q = p; *q = 5; *p = *p + 1; x = *q;",
+            &Rule::list_of_rules(),
+        )
+        .unwrap();
+        let value = |y: &str| state.iter().find(|(x, _i)| x == y).unwrap().1;
+        // the cell is fresh, past the variables, and shared by both names
+        assert_eq!(value("p"), value("q"));
+        assert!(value("p") >= 3);
+        assert_eq!(value("x"), 6);
+        // a dereference of a location that was never allocated is stuck
+        let stack = stepped("int x; x = *7;", &Rule::list_of_rules());
+        assert_eq!(stack.top().final_state(), None);
+    }
+
+    #[test]
+    fn test_sugar() {
+        let rules = Rule::list_of_rules();
+        let expected = values(&[("x", 4), ("i", 3)]);
+        let by_hand = "int x, i; x = 1; i = 0; while (i <= 2) { x = x + 1; i = i + 1; }";
+        assert_eq!(run(by_hand, &rules), expected);
+        assert_eq!(
+            run(
+                "int x, i; x = 1; for (i = 0; i <= 2; i = i + 1) { x = x + 1; }",
+                &rules
+            ),
+            expected
+        );
+        assert_eq!(
+            run(
+                "int x, i; x = 1; i = 0; do { x = x + 1; i = i + 1; } while (i <= 2);",
+                &rules
+            ),
+            expected
+        );
+        // the body of a do-while runs once even when the condition is false
+        assert_eq!(
+            run("int x, i; i = 5; do { x = x + 1; } while (i <= 2);", &rules),
+            values(&[("x", 1), ("i", 5)])
+        );
+        assert_eq!(
+            run(
+                "int x, i; if (x <= 0) { x = 4; } if (5 <= x) { x = 0; } i = 3;",
+                &rules
+            ),
+            expected
+        );
+    }
+}
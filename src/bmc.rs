@@ -0,0 +1,566 @@
+use std::fmt;
+
+use crate::ast::AExp;
+use crate::ast::BExp;
+use crate::ast::Block;
+use crate::ast::Pgm;
+use crate::ast::Rule;
+use crate::ast::Span;
+use crate::ast::Stmt;
+use crate::equiv::Bindings;
+use crate::sat::Lit;
+use crate::sat::Solver;
+use crate::stack::Stack;
+use crate::vm::show_memory;
+
+// the width of i32, so that the bit-blasted runs are exactly those of the semantics,
+// overflow and division by 0 getting stuck included
+pub const WIDTH: usize = 32;
+// the default number of times each loop is unrolled
+pub const DEPTH: usize = 4;
+
+// a fixed-width integer in two's complement, least significant bit first
+type Bv = Vec<Lit>;
+
+// the runs reaching a point of the program: those still running normally,
+// and those propagating an exception, which are mutually exclusive
+#[derive(Clone, Debug)]
+struct Sym {
+    vars: Vec<(String, Bv)>,
+    normal: Lit,
+    raised: Lit,
+    value: Bv,
+}
+
+impl Sym {
+    fn lookup(&self, x: &str) -> Option<&Bv> {
+        self.vars.iter().find(|(y, _bv)| y == x).map(|(_y, bv)| bv)
+    }
+}
+
+// builds the circuit as clauses of the solver, by the Tseitin encoding of each gate
+struct Blaster {
+    solver: Solver,
+    t: Lit,
+}
+
+impl Blaster {
+    fn new() -> Blaster {
+        let mut solver = Solver::new();
+        let t = solver.new_var();
+        solver.add_clause(&[t]);
+        Blaster { solver, t }
+    }
+    fn constant(&self, b: bool) -> Lit {
+        if b {
+            self.t
+        } else {
+            !self.t
+        }
+    }
+    fn and(&mut self, a: Lit, b: Lit) -> Lit {
+        let f = !self.t;
+        match () {
+            _ if a == f || b == f || a == !b => f,
+            _ if a == self.t || a == b => b,
+            _ if b == self.t => a,
+            _ => {
+                let g = self.solver.new_var();
+                self.solver.add_clause(&[!g, a]);
+                self.solver.add_clause(&[!g, b]);
+                self.solver.add_clause(&[g, !a, !b]);
+                g
+            }
+        }
+    }
+    fn or(&mut self, a: Lit, b: Lit) -> Lit {
+        !self.and(!a, !b)
+    }
+    fn xor(&mut self, a: Lit, b: Lit) -> Lit {
+        match () {
+            _ if a == b => !self.t,
+            _ if a == !b => self.t,
+            _ if a == !self.t => b,
+            _ if a == self.t => !b,
+            _ if b == !self.t => a,
+            _ if b == self.t => !a,
+            _ => {
+                let g = self.solver.new_var();
+                self.solver.add_clause(&[!g, a, b]);
+                self.solver.add_clause(&[!g, !a, !b]);
+                self.solver.add_clause(&[g, !a, b]);
+                self.solver.add_clause(&[g, a, !b]);
+                g
+            }
+        }
+    }
+    fn ite(&mut self, c: Lit, a: Lit, b: Lit) -> Lit {
+        match () {
+            _ if c == self.t || a == b => a,
+            _ if c == !self.t => b,
+            _ => {
+                let g = self.solver.new_var();
+                self.solver.add_clause(&[!c, !a, g]);
+                self.solver.add_clause(&[!c, a, !g]);
+                self.solver.add_clause(&[c, !b, g]);
+                self.solver.add_clause(&[c, b, !g]);
+                g
+            }
+        }
+    }
+    fn ite_bv(&mut self, c: Lit, a: &[Lit], b: &[Lit]) -> Bv {
+        a.iter().zip(b).map(|(x, y)| self.ite(c, *x, *y)).collect()
+    }
+    fn int(&self, i: i32) -> Bv {
+        (0..WIDTH)
+            .map(|k| self.constant((i >> k) & 1 == 1))
+            .collect()
+    }
+    fn fresh(&mut self) -> Bv {
+        (0..WIDTH).map(|_k| self.solver.new_var()).collect()
+    }
+    // ripple-carry addition, with the carry out of the most significant bit
+    fn adder(&mut self, a: &[Lit], b: &[Lit], carry: Lit) -> (Bv, Lit) {
+        let mut carry = carry;
+        let mut sum = vec![];
+        for (x, y) in a.iter().zip(b) {
+            let half = self.xor(*x, *y);
+            sum.push(self.xor(half, carry));
+            let both = self.and(*x, *y);
+            let propagated = self.and(half, carry);
+            carry = self.or(both, propagated);
+        }
+        (sum, carry)
+    }
+    fn negate(&mut self, a: &[Lit]) -> Bv {
+        let inverted = a.iter().map(|x| !*x).collect::<Bv>();
+        let zero = vec![!self.t; a.len()];
+        self.adder(&inverted, &zero, self.t).0
+    }
+    // the sum and whether it overflows
+    fn add(&mut self, a: &[Lit], b: &[Lit]) -> (Bv, Lit) {
+        let (sum, _carry) = self.adder(a, b, !self.t);
+        let n = a.len() - 1;
+        let same_sign = !self.xor(a[n], b[n]);
+        let flipped = self.xor(sum[n], a[n]);
+        let overflow = self.and(same_sign, flipped);
+        (sum, overflow)
+    }
+    fn unsigned_less(&mut self, a: &[Lit], b: &[Lit]) -> Lit {
+        let mut less = !self.t;
+        for (x, y) in a.iter().zip(b) {
+            let smaller = self.and(!*x, *y);
+            let equal = !self.xor(*x, *y);
+            let before = self.and(equal, less);
+            less = self.or(smaller, before);
+        }
+        less
+    }
+    // a <= b on signed integers, which compare as unsigned ones with their sign bit flipped
+    fn less_eq(&mut self, a: &[Lit], b: &[Lit]) -> Lit {
+        let flip = |v: &[Lit]| {
+            let mut v = v.to_vec();
+            let n = v.len() - 1;
+            v[n] = !v[n];
+            v
+        };
+        !self.unsigned_less(&flip(b), &flip(a))
+    }
+    fn is_zero(&mut self, a: &[Lit]) -> Lit {
+        a.iter().fold(self.t, |zero, x| self.and(zero, !*x))
+    }
+    // division rounding towards 0, by restoring division of the magnitudes one bit wider,
+    // with whether the divisor is 0 or the quotient overflows
+    fn divide(&mut self, a: &[Lit], b: &[Lit]) -> (Bv, Lit) {
+        let n = a.len();
+        let magnitude = |blaster: &mut Blaster, v: &[Lit]| {
+            let mut extended = v.to_vec();
+            extended.push(v[n - 1]);
+            let negated = blaster.negate(&extended);
+            blaster.ite_bv(v[n - 1], &negated, &extended)
+        };
+        let dividend = magnitude(self, a);
+        let divisor = magnitude(self, b);
+        let not_divisor = divisor.iter().map(|x| !*x).collect::<Bv>();
+        let mut remainder = vec![!self.t; n + 1];
+        let mut quotient = vec![!self.t; n + 1];
+        for i in (0..=n).rev() {
+            remainder.pop();
+            remainder.insert(0, dividend[i]);
+            let fits = !self.unsigned_less(&remainder, &divisor);
+            let (difference, _carry) = self.adder(&remainder, &not_divisor, self.t);
+            remainder = self.ite_bv(fits, &difference, &remainder);
+            quotient[i] = fits;
+        }
+        let negative = self.xor(a[n - 1], b[n - 1]);
+        let negated = self.negate(&quotient);
+        let signed = self.ite_bv(negative, &negated, &quotient);
+        let overflow = self.xor(signed[n], signed[n - 1]);
+        let by_zero = self.is_zero(b);
+        let fails = self.or(by_zero, overflow);
+        (signed[..n].to_vec(), fails)
+    }
+    fn value(&self, bv: &[Lit]) -> i32 {
+        bv.iter()
+            .enumerate()
+            .fold(0, |i, (k, x)| i | (i32::from(self.solver.model(*x)) << k))
+    }
+
+    // the value of an expression and whether evaluating it gets stuck
+    fn aexp(&mut self, a: &AExp, sym: &Sym) -> Result<(Bv, Lit), String> {
+        match a {
+            AExp::Int(i, _) => Ok((self.int(*i), !self.t)),
+            AExp::Id(x, _) => match sym.lookup(x) {
+                Some(bv) => Ok((bv.clone(), !self.t)),
+                None => Ok((self.int(0), self.t)),
+            },
+            AExp::Plus(a1, a2, _) | AExp::Divide(a1, a2, _) => {
+                let (v1, f1) = self.aexp(a1, sym)?;
+                let (v2, f2) = self.aexp(a2, sym)?;
+                let (v, f) = match a {
+                    AExp::Plus(..) => self.add(&v1, &v2),
+                    _ => self.divide(&v1, &v2),
+                };
+                let f12 = self.or(f1, f2);
+                Ok((v, self.or(f12, f)))
+            }
+            AExp::Deref(_a, _) => Err("Pointers are not supported by bounded checking".to_string()),
+        }
+    }
+    // there are no rules for &&, so the semantics gets stuck on it
+    fn bexp(&mut self, b: &BExp, sym: &Sym) -> Result<(Lit, Lit), String> {
+        match b {
+            BExp::Bool(b, _) => Ok((self.constant(*b), !self.t)),
+            BExp::LessThanEq(a1, a2, _) => {
+                let (v1, f1) = self.aexp(a1, sym)?;
+                let (v2, f2) = self.aexp(a2, sym)?;
+                Ok((self.less_eq(&v1, &v2), self.or(f1, f2)))
+            }
+            BExp::Negation(b, _) => {
+                let (v, f) = self.bexp(b, sym)?;
+                Ok((!v, f))
+            }
+            BExp::And(_b1, _b2, _) => Ok((!self.t, self.t)),
+        }
+    }
+    // the value of an assertion and whether it is defined, as by BExp::evaluate,
+    // where && has its usual meaning and does not evaluate its right side after false
+    fn assertion(&mut self, b: &BExp, sym: &Sym) -> Result<(Lit, Lit), String> {
+        match b {
+            BExp::And(b1, b2, _) => {
+                let (v1, d1) = self.assertion(b1, sym)?;
+                let (v2, d2) = self.assertion(b2, sym)?;
+                let needed = self.or(!v1, d2);
+                Ok((self.and(v1, v2), self.and(d1, needed)))
+            }
+            BExp::Negation(b, _) => {
+                let (v, d) = self.assertion(b, sym)?;
+                Ok((!v, d))
+            }
+            _ => {
+                let (v, f) = self.bexp(b, sym)?;
+                Ok((v, !f))
+            }
+        }
+    }
+
+    // the runs continuing normally are those where the guard holds
+    fn guard(&mut self, sym: &Sym, guard: Lit) -> Sym {
+        let normal = self.and(sym.normal, guard);
+        Sym {
+            normal,
+            ..sym.clone()
+        }
+    }
+    // the runs of the first branch where c holds and of the second one otherwise
+    fn merge(&mut self, c: Lit, s1: Sym, s2: Sym) -> Sym {
+        let vars = s1
+            .vars
+            .iter()
+            .zip(&s2.vars)
+            .map(|((x, v1), (_x, v2))| (x.clone(), self.ite_bv(c, v1, v2)))
+            .collect();
+        Sym {
+            vars,
+            normal: self.or(s1.normal, s2.normal),
+            raised: self.or(s1.raised, s2.raised),
+            value: self.ite_bv(c, &s1.value, &s2.value),
+        }
+    }
+    fn block(&mut self, b: &Block, sym: Sym, depth: usize) -> Result<Sym, String> {
+        match b {
+            Block::EmptyBlock => Ok(sym),
+            Block::BlockStmt(s, _) => self.stmt(s, sym, depth),
+        }
+    }
+    fn branch(
+        &mut self,
+        c: Lit,
+        b1: &Block,
+        b2: &Block,
+        sym: Sym,
+        depth: usize,
+    ) -> Result<Sym, String> {
+        let then = self.guard(&sym, c);
+        let s1 = self.block(b1, then, depth)?;
+        let otherwise = self.guard(&sym, !c);
+        let s2 = self.block(b2, otherwise, depth)?;
+        Ok(self.merge(c, s1, s2))
+    }
+    // o < while (B) S,Sigma > => < if (B) {S while (B) S} else {},Sigma >, unrolled depth times,
+    // after which the runs that would go round once more are left out
+    fn while_loop(
+        &mut self,
+        b: &BExp,
+        body: &Block,
+        sym: Sym,
+        depth: usize,
+    ) -> Result<Sym, String> {
+        let (c, f) = self.bexp(b, &sym)?;
+        let sym = self.guard(&sym, !f);
+        if depth == 0 {
+            return Ok(self.guard(&sym, !c));
+        }
+        let then = self.guard(&sym, c);
+        let once = self.block(body, then, depth)?;
+        let again = self.while_loop(b, body, once, depth - 1)?;
+        let otherwise = self.guard(&sym, !c);
+        Ok(self.merge(c, again, otherwise))
+    }
+    fn stmt(&mut self, s: &Stmt, sym: Sym, depth: usize) -> Result<Sym, String> {
+        match s {
+            Stmt::StmtBlock(b, _) => self.block(b, sym, depth),
+            Stmt::Sequence(s1, s2, _) => {
+                let sym = self.stmt(s1, sym, depth)?;
+                self.stmt(s2, sym, depth)
+            }
+            Stmt::Assign(x, a, _) => {
+                let (v, f) = self.aexp(a, &sym)?;
+                let declared = self.constant(sym.lookup(x).is_some());
+                let ok = self.and(!f, declared);
+                let mut sym = self.guard(&sym, ok);
+                if let Some(i) = sym.vars.iter().position(|(y, _bv)| y == x) {
+                    let old = sym.vars[i].1.clone();
+                    sym.vars[i].1 = self.ite_bv(sym.normal, &v, &old);
+                }
+                Ok(sym)
+            }
+            Stmt::IfThenElse(b, b1, b2, _) => {
+                let (c, f) = self.bexp(b, &sym)?;
+                let sym = self.guard(&sym, !f);
+                self.branch(c, b1, b2, sym, depth)
+            }
+            Stmt::IfThen(b, b1, _) => {
+                let (c, f) = self.bexp(b, &sym)?;
+                let sym = self.guard(&sym, !f);
+                self.branch(c, b1, &Block::EmptyBlock, sym, depth)
+            }
+            Stmt::Choice(b1, b2, _) => {
+                let c = self.solver.new_var();
+                self.branch(c, b1, b2, sym, depth)
+            }
+            Stmt::While(b, b1, _) => self.while_loop(b, b1, sym, depth),
+            Stmt::DoWhile(b1, b, _) => {
+                let sym = self.block(b1, sym, depth)?;
+                self.while_loop(b, b1, sym, depth)
+            }
+            Stmt::For(s1, b, s2, b1, _) => {
+                let sym = self.stmt(s1, sym, depth)?;
+                let body = Block::BlockStmt(
+                    Box::new(Stmt::Sequence(
+                        Box::new(Stmt::StmtBlock(b1.clone(), Span::default())),
+                        s2.clone(),
+                        Span::default(),
+                    )),
+                    Span::default(),
+                );
+                self.while_loop(b, &body, sym, depth)
+            }
+            Stmt::Throw(a, _) => {
+                let (v, f) = self.aexp(a, &sym)?;
+                let throws = self.and(sym.normal, !f);
+                Ok(Sym {
+                    normal: !self.t,
+                    raised: self.or(sym.raised, throws),
+                    value: self.ite_bv(throws, &v, &sym.value),
+                    vars: sym.vars,
+                })
+            }
+            // the handler runs on the exceptions raised by the try block, with x bound to them
+            Stmt::TryCatch(b1, x, b2, _) => {
+                let inside = Sym {
+                    raised: !self.t,
+                    ..sym.clone()
+                };
+                let tried = self.block(b1, inside, depth)?;
+                let declared = self.constant(sym.lookup(x).is_some());
+                let caught = self.and(tried.raised, declared);
+                let mut handler = Sym {
+                    normal: caught,
+                    raised: !self.t,
+                    ..tried.clone()
+                };
+                if let Some(i) = handler.vars.iter().position(|(y, _bv)| y == x) {
+                    let old = handler.vars[i].1.clone();
+                    handler.vars[i].1 = self.ite_bv(caught, &tried.value, &old);
+                }
+                let handled = self.block(b2, handler, depth)?;
+                Ok(Sym {
+                    normal: self.or(tried.normal, handled.normal),
+                    raised: self.or(sym.raised, handled.raised),
+                    value: self.ite_bv(sym.raised, &sym.value, &handled.value),
+                    vars: handled.vars,
+                })
+            }
+            Stmt::New(_, _) | Stmt::AssignDeref(..) => {
+                Err("Pointers are not supported by bounded checking".to_string())
+            }
+        }
+    }
+}
+
+// an initial state from which the program finishes in a state violating the assertion
+#[derive(Clone, Debug)]
+pub struct Counterexample {
+    pub pgm: Pgm,
+    pub start: Bindings,
+    pub finish: Bindings,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "starting from {}\nthe program finishes with {}",
+            show_memory(&self.start),
+            show_memory(&self.finish)
+        )
+    }
+}
+
+impl Counterexample {
+    // the run in the stepper, from the body of the program with the values found
+    pub fn to_stack(&self) -> Stack<Rule> {
+        Stack::create_from_state(&self.pgm, &self.start)
+    }
+}
+
+// whether some values of its variables make the condition true as BExp::evaluate has it,
+// exactly over all of i32 rather than by searching a range
+pub fn satisfiable(b: &BExp) -> Result<bool, String> {
+    let mut blaster = Blaster::new();
+    let vars = b.vars().into_iter().map(|x| (x, blaster.fresh())).collect();
+    let sym = Sym {
+        vars,
+        normal: blaster.t,
+        raised: !blaster.t,
+        value: blaster.int(0),
+    };
+    let (value, defined) = blaster.assertion(b, &sym)?;
+    let holds = blaster.and(value, defined);
+    blaster.solver.add_clause(&[holds]);
+    Ok(blaster.solver.solve())
+}
+
+// whether every run from any initial state that finishes, taking each loop at most depth
+// times, ends in a state where the assertion holds; the size of the formula is reported too
+pub fn check(
+    pgm: &Pgm,
+    assertion: &BExp,
+    depth: usize,
+) -> Result<(Option<Counterexample>, usize), String> {
+    let Pgm::Program(xl, _levels, s) = pgm;
+    let mut blaster = Blaster::new();
+    let start = xl
+        .iter()
+        .map(|x| (x.clone(), blaster.fresh()))
+        .collect::<Vec<(String, Bv)>>();
+    let sym = Sym {
+        vars: start.clone(),
+        normal: blaster.t,
+        raised: !blaster.t,
+        value: blaster.int(0),
+    };
+    let end = blaster.stmt(s, sym, depth)?;
+    let (value, defined) = blaster.assertion(assertion, &end)?;
+    let holds = blaster.and(value, defined);
+    blaster.solver.add_clause(&[end.normal]);
+    blaster.solver.add_clause(&[!holds]);
+    let vars = blaster.solver.vars();
+    if !blaster.solver.solve() {
+        return Ok((None, vars));
+    }
+    let values = |bvs: &[(String, Bv)]| {
+        bvs.iter()
+            .map(|(x, bv)| (x.clone(), blaster.value(bv)))
+            .collect::<Bindings>()
+    };
+    Ok((
+        Some(Counterexample {
+            pgm: pgm.clone(),
+            start: values(&start),
+            finish: values(&end.vars),
+        }),
+        vars,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::equiv::run;
+    use crate::equiv::Outcome;
+    use crate::parse::parse;
+    use crate::parse::parse_bexp;
+
+    fn bmc(pgm: &str, assertion: &str, depth: usize) -> Option<Counterexample> {
+        let pgm = parse(pgm.to_string()).unwrap();
+        let assertion = parse_bexp(assertion.to_string()).unwrap();
+        let cex = check(&pgm, &assertion, depth).unwrap().0;
+        // a counterexample is a real run of the semantics
+        if let Some(cex) = &cex {
+            match run(&pgm, &cex.start).0 {
+                Outcome::Finished(sigma) => {
+                    assert_ne!(assertion.evaluate(&sigma), Some(true));
+                    let mut finish = cex.finish.clone();
+                    finish.sort();
+                    assert_eq!(sigma, finish);
+                }
+                outcome => panic!("the counterexample {}", outcome),
+            }
+        }
+        cex
+    }
+    #[test]
+    fn test_arithmetic() {
+        // x + 1 gets stuck rather than wrapping around when x is the largest integer
+        assert!(bmc("int x, y; y = x + 1;", "x <= y", 0).is_none());
+        let cex = bmc("int x, y; y = 10 / x;", "y <= 5", 0).unwrap();
+        assert_eq!(cex.start[0], ("x".to_string(), 1));
+        assert!(bmc("int x, y; y = 7 / x;", "!(x <= 0 && 0 <= x)", 0).is_none());
+        assert!(bmc(
+            "int x, y; y = 7 / (x + 1);",
+            "y <= 7 && !(y <= 0 && 0 <= y)",
+            0
+        )
+        .is_some());
+        assert!(bmc("int x, y; y = 7 / x;", "!(8 <= y)", 0).is_none());
+    }
+    #[test]
+    fn test_loops() {
+        let sum = "int n, i, s; i = 0; s = 0; while (i <= n) { s = s + i; i = i + 1; }";
+        assert!(bmc(sum, "n + 1 <= i", 3).is_none());
+        let cex = bmc(sum, "s <= 5", 4).unwrap();
+        assert_eq!(cex.start[0], ("n".to_string(), 3));
+        // the runs going round more often are not checked
+        assert!(bmc(sum, "s <= 5", 3).is_none());
+    }
+    #[test]
+    fn test_exceptions() {
+        let pgm =
+            "int x, e; try { if (x <= 0) { throw 1 / x; } else { } x = 1; } catch (e) { x = e; }";
+        let cex = bmc(pgm, "!(x + 1 <= 0 && 0 <= x + 1)", 0).unwrap();
+        assert_eq!(cex.start[0], ("x".to_string(), -1));
+        assert!(bmc("int x; throw x;", "false", 0).is_none());
+    }
+}
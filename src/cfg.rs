@@ -0,0 +1,409 @@
+use std::collections::VecDeque;
+
+use crate::ast::Block;
+use crate::ast::Configuration;
+use crate::ast::Span;
+use crate::ast::Stmt;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Node {
+    Entry,
+    Exit,
+    // a basic block of straight-line statements
+    Block(Vec<Stmt>),
+    // the statement whose condition decides the outgoing true and false edges
+    Branch(Stmt),
+    // the choice statement at these bytes of the source
+    Choice(Span),
+    // the start of a catch block, which binds the value thrown to its variable
+    Handler(String),
+}
+
+impl Node {
+    pub fn label(&self) -> String {
+        match self {
+            Node::Entry => "entry".to_string(),
+            Node::Exit => "exit".to_string(),
+            Node::Block(ss) => ss
+                .iter()
+                .map(|s| s.to_string().trim().to_string())
+                .collect::<Vec<String>>()
+                .join("\n"),
+            Node::Branch(Stmt::IfThenElse(b, _, _, _)) | Node::Branch(Stmt::IfThen(b, _, _)) => {
+                format!("if {}", b)
+            }
+            Node::Branch(Stmt::While(b, _b1, _)) | Node::Branch(Stmt::DoWhile(_b1, b, _)) => {
+                format!("while {}", b)
+            }
+            Node::Branch(Stmt::For(_s1, b, _s2, _b1, _)) => format!("for {}", b),
+            Node::Branch(s) => s.to_string(),
+            Node::Choice(_) => "[]".to_string(),
+            Node::Handler(x) => format!("catch ({})", x),
+        }
+    }
+}
+
+// node 0 is the entry and node 1 the exit
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cfg {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<(usize, usize, &'static str)>,
+}
+
+// the edges leaving a statement that are still to be connected to whatever follows it
+type Exits = Vec<(usize, &'static str)>;
+
+struct Builder {
+    cfg: Cfg,
+    // the basic block that straight-line statements can still be appended to
+    open: Option<usize>,
+    // the throws of each enclosing try block, innermost last
+    handlers: Vec<Exits>,
+}
+
+impl Builder {
+    fn node(&mut self, n: Node, preds: Exits) -> usize {
+        self.cfg.nodes.push(n);
+        let id = self.cfg.nodes.len() - 1;
+        self.link(preds, id);
+        id
+    }
+    fn link(&mut self, preds: Exits, target: usize) {
+        for (source, label) in preds {
+            if self.open == Some(source) {
+                self.open = None;
+            }
+            self.cfg.edges.push((source, target, label));
+        }
+    }
+    fn simple(&mut self, s: &Stmt, preds: Exits) -> Exits {
+        match (self.open, preds.as_slice()) {
+            (Some(open), [(source, "")]) if open == *source => {
+                if let Node::Block(ss) = &mut self.cfg.nodes[open] {
+                    ss.push(s.clone());
+                }
+                preds
+            }
+            _ => {
+                let id = self.node(Node::Block(vec![s.clone()]), preds);
+                self.open = Some(id);
+                vec![(id, "")]
+            }
+        }
+    }
+    // a loop whose condition is tested before the body, with an update after it for for loops
+    fn test_first(&mut self, s: &Stmt, body: &Block, update: Option<&Stmt>, preds: Exits) -> Exits {
+        let branch = self.node(Node::Branch(s.clone()), preds);
+        let mut exits = self.block(body, vec![(branch, "true")]);
+        if let Some(update) = update {
+            exits = self.stmt(update, exits);
+        }
+        self.link(exits, branch);
+        vec![(branch, "false")]
+    }
+    fn stmt(&mut self, s: &Stmt, preds: Exits) -> Exits {
+        match s {
+            Stmt::StmtBlock(b, _) => self.block(b, preds),
+            Stmt::Sequence(s1, s2, _) => {
+                let exits = self.stmt(s1, preds);
+                self.stmt(s2, exits)
+            }
+            Stmt::Assign(_, _, _) | Stmt::New(_, _) | Stmt::AssignDeref(_, _, _) => {
+                self.simple(s, preds)
+            }
+            Stmt::Throw(_a, _) => {
+                let exits = self.simple(s, preds);
+                self.open = None;
+                let throws = exits.into_iter().map(|(source, _label)| (source, "throw"));
+                match self.handlers.last_mut() {
+                    Some(handler) => handler.extend(throws),
+                    None => {
+                        let throws = throws.collect();
+                        self.link(throws, 1);
+                    }
+                }
+                vec![]
+            }
+            Stmt::TryCatch(b1, x, b2, _) => {
+                self.handlers.push(vec![]);
+                let mut exits = self.block(b1, preds);
+                let throws = self.handlers.pop().expect("pushed above");
+                let handler = self.node(Node::Handler(x.clone()), throws);
+                exits.extend(self.block(b2, vec![(handler, "")]));
+                exits
+            }
+            Stmt::IfThenElse(_b, b1, b2, _) => {
+                let branch = self.node(Node::Branch(s.clone()), preds);
+                let mut exits = self.block(b1, vec![(branch, "true")]);
+                exits.extend(self.block(b2, vec![(branch, "false")]));
+                exits
+            }
+            Stmt::IfThen(_b, b1, _) => {
+                let branch = self.node(Node::Branch(s.clone()), preds);
+                let mut exits = self.block(b1, vec![(branch, "true")]);
+                exits.push((branch, "false"));
+                exits
+            }
+            Stmt::Choice(b1, b2, _) => {
+                let choice = self.node(Node::Choice(s.span()), preds);
+                let mut exits = self.block(b1, vec![(choice, "")]);
+                exits.extend(self.block(b2, vec![(choice, "")]));
+                exits
+            }
+            Stmt::While(_b, body, _) => self.test_first(s, body, None, preds),
+            Stmt::For(init, _b, update, body, _) => {
+                let exits = self.stmt(init, preds);
+                self.test_first(s, body, Some(update), exits)
+            }
+            // the body starts a fresh block so that the back edge can jump to it
+            Stmt::DoWhile(body, _b, _) => {
+                self.open = None;
+                let first = self.cfg.nodes.len();
+                let exits = self.block(body, preds);
+                let branch = self.node(Node::Branch(s.clone()), exits);
+                self.cfg.edges.push((branch, first, "true"));
+                vec![(branch, "false")]
+            }
+        }
+    }
+    fn block(&mut self, b: &Block, preds: Exits) -> Exits {
+        match b {
+            Block::EmptyBlock => preds,
+            Block::BlockStmt(s, _) => self.stmt(s, preds),
+        }
+    }
+}
+
+// the statement that the next step of a residual program works on
+fn head(s: &Stmt) -> &Stmt {
+    match s {
+        Stmt::Sequence(s1, s2, _)
+            if **s1 == Stmt::StmtBlock(Box::new(Block::EmptyBlock), Span::default()) =>
+        {
+            head(s2)
+        }
+        Stmt::Sequence(s1, _s2, _) => head(s1),
+        Stmt::StmtBlock(b, _) | Stmt::TryCatch(b, _, _, _) => match &**b {
+            Block::BlockStmt(s, _) => head(s),
+            Block::EmptyBlock => s,
+        },
+        s => s,
+    }
+}
+
+// undoes the unrolling of a loop, if (B) {S while (B) S} else {} => while (B) S
+fn rolled(s: &Stmt) -> &Stmt {
+    if let Stmt::IfThenElse(_b, b1, b2, _) = s {
+        if let (Block::BlockStmt(seq, _), Block::EmptyBlock) = (&**b1, &**b2) {
+            if let Stmt::Sequence(body, w, _) = &**seq {
+                if let Stmt::While(_b, body_w, _) = &**w {
+                    if **body == Stmt::StmtBlock(body_w.clone(), Span::default()) {
+                        return w;
+                    }
+                }
+            }
+        }
+    }
+    s
+}
+
+// whether a statement of the residual program comes from the statement of the original one,
+// possibly with some of its expressions already evaluated or its loop rewritten
+fn origin(orig: &Stmt, s: &Stmt) -> bool {
+    match (orig, rolled(s)) {
+        (orig, s) if orig == s => true,
+        (Stmt::Assign(x, _a1, _), Stmt::Assign(y, _a2, _)) => x == y,
+        (Stmt::AssignDeref(_, _, _), Stmt::AssignDeref(_, _, _))
+        | (Stmt::Throw(_, _), Stmt::Throw(_, _)) => true,
+        (Stmt::IfThenElse(_b1, t1, e1, _), Stmt::IfThenElse(_b2, t2, e2, _)) => {
+            t1 == t2 && e1 == e2
+        }
+        (Stmt::IfThen(_b1, t1, _), Stmt::IfThen(_b2, t2, _)) => t1 == t2,
+        (Stmt::While(b1, body1, _), Stmt::While(b2, body2, _)) => b1 == b2 && body1 == body2,
+        (Stmt::DoWhile(body1, b1, _), Stmt::While(b2, body2, _)) => b1 == b2 && body1 == body2,
+        (Stmt::For(_s1, b1, update, body1, _), Stmt::While(b2, body2, _)) => {
+            b1 == b2
+                && **body2
+                    == Block::BlockStmt(
+                        Box::new(Stmt::Sequence(
+                            Box::new(Stmt::StmtBlock(body1.clone(), Span::default())),
+                            update.clone(),
+                            Span::default(),
+                        )),
+                        Span::default(),
+                    )
+        }
+        _ => false,
+    }
+}
+
+impl Cfg {
+    pub fn build(s: &Stmt) -> Cfg {
+        let mut builder = Builder {
+            cfg: Cfg {
+                nodes: vec![Node::Entry, Node::Exit],
+                edges: vec![],
+            },
+            open: None,
+            handlers: vec![],
+        };
+        let exits = builder.stmt(s, vec![(0, "")]);
+        builder.link(exits, 1);
+        builder.cfg
+    }
+    // the nodes the statement under evaluation in a configuration may come from
+    pub fn current(&self, conf: &Configuration) -> Vec<usize> {
+        let s = match conf {
+            Configuration::StmtConf(s, _sigma) => head(s),
+            _ => return vec![],
+        };
+        if *s == Stmt::StmtBlock(Box::new(Block::EmptyBlock), Span::default()) {
+            return vec![1];
+        }
+        // a statement keeps the span of the source it came from, which tells equal statements apart
+        let span = s.span();
+        if !span.is_empty() {
+            return (0..self.nodes.len())
+                .filter(|n| match &self.nodes[*n] {
+                    Node::Block(ss) => ss.iter().any(|orig| orig.span().same(&span)),
+                    Node::Branch(orig) => orig.span().same(&span),
+                    Node::Choice(choice) => choice.same(&span),
+                    _ => false,
+                })
+                .collect();
+        }
+        // forms built without a span are matched by their structure
+        let matches = |exact: bool| {
+            (0..self.nodes.len())
+                .filter(|n| match &self.nodes[*n] {
+                    Node::Block(ss) => ss
+                        .iter()
+                        .any(|orig| (orig == s) || !exact && origin(orig, s)),
+                    Node::Branch(orig) => (orig == s) || !exact && origin(orig, s),
+                    Node::Choice(_) => matches!(s, Stmt::Choice(_b1, _b2, _)),
+                    _ => false,
+                })
+                .collect::<Vec<usize>>()
+        };
+        let exact = matches(true);
+        if exact.is_empty() {
+            matches(false)
+        } else {
+            exact
+        }
+    }
+    // the breadth-first distance of every node from the entry, used to draw the graph in rows;
+    // unreachable nodes go below the reachable ones
+    pub fn layers(&self) -> Vec<usize> {
+        let mut depth = vec![None; self.nodes.len()];
+        let mut queue = VecDeque::from(vec![0]);
+        depth[0] = Some(0);
+        while let Some(n) = queue.pop_front() {
+            for (_source, target, _label) in self.edges.iter().filter(|e| e.0 == n) {
+                if depth[*target].is_none() {
+                    depth[*target] = Some(depth[n].expect("queued nodes have a depth") + 1);
+                    queue.push_back(*target);
+                }
+            }
+        }
+        let below = depth.iter().flatten().max().map_or(0, |d| d + 1);
+        depth.into_iter().map(|d| d.unwrap_or(below)).collect()
+    }
+    pub fn to_dot(&self, highlight: &[usize]) -> String {
+        let mut dot = "digraph cfg {\n  node [shape=box, fontname=monospace];\n".to_string();
+        for (n, node) in self.nodes.iter().enumerate() {
+            let shape = match node {
+                Node::Entry | Node::Exit => ", shape=oval",
+                Node::Branch(_) | Node::Choice(_) => ", shape=diamond",
+                Node::Block(_) | Node::Handler(_) => "",
+            };
+            let fill = if highlight.contains(&n) {
+                ", style=filled, fillcolor=yellow"
+            } else {
+                ""
+            };
+            let label = node.label().replace('\\', "\\\\").replace('"', "\\\"");
+            dot.push_str(&format!(
+                "  n{} [label=\"{}\\l\"{}{}];\n",
+                n,
+                label.replace('\n', "\\l"),
+                shape,
+                fill
+            ));
+        }
+        for (source, target, label) in &self.edges {
+            if label.is_empty() {
+                dot.push_str(&format!("  n{} -> n{};\n", source, target));
+            } else {
+                dot.push_str(&format!(
+                    "  n{} -> n{} [label=\"{}\"];\n",
+                    source, target, label
+                ));
+            }
+        }
+        dot.push('}');
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Pgm;
+    use crate::ast::Rule;
+    use crate::parse::parse;
+    use crate::stack::RewriteRule;
+    use crate::stack::Stack;
+
+    fn cfg(s: &str) -> Cfg {
+        let Pgm::Program(_xl, _levels, s) = parse(s.to_string()).unwrap();
+        Cfg::build(&s)
+    }
+    #[test]
+    fn test_build() {
+        let g = cfg(
+            "int x, y; x = 1; y = 2; while (x <= 5) { x = x + 1; } if (y <= x) { y = x; } else { }",
+        );
+        // entry, exit, {x = 1 y = 2}, while, {x = x + 1}, if, {y = x}
+        assert_eq!(g.nodes.len(), 7);
+        assert_eq!(g.nodes[2].label(), "x = 1 ;\ny = 2 ;");
+        assert!(g.edges.contains(&(3, 4, "true")));
+        assert!(g.edges.contains(&(4, 3, "")));
+        assert!(g.edges.contains(&(3, 5, "false")));
+        assert!(g.edges.contains(&(5, 1, "false")));
+        assert!(g
+            .to_dot(&[3])
+            .contains("n3 [label=\"while (x <= 5)\\l\", shape=diamond, style=filled"));
+        let g = cfg("int x; do { x = x + 1; } while !(3 <= x);");
+        assert!(g.edges.contains(&(3, 2, "true")));
+    }
+    #[test]
+    fn test_current() {
+        let pgm = "int x, y; x = 1; while (x <= 2) { x = x + 1; } y = x;";
+        let g = cfg(pgm);
+        let mut stack = Stack::<Rule>::create_from_string(pgm.to_string()).unwrap();
+        let mut visited = vec![];
+        while stack.step(&Rule::list_of_rules()) {
+            for n in g.current(stack.top()) {
+                if !visited.contains(&n) {
+                    visited.push(n);
+                }
+            }
+        }
+        // the first assignment, the loop, its body, the last assignment and the exit
+        visited.sort_unstable();
+        assert_eq!(visited, vec![1, 2, 3, 4, 5]);
+        // of two equal statements only the one executing is current
+        let pgm = "int x, y; x = 1; if (y <= 0) { x = 1; } else { }";
+        let g = cfg(pgm);
+        let mut stack = Stack::<Rule>::create_from_string(pgm.to_string()).unwrap();
+        let mut visited = vec![];
+        while stack.step(&Rule::list_of_rules()) {
+            let current = g.current(stack.top());
+            assert!(current.len() <= 1);
+            visited.extend(current);
+        }
+        visited.dedup();
+        assert_eq!(visited, vec![2, 3, 4, 1]);
+    }
+}
@@ -0,0 +1,457 @@
+use std::fmt;
+
+use crate::ast::AExp;
+use crate::ast::BExp;
+use crate::ast::Block;
+use crate::ast::Configuration;
+use crate::ast::Level;
+use crate::ast::Pgm;
+use crate::ast::Rule;
+use crate::ast::Span;
+use crate::ast::Stmt;
+use crate::stack::Cost;
+use crate::stack::RewriteRule;
+use crate::stack::Stack;
+use crate::stack::RUN_LIMIT;
+
+// the values tried for the variable whose effect on the cost is shown
+pub const GROWTH: std::ops::RangeInclusive<i32> = 0..=12;
+
+fn one_line<T: fmt::Display>(t: &T) -> String {
+    t.to_string()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// a compound statement is shown by its header, its parts are listed below it
+pub fn head(s: &Stmt) -> String {
+    match s {
+        Stmt::IfThenElse(b, _, _, _) | Stmt::IfThen(b, _, _) => format!("if {}", b),
+        Stmt::While(b, _b1, _) => format!("while {}", b),
+        Stmt::DoWhile(_b1, b, _) => format!("do ... while {}", b),
+        Stmt::For(s1, b, s2, _b1, _) => {
+            format!("for ({} {} ; {})", one_line(s1), b, one_line(s2))
+        }
+        Stmt::TryCatch(_b1, x, _b2, _) => format!("try ... catch ({})", x),
+        Stmt::Choice(_b1, _b2, _) => "... [] ...".to_string(),
+        _ => one_line(s),
+    }
+}
+
+// o < while (B) S,Sigma > => < if (B) {S while (B) S} else {},Sigma >
+fn unrolled(b: &BExp, b1: &Block) -> Stmt {
+    let w = Stmt::While(Box::new(b.clone()), Box::new(b1.clone()), Span::default());
+    Stmt::IfThenElse(
+        Box::new(b.clone()),
+        Box::new(Block::BlockStmt(
+            Box::new(Stmt::Sequence(
+                Box::new(Stmt::StmtBlock(Box::new(b1.clone()), Span::default())),
+                Box::new(w),
+                Span::default(),
+            )),
+            Span::default(),
+        )),
+        Box::new(Block::EmptyBlock),
+        Span::default(),
+    )
+}
+
+// a is a partly evaluated a0: some of its subexpressions have been replaced by their value
+fn instance_a(a0: &AExp, a: &AExp) -> bool {
+    match (a0, a) {
+        _ if a0 == a => true,
+        (AExp::Int(_, _), _) => false,
+        (_, AExp::Int(_, _)) => true,
+        (AExp::Plus(a1, a2, _), AExp::Plus(a3, a4, _))
+        | (AExp::Divide(a1, a2, _), AExp::Divide(a3, a4, _)) => {
+            instance_a(a1, a3) && instance_a(a2, a4)
+        }
+        (AExp::Deref(a1, _), AExp::Deref(a2, _)) => instance_a(a1, a2),
+        _ => false,
+    }
+}
+
+fn instance_b(b0: &BExp, b: &BExp) -> bool {
+    match (b0, b) {
+        _ if b0 == b => true,
+        (BExp::Bool(_, _), _) => false,
+        (_, BExp::Bool(_, _)) => true,
+        (BExp::LessThanEq(a1, a2, _), BExp::LessThanEq(a3, a4, _)) => {
+            instance_a(a1, a3) && instance_a(a2, a4)
+        }
+        (BExp::Negation(b1, _), BExp::Negation(b2, _)) => instance_b(b1, b2),
+        (BExp::And(b1, b2, _), BExp::And(b3, b4, _)) => instance_b(b1, b3) && instance_b(b2, b4),
+        _ => false,
+    }
+}
+
+// s is what is left of s0 while the expressions it evaluates in place are being evaluated
+fn instance(s0: &Stmt, s: &Stmt) -> bool {
+    match (s0, s) {
+        _ if s0 == s => true,
+        (Stmt::Assign(x0, a0, _), Stmt::Assign(x, a, _)) => x0 == x && instance_a(a0, a),
+        (Stmt::Throw(a0, _), Stmt::Throw(a, _)) => instance_a(a0, a),
+        (Stmt::AssignDeref(a1, a2, _), Stmt::AssignDeref(a3, a4, _)) => {
+            instance_a(a1, a3) && instance_a(a2, a4)
+        }
+        (Stmt::IfThenElse(b0, s1, s2, _), Stmt::IfThenElse(b, s3, s4, _)) => {
+            instance_b(b0, b) && s1 == s3 && s2 == s4
+        }
+        // the body of a try block steps on its own, only the handler stays the same
+        (Stmt::TryCatch(_b1, x0, b0, _), Stmt::TryCatch(_b2, x, b, _)) => x0 == x && b0 == b,
+        _ => false,
+    }
+}
+
+// the statements of a program in order, each with the forms it takes while it runs:
+// itself, and the loops and conditionals it is desugared or unrolled into
+#[derive(Clone, Debug)]
+struct Source {
+    statements: Vec<(usize, String)>,
+    forms: Vec<(Stmt, usize)>,
+}
+
+impl Source {
+    fn build(s: &Stmt) -> Source {
+        let mut source = Source {
+            statements: vec![],
+            forms: vec![],
+        };
+        source.add(s, 0);
+        source
+    }
+    fn add_block(&mut self, b: &Block, depth: usize) {
+        if let Block::BlockStmt(s, _) = b {
+            self.add(s, depth);
+        }
+    }
+    fn add(&mut self, s: &Stmt, depth: usize) {
+        match s {
+            Stmt::StmtBlock(b, _) => return self.add_block(b, depth),
+            Stmt::Sequence(s1, s2, _) => {
+                self.add(s1, depth);
+                return self.add(s2, depth);
+            }
+            _ => (),
+        }
+        let i = self.statements.len();
+        self.statements.push((depth, head(s)));
+        self.forms.push((s.clone(), i));
+        match s {
+            Stmt::IfThenElse(_, b1, b2, _)
+            | Stmt::Choice(b1, b2, _)
+            | Stmt::TryCatch(b1, _, b2, _) => {
+                self.add_block(b1, depth + 1);
+                self.add_block(b2, depth + 1);
+            }
+            // o < if (B) S,Sigma > => < if (B) S else {},Sigma >
+            Stmt::IfThen(b, b1, _) => {
+                let ite = Stmt::IfThenElse(
+                    b.clone(),
+                    b1.clone(),
+                    Box::new(Block::EmptyBlock),
+                    Span::default(),
+                );
+                self.forms.push((ite, i));
+                self.add_block(b1, depth + 1);
+            }
+            Stmt::While(b, b1, _) => {
+                self.forms.push((unrolled(b, b1), i));
+                self.add_block(b1, depth + 1);
+            }
+            // o < do S while (B) ;,Sigma > => < {S while (B) S},Sigma >
+            Stmt::DoWhile(b1, b, _) => {
+                let w = Stmt::While(b.clone(), b1.clone(), Span::default());
+                self.forms.push((w, i));
+                self.forms.push((unrolled(b, b1), i));
+                self.add_block(b1, depth + 1);
+            }
+            // o < for (S1 B ; S2) S,Sigma > => < {S1 while (B) {S S2}},Sigma >
+            Stmt::For(s1, b, s2, b1, _) => {
+                let body = Block::BlockStmt(
+                    Box::new(Stmt::Sequence(
+                        Box::new(Stmt::StmtBlock(b1.clone(), Span::default())),
+                        s2.clone(),
+                        Span::default(),
+                    )),
+                    Span::default(),
+                );
+                self.forms.push((
+                    Stmt::While(b.clone(), Box::new(body.clone()), Span::default()),
+                    i,
+                ));
+                self.forms.push((unrolled(b, &body), i));
+                self.add(s1, depth + 1);
+                self.add(s2, depth + 1);
+                self.add_block(b1, depth + 1);
+            }
+            _ => (),
+        }
+    }
+    // a statement is an instance of what it was at the previous step until it finishes,
+    // and takes one of its forms when it starts; identical statements are all charged
+    // to the first of them
+    fn find(&self, s: &Stmt, last: &Option<(Stmt, usize)>) -> Option<usize> {
+        let first = |matches: &dyn Fn(&Stmt) -> bool| {
+            self.forms
+                .iter()
+                .find(|(s0, _i)| matches(s0))
+                .map(|(_s0, i)| *i)
+        };
+        match last {
+            Some((s0, i)) if instance(s0, s) => Some(*i),
+            _ => first(&|s0| s0 == s),
+        }
+        .or_else(|| first(&|s0| instance(s0, s)))
+    }
+}
+
+// the number of steps of a run, by axiom and by the source statement they belong to
+#[derive(Clone, PartialEq, Debug)]
+pub struct Profile {
+    pub total: usize,
+    pub rules: Vec<(String, usize)>,
+    // the declarations, each statement indented under the one containing it, and sequencing
+    pub statements: Vec<(String, usize)>,
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .rules
+            .iter()
+            .chain(self.statements.iter())
+            .map(|(label, _n)| label.len())
+            .max()
+            .unwrap_or(0);
+        writeln!(f, "{} steps\n\nby rule:", self.total)?;
+        for (label, n) in &self.rules {
+            writeln!(f, "  {:<width$}  {:>5}", label, n, width = width)?;
+        }
+        writeln!(f, "\nby statement:")?;
+        for (label, n) in &self.statements {
+            writeln!(f, "  {:<width$}  {:>5}", label, n, width = width)?;
+        }
+        Ok(())
+    }
+}
+
+// the program a stack started from, with the variables of the state it started in
+pub fn program(stack: &Stack<Rule>) -> Pgm {
+    match &stack.trace()[0] {
+        Configuration::PgmConf(pgm) => *pgm.clone(),
+        Configuration::StmtConf(s, sigma) => {
+            let xl = sigma
+                .bindings()
+                .into_iter()
+                .map(|(x, _i)| x)
+                .collect::<Vec<String>>();
+            let levels = xl.iter().map(|_x| Level::Low).collect();
+            Pgm::Program(xl, levels, *s.clone())
+        }
+        _ => Pgm::Program(
+            vec![],
+            vec![],
+            Stmt::StmtBlock(Box::new(Block::EmptyBlock), Span::default()),
+        ),
+    }
+}
+
+// a step inside the declarations, inside a sequence or block but none of its statements,
+// or inside a statement
+enum Charge {
+    Declarations,
+    Sequencing,
+    Statement(usize),
+    Other(String),
+}
+
+pub fn profile(stack: &Stack<Rule>) -> Profile {
+    let Pgm::Program(_xl, _levels, s) = program(stack);
+    let source = Source::build(&s);
+    let mut rules = Rule::list_of_rules()
+        .into_iter()
+        .map(|rule| (rule, 0))
+        .collect::<Vec<(Rule, usize)>>();
+    let mut statements = vec![0; source.statements.len()];
+    let (mut declarations, mut sequencing) = (0, 0);
+    let mut others: Vec<(String, usize)> = vec![];
+    let mut last = None;
+    for Cost { axiom, charged } in stack.costs() {
+        if let Some((_rule, n)) = rules.iter_mut().find(|(rule, _n)| rule == axiom) {
+            *n += 1;
+        }
+        let charge = match charged {
+            Some(Configuration::StmtConf(s, _sigma)) => match **s {
+                Stmt::Sequence(..) | Stmt::StmtBlock(_, _) => Charge::Sequencing,
+                _ => match source.find(s, &last) {
+                    Some(i) => {
+                        last = Some((*s.clone(), i));
+                        Charge::Statement(i)
+                    }
+                    None => Charge::Other(one_line(s)),
+                },
+            },
+            Some(conf) => match conf {
+                Configuration::PgmConf(_) => Charge::Declarations,
+                _ => Charge::Other(conf.to_string()),
+            },
+            None => Charge::Other("(none)".to_string()),
+        };
+        if !matches!(charge, Charge::Statement(_)) {
+            last = None;
+        }
+        match charge {
+            Charge::Declarations => declarations += 1,
+            Charge::Sequencing => sequencing += 1,
+            Charge::Statement(i) => statements[i] += 1,
+            Charge::Other(label) => match others.iter_mut().find(|(l, _n)| *l == label) {
+                Some((_l, n)) => *n += 1,
+                None => others.push((label, 1)),
+            },
+        }
+    }
+    // the most frequent rules first, in the order of the rule list otherwise
+    rules.sort_by_key(|(_rule, n)| std::cmp::Reverse(*n));
+    let mut by_statement = vec![("(declarations)".to_string(), declarations)];
+    by_statement.extend(
+        source
+            .statements
+            .into_iter()
+            .zip(statements)
+            .map(|((depth, label), n)| (format!("{}{}", "  ".repeat(depth), label), n)),
+    );
+    by_statement.push(("(sequencing)".to_string(), sequencing));
+    by_statement.extend(others);
+    Profile {
+        total: stack.costs().len(),
+        rules: rules
+            .into_iter()
+            .filter(|(_rule, n)| *n > 0)
+            .map(|(rule, n)| (rule.get_label(), n))
+            .collect(),
+        statements: by_statement,
+    }
+}
+
+// the statement with every constant assigned to x replaced by i
+fn vary(s: &Stmt, x: &str, i: i32) -> Stmt {
+    let block = |b: &Block| match b {
+        Block::EmptyBlock => Block::EmptyBlock,
+        Block::BlockStmt(s, _) => Block::BlockStmt(Box::new(vary(s, x, i)), Span::default()),
+    };
+    let stmt = |s: &Stmt| Box::new(vary(s, x, i));
+    match s {
+        Stmt::Assign(y, a, _) if y == x && matches!(**a, AExp::Int(_, _)) => Stmt::Assign(
+            y.clone(),
+            Box::new(AExp::Int(i, Span::default())),
+            Span::default(),
+        ),
+        Stmt::StmtBlock(b, _) => Stmt::StmtBlock(Box::new(block(b)), Span::default()),
+        Stmt::Sequence(s1, s2, _) => Stmt::Sequence(stmt(s1), stmt(s2), Span::default()),
+        Stmt::IfThenElse(b, b1, b2, _) => Stmt::IfThenElse(
+            b.clone(),
+            Box::new(block(b1)),
+            Box::new(block(b2)),
+            Span::default(),
+        ),
+        Stmt::While(b, b1, _) => Stmt::While(b.clone(), Box::new(block(b1)), Span::default()),
+        Stmt::Choice(b1, b2, _) => {
+            Stmt::Choice(Box::new(block(b1)), Box::new(block(b2)), Span::default())
+        }
+        Stmt::TryCatch(b1, y, b2, _) => Stmt::TryCatch(
+            Box::new(block(b1)),
+            y.clone(),
+            Box::new(block(b2)),
+            Span::default(),
+        ),
+        Stmt::IfThen(b, b1, _) => Stmt::IfThen(b.clone(), Box::new(block(b1)), Span::default()),
+        Stmt::DoWhile(b1, b, _) => Stmt::DoWhile(Box::new(block(b1)), b.clone(), Span::default()),
+        Stmt::For(s1, b, s2, b1, _) => Stmt::For(
+            stmt(s1),
+            b.clone(),
+            stmt(s2),
+            Box::new(block(b1)),
+            Span::default(),
+        ),
+        Stmt::Assign(..) | Stmt::Throw(_, _) | Stmt::New(_, _) | Stmt::AssignDeref(..) => s.clone(),
+    }
+}
+
+// the number of steps of the program for each value of x, which it starts with
+// and which replaces every constant assigned to it; None if the run does not finish
+pub fn growth(pgm: &Pgm, x: &str) -> Vec<(i32, Option<usize>)> {
+    let Pgm::Program(xl, levels, s) = pgm;
+    GROWTH
+        .map(|i| {
+            let varied = Pgm::Program(xl.clone(), levels.clone(), vary(s, x, i));
+            let start = xl
+                .iter()
+                .map(|y| (y.clone(), if y == x { i } else { 0 }))
+                .collect::<Vec<(String, i32)>>();
+            let mut stack = Stack::<Rule>::create_from_state(&varied, &start);
+            let steps = stack.run(&Rule::list_of_rules(), RUN_LIMIT);
+            let finished = stack.top().final_state().is_some();
+            (i, Some(steps).filter(|_steps| finished))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile() {
+        let mut stack = Stack::<Rule>::create_from_string(
+            "int x, y; y = 2; while (x <= y) { x = x + 1; } y = y + x;".to_string(),
+        )
+        .unwrap();
+        let steps = stack.run(&Rule::list_of_rules(), RUN_LIMIT);
+        let p = profile(&stack);
+        assert_eq!(p.total, steps);
+        assert_eq!(
+            p.statements.iter().map(|(_label, n)| n).sum::<usize>(),
+            steps
+        );
+        let count = |label: &str| {
+            p.statements
+                .iter()
+                .find(|(l, _n)| l.trim() == label)
+                .map(|(_l, n)| *n)
+                .unwrap()
+        };
+        assert_eq!(count("(declarations)"), 1);
+        // a lookup, the addition and the assignment, three times
+        assert_eq!(count("x = (x + 1) ;"), 9);
+        // unrolling, two lookups, the comparison and the choice of branch, four times
+        assert_eq!(count("while (x <= y)"), 20);
+        assert_eq!(p.rules[0], ("Variable Lookup".to_string(), 13));
+        // every step of the desugared loops is charged to a source statement
+        let mut stack = Stack::<Rule>::create_from_string(
+            "int x, y; for (x = 0; x <= 2; x = x + 1) { y = y + x; } do { y = y + 1; } while (y <= 5); if (x <= y) { x = 1; }".to_string(),
+        )
+        .unwrap();
+        stack.run(&Rule::list_of_rules(), RUN_LIMIT);
+        let p = profile(&stack);
+        assert_eq!(p.statements.last().unwrap().0, "(sequencing)");
+        assert!(p.statements.iter().all(|(_label, n)| *n > 0));
+        // x = 1 is also what x = x + 1 becomes when x is 0
+        assert_eq!(p.statements[8], ("  x = 1 ;".to_string(), 1));
+    }
+    #[test]
+    fn test_growth() {
+        // the loop of Stack::new, which runs y - 4 times once y is at least 5
+        let pgm = program(&Stack::new());
+        let costs = growth(&pgm, "y");
+        let steps = costs
+            .iter()
+            .map(|(_i, steps)| steps.unwrap())
+            .collect::<Vec<usize>>();
+        assert!(steps[..5].iter().all(|n| *n == steps[0]));
+        let per_iteration = steps[6] - steps[5];
+        assert!(per_iteration > 0);
+        assert!(steps[5..].windows(2).all(|w| w[1] - w[0] == per_iteration));
+    }
+}
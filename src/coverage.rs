@@ -0,0 +1,201 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use crate::ast::Configuration;
+use crate::ast::Rule;
+use crate::ltl::STATE_LIMIT;
+use crate::parse::parse;
+use crate::parse::ParseError;
+use crate::stack::RewriteRule;
+use crate::stack::Stack;
+
+// what became of a program of the corpus
+#[derive(Clone, PartialEq, Debug)]
+pub enum Explored {
+    Unparsed(ParseError),
+    // every reachable configuration was explored
+    Complete(usize),
+    // exploration stopped after STATE_LIMIT configurations
+    Partial,
+}
+
+// how often every rule took part in a step, congruences included,
+// over all runs of the programs of a corpus
+#[derive(Clone, PartialEq, Debug)]
+pub struct Coverage {
+    pub programs: Vec<(String, Explored)>,
+    // the rule, the number of steps it took part in and the number of programs using it
+    pub rules: Vec<(Rule, usize, usize)>,
+}
+
+impl Coverage {
+    pub fn unexercised(&self) -> Vec<Rule> {
+        self.rules
+            .iter()
+            .filter(|(_rule, steps, _programs)| *steps == 0)
+            .map(|(rule, _steps, _programs)| rule.clone())
+            .collect()
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, explored) in &self.programs {
+            match explored {
+                Explored::Unparsed(e) => writeln!(f, "{}: {}", name, e)?,
+                Explored::Complete(n) => writeln!(f, "{}: {} configurations", name, n)?,
+                Explored::Partial => {
+                    writeln!(f, "{}: stopped after {} configurations", name, STATE_LIMIT)?
+                }
+            }
+        }
+        let width = self
+            .rules
+            .iter()
+            .map(|(rule, _steps, _programs)| rule.get_label().len())
+            .max()
+            .unwrap_or(0);
+        writeln!(
+            f,
+            "\n{:<width$}  {:>6}  {:>8}",
+            "rule",
+            "steps",
+            "programs",
+            width = width
+        )?;
+        for (rule, steps, programs) in &self.rules {
+            writeln!(
+                f,
+                "{:<width$}  {:>6}  {:>8}",
+                rule.get_label(),
+                steps,
+                programs,
+                width = width
+            )?;
+        }
+        let unexercised = self.unexercised();
+        if unexercised.is_empty() {
+            writeln!(f, "\nevery rule was exercised")
+        } else {
+            writeln!(f, "\nnever exercised:")?;
+            for rule in unexercised {
+                writeln!(f, "{}  {}", rule.get_label(), rule.get_description())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// the .imp files of a directory by name, with their contents
+pub fn corpus(dir: &Path) -> Result<Vec<(String, String)>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let mut programs = vec![];
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.extension().is_some_and(|ext| ext == "imp") {
+            let text =
+                std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let name = path
+                .file_name()
+                .map_or(String::new(), |name| name.to_string_lossy().to_string());
+            programs.push((name, text));
+        }
+    }
+    programs.sort();
+    Ok(programs)
+}
+
+// every step of every run from the initial configuration, following both branches of a choice;
+// the rules of each derivation are counted once per configuration it leaves from
+fn explore(text: &str, steps: &mut [usize]) -> Explored {
+    let pgm = match parse(text.to_string()) {
+        Ok(pgm) => pgm,
+        Err(e) => return Explored::Unparsed(e),
+    };
+    let rules = Rule::list_of_rules();
+    let init = Configuration::PgmConf(Box::new(pgm));
+    let mut visited: HashSet<Configuration> = HashSet::new();
+    visited.insert(init.clone());
+    let mut todo = vec![init];
+    while let Some(conf) = todo.pop() {
+        for (chain, next) in Stack::<Rule>::create(conf).successors(&rules) {
+            for rule in chain {
+                if let Some(i) = rules.iter().position(|r| *r == rule) {
+                    steps[i] += 1;
+                }
+            }
+            if !visited.contains(&next) {
+                if visited.len() == STATE_LIMIT {
+                    return Explored::Partial;
+                }
+                visited.insert(next.clone());
+                todo.push(next);
+            }
+        }
+    }
+    Explored::Complete(visited.len())
+}
+
+pub fn coverage(programs: &[(String, String)]) -> Coverage {
+    let rules = Rule::list_of_rules();
+    let mut total = vec![0; rules.len()];
+    let mut used = vec![0; rules.len()];
+    let mut explored = vec![];
+    for (name, text) in programs {
+        let mut steps = vec![0; rules.len()];
+        explored.push((name.clone(), explore(text, &mut steps)));
+        for (i, n) in steps.into_iter().enumerate() {
+            total[i] += n;
+            used[i] += usize::from(n > 0);
+        }
+    }
+    Coverage {
+        programs: explored,
+        rules: rules
+            .into_iter()
+            .zip(total.into_iter().zip(used))
+            .map(|(rule, (steps, programs))| (rule, steps, programs))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coverage() {
+        let c = coverage(&[
+            ("plus.imp".to_string(), "int x; x = x + 1;".to_string()),
+            (
+                "choice.imp".to_string(),
+                "int x; { x = 1; } [] { }".to_string(),
+            ),
+            ("bad.imp".to_string(), "int x; x = ;".to_string()),
+        ]);
+        assert!(matches!(c.programs[2].1, Explored::Unparsed(_)));
+        let unexercised = c.unexercised();
+        assert!(unexercised.contains(&Rule::RewriteNegateTrue));
+        assert!(!unexercised.contains(&Rule::RewriteChoiceRight));
+        let plus = c
+            .rules
+            .iter()
+            .find(|(rule, _steps, _programs)| *rule == Rule::RewritePlus);
+        assert_eq!(
+            plus.map(|(_rule, steps, programs)| (*steps, *programs)),
+            Some((1, 1))
+        );
+    }
+    // the programs shipped with the app exercise the whole semantics
+    #[test]
+    fn test_corpus() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("programs");
+        let c = coverage(&corpus(&dir).unwrap());
+        assert!(c
+            .programs
+            .iter()
+            .all(|(_name, explored)| matches!(explored, Explored::Complete(_))));
+        assert_eq!(c.unexercised(), vec![]);
+    }
+}
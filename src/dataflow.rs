@@ -0,0 +1,410 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::ast::Pgm;
+use crate::ast::Stmt;
+use crate::cfg::Cfg;
+use crate::cfg::Node;
+
+// a statement or branch condition, with the variables it reads and the one it assigns
+#[derive(Clone, PartialEq, Debug)]
+pub struct Point {
+    pub label: String,
+    pub uses: Vec<String>,
+    pub def: Option<String>,
+    // a pointer assignment, which may write any variable but need not
+    pub stores: bool,
+    // false for the entry, exit and choice points, which are not shown in the listing
+    pub shown: bool,
+}
+
+impl Point {
+    // vars are the declared variables, which sit at the first locations of the store
+    fn stmt(s: &Stmt, vars: &[String]) -> Point {
+        let (mut uses, def, derefs) = match s {
+            Stmt::Assign(x, a, _) => (a.vars(), Some(x.clone()), a.derefs()),
+            Stmt::New(x, _) => (vec![], Some(x.clone()), false),
+            Stmt::AssignDeref(a1, a2, _) => {
+                let mut v = a1.vars();
+                v.extend(
+                    a2.vars()
+                        .into_iter()
+                        .filter(|y| !v.contains(y))
+                        .collect::<Vec<_>>(),
+                );
+                (v, None, a1.derefs() || a2.derefs())
+            }
+            Stmt::Throw(a, _) => (a.vars(), None, a.derefs()),
+            Stmt::IfThenElse(b, _, _, _) | Stmt::IfThen(b, _, _) | Stmt::While(b, _, _) => {
+                (b.vars(), None, b.derefs())
+            }
+            Stmt::DoWhile(_b1, b, _) | Stmt::For(_, b, _, _b1, _) => (b.vars(), None, b.derefs()),
+            _ => (vec![], None, false),
+        };
+        // a read through a pointer may read any variable
+        if derefs {
+            uses.extend(
+                vars.iter()
+                    .filter(|x| !uses.contains(x))
+                    .cloned()
+                    .collect::<Vec<_>>(),
+            );
+        }
+        Point {
+            label: s.to_string().trim().to_string(),
+            uses,
+            def,
+            stores: matches!(s, Stmt::AssignDeref(_, _, _)),
+            shown: true,
+        }
+    }
+    fn empty(label: String) -> Point {
+        Point {
+            label,
+            uses: vec![],
+            def: None,
+            stores: false,
+            shown: false,
+        }
+    }
+}
+
+// the control-flow graph with one point per statement rather than per basic block
+#[derive(Clone, PartialEq, Debug)]
+pub struct Flow {
+    pub points: Vec<Point>,
+    pub edges: Vec<(usize, usize)>,
+    pub entry: usize,
+    pub exit: usize,
+}
+
+impl Flow {
+    pub fn build(cfg: &Cfg, vars: &[String]) -> Flow {
+        let mut points = vec![];
+        let mut edges = vec![];
+        let mut first = vec![];
+        let mut last = vec![];
+        for node in &cfg.nodes {
+            first.push(points.len());
+            match node {
+                Node::Block(ss) => {
+                    for s in ss {
+                        if points.len() > *first.last().expect("pushed above") {
+                            edges.push((points.len() - 1, points.len()));
+                        }
+                        points.push(Point::stmt(s, vars));
+                    }
+                }
+                Node::Branch(s) => {
+                    let mut p = Point::stmt(s, vars);
+                    p.label = node.label();
+                    points.push(p);
+                }
+                Node::Handler(x) => points.push(Point {
+                    label: node.label(),
+                    uses: vec![],
+                    def: Some(x.clone()),
+                    stores: false,
+                    shown: true,
+                }),
+                Node::Entry | Node::Exit | Node::Choice(_) => {
+                    points.push(Point::empty(node.label()))
+                }
+            }
+            last.push(points.len() - 1);
+        }
+        edges.extend(cfg.edges.iter().map(|(s, t, _label)| (last[*s], first[*t])));
+        Flow {
+            points,
+            edges,
+            entry: first[0],
+            exit: first[1],
+        }
+    }
+}
+
+// an instance of the monotone framework, its facts form a lattice of finite height
+pub trait Analysis {
+    type Fact: Clone + PartialEq;
+
+    // false for backward analyses, whose facts flow from the exit against the edges
+    fn forward(&self) -> bool;
+    fn bottom(&self) -> Self::Fact;
+    // the fact at the entry of a forward analysis or at the exit of a backward one
+    fn boundary(&self) -> Self::Fact;
+    fn join(&self, f1: &Self::Fact, f2: &Self::Fact) -> Self::Fact;
+    fn transfer(&self, flow: &Flow, p: usize, fact: &Self::Fact) -> Self::Fact;
+}
+
+// the facts flowing into and out of every point, in the direction of the analysis
+#[derive(Clone, PartialEq, Debug)]
+pub struct Solution<F> {
+    pub input: Vec<F>,
+    pub output: Vec<F>,
+}
+
+// the least fixpoint by chaotic iteration over a worklist
+pub fn solve<A: Analysis>(analysis: &A, flow: &Flow) -> Solution<A::Fact> {
+    let n = flow.points.len();
+    let (start, preds, succs): (usize, Vec<Vec<usize>>, Vec<Vec<usize>>) = {
+        let mut into = vec![vec![]; n];
+        let mut from = vec![vec![]; n];
+        for (s, t) in &flow.edges {
+            into[*t].push(*s);
+            from[*s].push(*t);
+        }
+        if analysis.forward() {
+            (flow.entry, into, from)
+        } else {
+            (flow.exit, from, into)
+        }
+    };
+    let mut input = vec![analysis.bottom(); n];
+    let mut output = vec![analysis.bottom(); n];
+    let mut worklist = (0..n).collect::<Vec<usize>>();
+    while let Some(p) = worklist.pop() {
+        let init = if p == start {
+            analysis.boundary()
+        } else {
+            analysis.bottom()
+        };
+        input[p] = preds[p]
+            .iter()
+            .fold(init, |fact, q| analysis.join(&fact, &output[*q]));
+        let fact = analysis.transfer(flow, p, &input[p]);
+        if fact != output[p] {
+            output[p] = fact;
+            worklist.extend(
+                succs[p]
+                    .iter()
+                    .filter(|q| !worklist.contains(q))
+                    .collect::<Vec<_>>(),
+            );
+        }
+    }
+    Solution { input, output }
+}
+
+// the variables that may be read before they are next assigned, backwards
+pub struct LiveVariables;
+
+impl Analysis for LiveVariables {
+    type Fact = BTreeSet<String>;
+
+    fn forward(&self) -> bool {
+        false
+    }
+    fn bottom(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+    fn boundary(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+    fn join(&self, f1: &Self::Fact, f2: &Self::Fact) -> Self::Fact {
+        f1.union(f2).cloned().collect()
+    }
+    // live before = (live after - def) + uses
+    fn transfer(&self, flow: &Flow, p: usize, fact: &Self::Fact) -> Self::Fact {
+        let point = &flow.points[p];
+        let mut live = fact.clone();
+        if let Some(x) = &point.def {
+            live.remove(x);
+        }
+        live.extend(point.uses.iter().cloned());
+        live
+    }
+}
+
+// the assignments, by point, whose value may still be in their variable, forwards
+pub struct ReachingDefinitions;
+
+impl Analysis for ReachingDefinitions {
+    type Fact = BTreeSet<usize>;
+
+    fn forward(&self) -> bool {
+        true
+    }
+    fn bottom(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+    fn boundary(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+    fn join(&self, f1: &Self::Fact, f2: &Self::Fact) -> Self::Fact {
+        f1.union(f2).cloned().collect()
+    }
+    // reaching after = (reaching before - other definitions of the variable) + this one,
+    // where a pointer assignment may define any variable and so kills none
+    fn transfer(&self, flow: &Flow, p: usize, fact: &Self::Fact) -> Self::Fact {
+        let point = &flow.points[p];
+        match &point.def {
+            Some(x) => fact
+                .iter()
+                .filter(|d| flow.points[**d].def.as_ref() != Some(x))
+                .cloned()
+                .chain(std::iter::once(p))
+                .collect(),
+            None if point.stores => fact.iter().cloned().chain(std::iter::once(p)).collect(),
+            None => fact.clone(),
+        }
+    }
+}
+
+// the declared variables that may not have been assigned yet, forwards;
+// they hold 0 in the semantics, but reading one is usually a mistake
+pub struct Unassigned {
+    pub vars: Vec<String>,
+}
+
+impl Analysis for Unassigned {
+    type Fact = BTreeSet<String>;
+
+    fn forward(&self) -> bool {
+        true
+    }
+    fn bottom(&self) -> Self::Fact {
+        BTreeSet::new()
+    }
+    fn boundary(&self) -> Self::Fact {
+        self.vars.iter().cloned().collect()
+    }
+    fn join(&self, f1: &Self::Fact, f2: &Self::Fact) -> Self::Fact {
+        f1.union(f2).cloned().collect()
+    }
+    fn transfer(&self, flow: &Flow, p: usize, fact: &Self::Fact) -> Self::Fact {
+        let mut unassigned = fact.clone();
+        if let Some(x) = &flow.points[p].def {
+            unassigned.remove(x);
+        }
+        unassigned
+    }
+}
+
+fn show_set<T: fmt::Display>(set: impl Iterator<Item = T>) -> String {
+    format!(
+        "{{{}}}",
+        set.map(|x| x.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+// every statement with the variables live before it and the definitions reaching it,
+// which are numbered by their line, followed by the reads of possibly unassigned variables
+pub fn report(pgm: &Pgm) -> String {
+    let Pgm::Program(xl, _levels, s) = pgm;
+    let flow = Flow::build(&Cfg::build(s), xl);
+    let live = solve(&LiveVariables, &flow);
+    let reaching = solve(&ReachingDefinitions, &flow);
+    let unassigned = solve(&Unassigned { vars: xl.clone() }, &flow);
+    let shown = (0..flow.points.len())
+        .filter(|p| flow.points[*p].shown)
+        .collect::<Vec<usize>>();
+    let line = |p: &usize| shown.iter().position(|q| q == p).map_or(0, |l| l + 1);
+    let width = shown
+        .iter()
+        .map(|p| flow.points[*p].label.len())
+        .max()
+        .unwrap_or(0);
+    let mut lines = vec![];
+    let mut warnings = vec![];
+    for p in &shown {
+        let point = &flow.points[*p];
+        lines.push(format!(
+            "{:>3}  {:<width$}  live: {}  reaching: {}",
+            line(p),
+            point.label.replace('\n', " "),
+            show_set(live.output[*p].iter()),
+            show_set(reaching.input[*p].iter().map(line)),
+            width = width
+        ));
+        for x in point
+            .uses
+            .iter()
+            .filter(|x| unassigned.input[*p].contains(*x))
+        {
+            warnings.push(format!(
+                "line {}: {} may be read before it is assigned",
+                line(p),
+                x
+            ));
+        }
+    }
+    lines.push("".to_string());
+    if warnings.is_empty() {
+        lines.push("no variable is read before it is assigned".to_string());
+    }
+    lines.extend(warnings);
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+
+    fn flow(s: &str) -> (Vec<String>, Flow) {
+        let Pgm::Program(xl, _levels, s) = parse(s.to_string()).unwrap();
+        let f = Flow::build(&Cfg::build(&s), &xl);
+        (xl, f)
+    }
+    fn set(v: &[&str]) -> BTreeSet<String> {
+        v.iter().map(|x| x.to_string()).collect()
+    }
+    #[test]
+    fn test_live() {
+        // entry, exit, x = 1, while, x = x + y, z = x
+        let (_xl, f) = flow("int x, y, z; x = 1; while (x <= 5) { x = x + y; } z = x;");
+        let live = solve(&LiveVariables, &f);
+        assert_eq!(live.output[2], set(&["y"]));
+        assert_eq!(live.output[3], set(&["x", "y"]));
+        assert_eq!(live.output[5], set(&["x"]));
+        assert_eq!(live.input[5], set(&[]));
+    }
+    #[test]
+    fn test_reaching() {
+        let (xl, f) = flow("int x, y; x = 1; if (y <= 0) { x = 2; } else { y = x; } y = x + y;");
+        // entry, exit, x = 1, if, x = 2, y = x, y = x + y
+        let reaching = solve(&ReachingDefinitions, &f);
+        assert_eq!(reaching.input[5], [2].iter().cloned().collect());
+        assert_eq!(reaching.input[6], [2, 4, 5].iter().cloned().collect());
+        let unassigned = solve(&Unassigned { vars: xl }, &f);
+        assert_eq!(unassigned.input[3], set(&["y"]));
+        assert_eq!(unassigned.input[6], set(&["y"]));
+    }
+    #[test]
+    fn test_report() {
+        let pgm = parse("int x, y; x = 1; while (x <= 5) { x = x + y; }".to_string()).unwrap();
+        let r = report(&pgm);
+        assert!(r.contains("line 3: y may be read before it is assigned"));
+        assert!(!r.contains("x may be read"));
+    }
+    #[test]
+    fn test_catch() {
+        let source = "int x, y; x = 1; try { throw 5; } catch (y) { x = y; }";
+        // entry, exit, x = 1, throw 5, catch (y), x = y
+        let (xl, f) = flow(source);
+        assert_eq!(f.points[4].def, Some("y".to_string()));
+        let live = solve(&LiveVariables, &f);
+        assert_eq!(live.input[2], set(&[]));
+        let reaching = solve(&ReachingDefinitions, &f);
+        assert_eq!(reaching.input[5], [2, 4].iter().cloned().collect());
+        let unassigned = solve(&Unassigned { vars: xl }, &f);
+        assert_eq!(unassigned.input[5], set(&[]));
+        let r = report(&parse(source.to_string()).unwrap());
+        assert!(r.contains("no variable is read before it is assigned"));
+    }
+    #[test]
+    fn test_pointers() {
+        // *0 is x, so x = 1 is not dead
+        let (_xl, f) = flow("int x, y; x = 1; y = *0;");
+        let live = solve(&LiveVariables, &f);
+        assert!(live.input[2].contains("x"));
+        let r = report(&parse("int x, y; y = *0;".to_string()).unwrap());
+        assert!(r.contains("line 1: x may be read before it is assigned"));
+        // entry, exit, x = 1, *p = 5, y = x
+        let (_xl, f) = flow("int x, y, p; x = 1; *p = 5; y = x;");
+        let reaching = solve(&ReachingDefinitions, &f);
+        assert_eq!(reaching.input[4], [2, 3].iter().cloned().collect());
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+
+// a rule of an inference system such as a type system or Hoare logic:
+// applied backwards to a goal judgment it yields the premises still to be derived
+pub trait InferenceRule: Clone + PartialEq + fmt::Debug {
+    type Judgment: Clone + fmt::Debug + fmt::Display;
+
+    fn list_of_rules() -> Vec<Self>;
+    fn get_description(&self) -> String;
+    fn get_label(&self) -> String;
+    // None if the rule does not conclude the judgment
+    fn premises(&self, judgment: &Self::Judgment) -> Option<Vec<Self::Judgment>>;
+}
+
+// a partial derivation tree built depth first, always expanding the first open goal
+#[derive(Clone, Debug)]
+pub struct Derivation<R: InferenceRule> {
+    // (depth, judgment, rule) in preorder
+    steps: Vec<(usize, R::Judgment, R)>,
+    // (depth, judgment), the first one is the current goal
+    goals: Vec<(usize, R::Judgment)>,
+    prev: Option<Box<Derivation<R>>>,
+}
+
+impl<R: InferenceRule> fmt::Display for Derivation<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let steps = self
+            .steps
+            .iter()
+            .map(|(d, j, r)| format!("{}{}   [{}]", "    ".repeat(*d), j, r.get_label()));
+        let goals = self
+            .goals
+            .iter()
+            .map(|(d, j)| format!("{}? {}", "    ".repeat(*d), j));
+        write!(
+            f,
+            "{}",
+            steps.chain(goals).collect::<Vec<String>>().join("\n")
+        )
+    }
+}
+
+impl<R: InferenceRule> Derivation<R> {
+    pub fn create(judgment: R::Judgment) -> Derivation<R> {
+        Derivation {
+            steps: vec![],
+            goals: vec![(0, judgment)],
+            prev: None,
+        }
+    }
+    pub fn current_goal(&self) -> Option<&R::Judgment> {
+        self.goals.first().map(|(_d, j)| j)
+    }
+    pub fn is_complete(&self) -> bool {
+        self.goals.is_empty()
+    }
+    pub fn can_apply_rule(&self, rule: R) -> bool {
+        match self.current_goal() {
+            Some(goal) => rule.premises(goal).is_some(),
+            None => false,
+        }
+    }
+    // true means a sucess apply, false failed to apply rule
+    pub fn apply_rule(&mut self, rule: R) -> bool {
+        let (depth, goal) = match self.goals.first() {
+            Some(x) => x.clone(),
+            None => return false,
+        };
+        match rule.premises(&goal) {
+            None => false,
+            Some(premises) => {
+                self.prev = Some(Box::new(self.clone()));
+                self.goals.remove(0);
+                let new_goals = premises.into_iter().map(|j| (depth + 1, j));
+                self.goals.splice(0..0, new_goals);
+                self.steps.push((depth, goal, rule));
+                true
+            }
+        }
+    }
+    // applies the first applicable rule until the derivation is complete or stuck,
+    // which is enough for syntax directed systems
+    pub fn auto(&mut self) -> bool {
+        while !self.is_complete() {
+            let rule = R::list_of_rules()
+                .into_iter()
+                .find(|rule| self.can_apply_rule(rule.clone()));
+            match rule {
+                Some(rule) => {
+                    self.apply_rule(rule);
+                }
+                None => return false,
+            }
+        }
+        true
+    }
+    pub fn undo(&mut self) {
+        if let Some(prev) = self.prev.take() {
+            *self = *prev;
+        }
+    }
+}
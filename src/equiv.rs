@@ -0,0 +1,201 @@
+use std::fmt;
+
+use crate::ast::Configuration;
+use crate::ast::Pgm;
+use crate::ast::Rule;
+use crate::stack::RewriteRule;
+use crate::stack::Stack;
+use crate::stack::RUN_LIMIT;
+use crate::vm::show_memory;
+
+// every variable takes each value of the range when there are at most EXHAUSTIVE_VARS of them
+pub const RANGE: std::ops::RangeInclusive<i32> = -2..=2;
+pub const EXHAUSTIVE_VARS: usize = 4;
+pub const TRIALS: usize = 200;
+
+pub type Bindings = Vec<(String, i32)>;
+
+// xorshift, so that the tests and the app see the same states
+pub struct Rng(pub u64);
+
+impl Rng {
+    // a small value, so that loops counting up to or down from it finish quickly
+    pub fn next(&mut self) -> i32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % 21) as i32 - 5
+    }
+}
+
+// the initial states tried, first all those over RANGE if there are few variables,
+// then TRIALS random ones
+pub fn inputs(xl: &[String]) -> Vec<Bindings> {
+    let mut inputs: Vec<Bindings> = vec![vec![]];
+    if xl.len() <= EXHAUSTIVE_VARS {
+        for x in xl {
+            inputs = inputs
+                .into_iter()
+                .flat_map(|sigma| {
+                    RANGE.map(move |i| {
+                        let mut sigma = sigma.clone();
+                        sigma.push((x.clone(), i));
+                        sigma
+                    })
+                })
+                .collect();
+        }
+    } else {
+        inputs.clear();
+    }
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    inputs.extend((0..TRIALS).map(|_trial| xl.iter().map(|x| (x.clone(), rng.next())).collect()));
+    inputs
+}
+
+// how a run from an initial state ends, with the variables sorted by name
+#[derive(Clone, PartialEq, Debug)]
+pub enum Outcome {
+    Finished(Bindings),
+    Uncaught(i32, Bindings),
+    Stuck,
+    // the run repeated a configuration or did not finish within RUN_LIMIT steps
+    Unfinished,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Finished(sigma) => write!(f, "finishes with {}", show_memory(sigma)),
+            Outcome::Uncaught(i, sigma) => {
+                write!(f, "throws {} uncaught with {}", i, show_memory(sigma))
+            }
+            Outcome::Stuck => write!(f, "gets stuck"),
+            Outcome::Unfinished => write!(f, "does not finish in {} steps", RUN_LIMIT),
+        }
+    }
+}
+
+fn sorted(mut sigma: Bindings) -> Bindings {
+    sigma.sort();
+    sigma
+}
+
+// the outcome of a run and the configurations it went through
+pub fn run(pgm: &Pgm, start: &[(String, i32)]) -> (Outcome, Vec<Configuration>) {
+    let mut stack = Stack::<Rule>::create_from_state(pgm, start);
+    let steps = stack.run(&Rule::list_of_rules(), RUN_LIMIT);
+    let outcome = match (stack.top().final_state(), stack.top()) {
+        (Some(sigma), _conf) => Outcome::Finished(sorted(sigma)),
+        (None, Configuration::UncaughtConf(i, sigma)) => {
+            Outcome::Uncaught(*i, sorted(sigma.bindings()))
+        }
+        _ if steps == RUN_LIMIT || stack.cycle().is_some() => Outcome::Unfinished,
+        _ => Outcome::Stuck,
+    };
+    (outcome, stack.trace().to_vec())
+}
+
+// an initial state from which the two programs end differently
+#[derive(Clone, Debug)]
+pub struct Difference {
+    pub start: Bindings,
+    pub outcomes: (Outcome, Outcome),
+    pub traces: (Vec<Configuration>, Vec<Configuration>),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "starting from {}", show_memory(&self.start))?;
+        writeln!(f, "the first program {}", self.outcomes.0)?;
+        writeln!(f, "the second program {}", self.outcomes.1)?;
+        for (name, trace) in [("first", &self.traces.0), ("second", &self.traces.1)] {
+            writeln!(f, "\ntrace of the {} program:", name)?;
+            for conf in trace {
+                writeln!(f, "{}", conf)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// runs both programs from every input, two runs that do not finish count as agreeing
+// since nothing can be observed about them
+pub fn check(pgm1: &Pgm, pgm2: &Pgm) -> Result<Option<Difference>, String> {
+    let (Pgm::Program(xl1, _levels1, _s1), Pgm::Program(xl2, _levels2, _s2)) = (pgm1, pgm2);
+    if sorted_names(xl1) != sorted_names(xl2) {
+        return Err(format!(
+            "The programs declare different variables: {} and {}",
+            xl1.join(", "),
+            xl2.join(", ")
+        ));
+    }
+    for start in inputs(xl1) {
+        let (outcome1, trace1) = run(pgm1, &start);
+        let (outcome2, trace2) = run(pgm2, &start);
+        if outcome1 != outcome2 {
+            return Ok(Some(Difference {
+                start,
+                outcomes: (outcome1, outcome2),
+                traces: (trace1, trace2),
+            }));
+        }
+    }
+    Ok(None)
+}
+
+fn sorted_names(xl: &[String]) -> Vec<String> {
+    let mut names = xl.to_vec();
+    names.sort();
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+
+    fn pgm(s: &str) -> Pgm {
+        parse(s.to_string()).unwrap()
+    }
+    #[test]
+    fn test_equivalent() {
+        // the unrolling of RewriteLoop
+        let p1 = pgm("int x, y; while (x <= 5) { x = x + 1; y = y + x; }");
+        let p2 = pgm(
+            "int y, x; if (x <= 5) { x = x + 1; y = y + x; while (x <= 5) { x = x + 1; y = y + x; } } else { }",
+        );
+        assert_eq!(
+            inputs(&["x".to_string(), "y".to_string()]).len(),
+            25 + TRIALS
+        );
+        assert!(check(&p1, &p2).unwrap().is_none());
+    }
+    #[test]
+    fn test_difference() {
+        let p1 = pgm("int x; if (x <= 0) { x = 0; } else { }");
+        let p2 = pgm("int x; x = 0;");
+        let d = check(&p1, &p2).unwrap().unwrap();
+        assert_eq!(d.start, vec![("x".to_string(), 1)]);
+        assert_eq!(d.outcomes.0, Outcome::Finished(vec![("x".to_string(), 1)]));
+        assert_eq!(d.traces.1.len(), 2);
+        let p3 = pgm("int x; x = 1 / (x + 2);");
+        let p4 = pgm("int x; x = 0;");
+        let d = check(&p3, &p4).unwrap().unwrap();
+        assert_eq!(d.outcomes.0, Outcome::Stuck);
+        assert!(check(&p1, &pgm("int y; y = 0;")).is_err());
+        // each program runs with its own layout, where *0 is the variable declared first
+        let p5 = pgm("int x, y; x = *0;");
+        let p6 = pgm("int y, x; x = *0;");
+        let d = check(&p5, &p6).unwrap().unwrap();
+        let (x, y) = (d.start[0].1, d.start[1].1);
+        assert_eq!(
+            d.outcomes.0,
+            Outcome::Finished(vec![("x".to_string(), x), ("y".to_string(), y)])
+        );
+        assert_eq!(
+            d.outcomes.1,
+            Outcome::Finished(vec![("x".to_string(), y), ("y".to_string(), y)])
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use std::fmt;
+
+use crate::ast::BExp;
+use crate::ast::Block;
+use crate::ast::Span;
+use crate::ast::Stmt;
+use crate::derivation::InferenceRule;
+
+// side conditions are checked for every assignment of the free variables to this range
+pub const BOUND: i32 = 5;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum HoareJudgment {
+    Triple(BExp, Stmt, BExp),
+    Implies(BExp, BExp),
+}
+
+impl fmt::Display for HoareJudgment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HoareJudgment::Triple(p, s, q) => {
+                write!(f, "{{{}}} {} {{{}}}", p, s, q)
+            }
+            HoareJudgment::Implies(p, q) => {
+                write!(f, "{} ==> {}", p, q)
+            }
+        }
+    }
+}
+
+fn and(b1: BExp, b2: BExp) -> BExp {
+    BExp::And(Box::new(b1), Box::new(b2), Span::default())
+}
+
+fn not(b: BExp) -> BExp {
+    BExp::Negation(Box::new(b), Span::default())
+}
+
+fn block_stmt(b: Block) -> Stmt {
+    Stmt::StmtBlock(Box::new(b), Span::default())
+}
+
+// every assignment of the variables to [-BOUND, BOUND]
+pub fn assignments(vars: &[String]) -> Vec<Vec<(String, i32)>> {
+    vars.iter().fold(vec![vec![]], |acc, x| {
+        acc.into_iter()
+            .flat_map(|sigma| {
+                (-BOUND..=BOUND).map(move |i| {
+                    let mut sigma = sigma.clone();
+                    sigma.push((x.clone(), i));
+                    sigma
+                })
+            })
+            .collect()
+    })
+}
+
+// P ==> Q by brute force; states in which P is undefined are ignored
+pub fn valid(p: &BExp, q: &BExp) -> bool {
+    let mut vars = p.vars();
+    vars.extend(q.vars().into_iter().filter(|x| !p.vars().contains(x)));
+    assignments(&vars)
+        .iter()
+        .all(|sigma| p.evaluate(sigma) != Some(true) || q.evaluate(sigma) == Some(true))
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum HoareRule {
+    // {P} {} {P}
+    Skip,
+    // {P} {S} {Q} if {P} S {Q}
+    Block,
+    // {Q[A / X]} X = A ; {Q} where Q reads no pointer
+    Assignment,
+    // {P} S1 S2 {Q} if {P} S1 {R} /\ {R} S2 {Q}
+    Sequence(BExp),
+    // {P} if (B) S1 else S2 {Q} if {P && B} S1 {Q} /\ {P && !B} S2 {Q}
+    Conditional,
+    // {P} if (B) S {Q} if {P && B} S {Q} /\ P && !B ==> Q
+    IfThen,
+    // {I} while (B) S {I && !B} if {I && B} S {I}
+    Loop,
+    // {P} S1 [] S2 {Q} if {P} S1 {Q} /\ {P} S2 {Q}
+    Choice,
+    // {P} S {Q} if P ==> P' /\ {P'} S {Q'} /\ Q' ==> Q
+    Consequence(BExp, BExp),
+    // P ==> Q if it holds for all values in [-BOUND, BOUND]
+    Implication,
+}
+
+impl HoareRule {
+    // the rule with its assertions taken from the user, None if they are needed but missing
+    pub fn instantiate(&self, a1: Option<BExp>, a2: Option<BExp>) -> Option<HoareRule> {
+        match self {
+            HoareRule::Sequence(_r) => Some(HoareRule::Sequence(a1?)),
+            HoareRule::Consequence(_p, _q) => Some(HoareRule::Consequence(a1?, a2?)),
+            rule => Some(rule.clone()),
+        }
+    }
+}
+
+impl InferenceRule for HoareRule {
+    type Judgment = HoareJudgment;
+
+    fn list_of_rules() -> Vec<HoareRule> {
+        vec![
+            HoareRule::Skip,
+            HoareRule::Block,
+            HoareRule::Assignment,
+            HoareRule::Sequence(BExp::Bool(true, Span::default())),
+            HoareRule::Conditional,
+            HoareRule::IfThen,
+            HoareRule::Loop,
+            HoareRule::Choice,
+            HoareRule::Consequence(
+                BExp::Bool(true, Span::default()),
+                BExp::Bool(true, Span::default()),
+            ),
+            HoareRule::Implication,
+        ]
+    }
+
+    fn get_description(&self) -> String {
+        match self {
+            HoareRule::Skip => "{P} {} {P}".to_string(),
+            HoareRule::Block => "{P} {S} {Q} if {P} S {Q}".to_string(),
+            HoareRule::Assignment => "{Q[A / X]} X = A ; {Q} where Q reads no pointer".to_string(),
+            HoareRule::Sequence(_r) => "{P} S1 S2 {Q} if {P} S1 {R} /\\ {R} S2 {Q}".to_string(),
+            HoareRule::Conditional => {
+                "{P} if (B) S1 else S2 {Q} if {P && B} S1 {Q} /\\ {P && !B} S2 {Q}".to_string()
+            }
+            HoareRule::IfThen => "{P} if (B) S {Q} if {P && B} S {Q} /\\ P && !B ==> Q".to_string(),
+            HoareRule::Loop => "{I} while (B) S {I && !B} if {I && B} S {I}".to_string(),
+            HoareRule::Choice => "{P} S1 [] S2 {Q} if {P} S1 {Q} /\\ {P} S2 {Q}".to_string(),
+            HoareRule::Consequence(_p, _q) => {
+                "{P} S {Q} if P ==> P' /\\ {P'} S {Q'} /\\ Q' ==> Q".to_string()
+            }
+            HoareRule::Implication => format!(
+                "P ==> Q if it holds for all values in [{}, {}]",
+                -BOUND, BOUND
+            ),
+        }
+    }
+
+    fn get_label(&self) -> String {
+        match self {
+            HoareRule::Skip => "Skip".to_string(),
+            HoareRule::Block => "Block".to_string(),
+            HoareRule::Assignment => "Assignment".to_string(),
+            HoareRule::Sequence(_r) => "Sequence (R = first assertion)".to_string(),
+            HoareRule::Conditional => "Conditional".to_string(),
+            HoareRule::IfThen => "If Then".to_string(),
+            HoareRule::Loop => "While".to_string(),
+            HoareRule::Choice => "Choice".to_string(),
+            HoareRule::Consequence(_p, _q) => {
+                "Consequence (P', Q' = first, second assertion)".to_string()
+            }
+            HoareRule::Implication => "Implication".to_string(),
+        }
+    }
+
+    fn premises(&self, judgment: &HoareJudgment) -> Option<Vec<HoareJudgment>> {
+        use HoareJudgment::*;
+        let premises = match (self, judgment.clone()) {
+            (HoareRule::Skip, Triple(p, Stmt::StmtBlock(b, _), q)) if p == q => match *b {
+                Block::EmptyBlock => vec![],
+                _ => return None,
+            },
+            (HoareRule::Block, Triple(p, Stmt::StmtBlock(b, _), q)) => match *b {
+                Block::BlockStmt(s, _) => vec![Triple(p, *s, q)],
+                _ => return None,
+            },
+            // substitution only sees x by name, not a read of x through a pointer
+            (HoareRule::Assignment, Triple(p, Stmt::Assign(x, a, _), q))
+                if !p.derefs() && !q.derefs() && p == q.substitute(&x, &a) =>
+            {
+                vec![]
+            }
+            (HoareRule::Sequence(r), Triple(p, Stmt::Sequence(s1, s2, _), q)) => {
+                vec![Triple(p, *s1, r.clone()), Triple(r.clone(), *s2, q)]
+            }
+            (HoareRule::Conditional, Triple(p, Stmt::IfThenElse(b, s1, s2, _), q)) => vec![
+                Triple(and(p.clone(), *b.clone()), block_stmt(*s1), q.clone()),
+                Triple(and(p, not(*b)), block_stmt(*s2), q),
+            ],
+            (HoareRule::IfThen, Triple(p, Stmt::IfThen(b, s, _), q)) => vec![
+                Triple(and(p.clone(), *b.clone()), block_stmt(*s), q.clone()),
+                Implies(and(p, not(*b)), q),
+            ],
+            (HoareRule::Loop, Triple(i, Stmt::While(b, s, _), q))
+                if q == and(i.clone(), not(*b.clone())) =>
+            {
+                vec![Triple(and(i.clone(), *b), block_stmt(*s), i)]
+            }
+            (HoareRule::Choice, Triple(p, Stmt::Choice(s1, s2, _), q)) => vec![
+                Triple(p.clone(), block_stmt(*s1), q.clone()),
+                Triple(p, block_stmt(*s2), q),
+            ],
+            (HoareRule::Consequence(p_prime, q_prime), Triple(p, s, q)) => vec![
+                Implies(p, p_prime.clone()),
+                Triple(p_prime.clone(), s, q_prime.clone()),
+                Implies(q_prime.clone(), q),
+            ],
+            (HoareRule::Implication, Implies(p, q)) if valid(&p, &q) => vec![],
+            _ => return None,
+        };
+        Some(premises)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::derivation::Derivation;
+    use crate::parse::parse;
+    use crate::parse::parse_bexp;
+
+    #[test]
+    fn test_valid() {
+        let p = parse_bexp("x <= 2 && 1 <= x".to_string()).unwrap();
+        let q = parse_bexp("x <= 3".to_string()).unwrap();
+        assert!(valid(&p, &q));
+        assert!(!valid(&q, &p));
+    }
+    #[test]
+    fn test_swap() {
+        let pgm = parse("int x, y, t; t = x; x = y; y = t;".to_string()).unwrap();
+        let s = match pgm {
+            crate::ast::Pgm::Program(_xl, _levels, s) => s,
+        };
+        let a = |s: &str| parse_bexp(s.to_string()).unwrap();
+        let mut d = Derivation::create(HoareJudgment::Triple(
+            a("x <= 1 && 2 <= y"),
+            s,
+            a("y <= 1 && 2 <= x"),
+        ));
+        // the sequence is (t = x; x = y) y = t, each midpoint is the next postcondition
+        // with the assignment substituted backwards
+        assert!(d.apply_rule(HoareRule::Sequence(a("t <= 1 && 2 <= x"))));
+        assert!(d.apply_rule(HoareRule::Sequence(a("t <= 1 && 2 <= y"))));
+        assert!(d.apply_rule(HoareRule::Assignment));
+        assert!(d.apply_rule(HoareRule::Assignment));
+        assert!(d.apply_rule(HoareRule::Assignment));
+        assert!(d.is_complete());
+    }
+    #[test]
+    fn test_assignment() {
+        let pgm = parse("int x, t; t = x;".to_string()).unwrap();
+        let s = match pgm {
+            crate::ast::Pgm::Program(_xl, _levels, s) => s,
+        };
+        let a = |s: &str| parse_bexp(s.to_string()).unwrap();
+        let mut d = Derivation::create(HoareJudgment::Triple(a("x <= 1"), s, a("t <= 2")));
+        // the precondition is not the postcondition with x for t, so only consequence helps
+        assert!(!d.apply_rule(HoareRule::Assignment));
+        assert!(d.apply_rule(HoareRule::Consequence(a("x <= 2"), a("t <= 2"))));
+        assert!(d.apply_rule(HoareRule::Implication));
+        assert!(d.apply_rule(HoareRule::Assignment));
+        assert!(d.apply_rule(HoareRule::Implication));
+        assert!(d.is_complete());
+        // a wrong consequence leaves an implication that does not hold
+        d.undo();
+        d.undo();
+        d.undo();
+        d.undo();
+        assert!(d.apply_rule(HoareRule::Consequence(a("x <= 3"), a("t <= 3"))));
+        assert!(d.apply_rule(HoareRule::Implication));
+        assert!(d.apply_rule(HoareRule::Assignment));
+        assert!(!d.apply_rule(HoareRule::Implication));
+        assert!(!d.is_complete());
+        // *0 is x, so it is 1 afterwards although substituting for x leaves it alone
+        let pgm = parse("int x; x = 1;".to_string()).unwrap();
+        let s = match pgm {
+            crate::ast::Pgm::Program(_xl, _levels, s) => s,
+        };
+        let mut d = Derivation::create(HoareJudgment::Triple(a("*0 <= 0"), s, a("*0 <= 0")));
+        assert!(!d.apply_rule(HoareRule::Assignment));
+    }
+}
@@ -0,0 +1,342 @@
+use std::fmt;
+
+use crate::lambda::parse::parse;
+use crate::stack::RewriteRule;
+use crate::stack::Stack;
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Term {
+    Var(String),
+    Lam(String, Box<Term>),
+    App(Box<Term>, Box<Term>),
+}
+
+impl fmt::Display for Term {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Term::Var(x) => {
+                write!(f, "{}", x)
+            }
+            Term::Lam(x, t) => {
+                write!(f, "(\\{}. {})", x, t)
+            }
+            Term::App(t1, t2) => {
+                write!(f, "({} {})", t1, t2)
+            }
+        }
+    }
+}
+
+impl Term {
+    fn is_value(&self) -> bool {
+        matches!(self, Term::Lam(_, _))
+    }
+
+    fn free_vars(&self) -> Vec<String> {
+        match self {
+            Term::Var(x) => vec![x.clone()],
+            Term::Lam(x, t) => t.free_vars().into_iter().filter(|y| y != x).collect(),
+            Term::App(t1, t2) => {
+                let mut v = t1.free_vars();
+                v.extend(t2.free_vars());
+                v
+            }
+        }
+    }
+
+    // capture avoiding substitution t[v / x]
+    fn substitute(&self, x: &str, v: &Term) -> Term {
+        match self {
+            Term::Var(y) if y == x => v.clone(),
+            Term::Var(y) => Term::Var(y.clone()),
+            Term::App(t1, t2) => {
+                Term::App(Box::new(t1.substitute(x, v)), Box::new(t2.substitute(x, v)))
+            }
+            // nothing to replace below a binder of x, or when x is not free
+            Term::Lam(y, t) if y == x || !t.free_vars().iter().any(|z| z == x) => self.clone(),
+            Term::Lam(y, t) => {
+                let free = v.free_vars();
+                if free.contains(y) {
+                    // rename the binder so that it does not capture a free variable of v,
+                    // nor get captured itself when x is replaced
+                    let mut fresh = format!("{}'", y);
+                    while free.contains(&fresh) || t.free_vars().contains(&fresh) || fresh == x {
+                        fresh.push('\'');
+                    }
+                    let t = t.substitute(y, &Term::Var(fresh.clone()));
+                    Term::Lam(fresh, Box::new(t.substitute(x, v)))
+                } else {
+                    Term::Lam(y.clone(), Box::new(t.substitute(x, v)))
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum LambdaConf {
+    TermConf(Box<Term>),
+    Dummy, //top level, meaning that it is an unconditional rewrite
+}
+
+impl fmt::Display for LambdaConf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LambdaConf::TermConf(t) => {
+                write!(f, "< {} >", t)
+            }
+            LambdaConf::Dummy => {
+                write!(f, "Error this shouldn't be here")
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Strategy {
+    CallByValue,
+    CallByName,
+}
+
+impl Stack<LambdaRule> {
+    pub fn create_from_string(s: String) -> Option<Stack<LambdaRule>> {
+        let t = parse(s)?;
+        Some(Stack::create(LambdaConf::TermConf(Box::new(t))))
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum LambdaRule {
+    // crl o < T1 T2 > => < T1' T2 > if o < T1 > => < T1' > .
+    CbvAppLeft,
+    // crl o < V1 T2 > => < V1 T2' > if o < T2 > => < T2' > .
+    CbvAppRight,
+    // rl o < (\X. T) V > => < T[V / X] > .
+    CbvBeta,
+    // crl o < T1 T2 > => < T1' T2 > if o < T1 > => < T1' > .
+    CbnAppLeft,
+    // rl o < (\X. T) T2 > => < T[T2 / X] > .
+    CbnBeta,
+}
+
+impl LambdaRule {
+    pub fn rules_for(strategy: Strategy) -> Vec<LambdaRule> {
+        match strategy {
+            Strategy::CallByValue => vec![
+                LambdaRule::CbvAppLeft,
+                LambdaRule::CbvAppRight,
+                LambdaRule::CbvBeta,
+            ],
+            Strategy::CallByName => vec![LambdaRule::CbnAppLeft, LambdaRule::CbnBeta],
+        }
+    }
+}
+
+impl RewriteRule for LambdaRule {
+    type Conf = LambdaConf;
+
+    fn dummy() -> LambdaConf {
+        LambdaConf::Dummy
+    }
+
+    fn is_dummy(conf: &LambdaConf) -> bool {
+        matches!(conf, LambdaConf::Dummy)
+    }
+
+    fn list_of_rules() -> Vec<LambdaRule> {
+        vec![
+            LambdaRule::CbvAppLeft,
+            LambdaRule::CbvAppRight,
+            LambdaRule::CbvBeta,
+            LambdaRule::CbnAppLeft,
+            LambdaRule::CbnBeta,
+        ]
+    }
+
+    fn get_description(&self) -> String {
+        match self {
+            LambdaRule::CbvAppLeft => {
+                "crl o < T1 T2 > => < T1' T2 > if o < T1 > => < T1' >".to_string()
+            }
+            LambdaRule::CbvAppRight => {
+                "crl o < V1 T2 > => < V1 T2' > if o < T2 > => < T2' >".to_string()
+            }
+            LambdaRule::CbvBeta => "rl o < (\\X. T) V > => < T[V / X] >".to_string(),
+            LambdaRule::CbnAppLeft => {
+                "crl o < T1 T2 > => < T1' T2 > if o < T1 > => < T1' >".to_string()
+            }
+            LambdaRule::CbnBeta => "rl o < (\\X. T) T2 > => < T[T2 / X] >".to_string(),
+        }
+    }
+
+    fn get_label(&self) -> String {
+        match self {
+            LambdaRule::CbvAppLeft => "Application Left (by value)".to_string(),
+            LambdaRule::CbvAppRight => "Application Right (by value)".to_string(),
+            LambdaRule::CbvBeta => "Beta (by value)".to_string(),
+            LambdaRule::CbnAppLeft => "Application Left (by name)".to_string(),
+            LambdaRule::CbnBeta => "Beta (by name)".to_string(),
+        }
+    }
+
+    fn get_next_configuration(&self, conf: LambdaConf) -> Option<LambdaConf> {
+        let ret = match self {
+            // crl o < T1 T2 > => < T1' T2 > if o < T1 > => < T1' > .
+            LambdaRule::CbvAppLeft | LambdaRule::CbnAppLeft => match conf {
+                LambdaConf::TermConf(t) => match *t {
+                    Term::App(t1, _t2) => LambdaConf::TermConf(t1),
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // crl o < V1 T2 > => < V1 T2' > if o < T2 > => < T2' > .
+            LambdaRule::CbvAppRight => match conf {
+                LambdaConf::TermConf(t) => match *t {
+                    Term::App(t1, t2) if t1.is_value() => LambdaConf::TermConf(t2),
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // rl o < (\X. T) V > => < T[V / X] > .
+            LambdaRule::CbvBeta => LambdaConf::Dummy,
+            // rl o < (\X. T) T2 > => < T[T2 / X] > .
+            LambdaRule::CbnBeta => LambdaConf::Dummy,
+        };
+        Some(ret)
+    }
+
+    fn reduce_down(&self, bottom: LambdaConf, top: LambdaConf) -> Option<LambdaConf> {
+        let x = match self {
+            // crl o < T1 T2 > => < T1' T2 > if o < T1 > => < T1' > .
+            LambdaRule::CbvAppLeft | LambdaRule::CbnAppLeft => {
+                let new_term = match top {
+                    LambdaConf::TermConf(t) => t,
+                    _ => return None,
+                };
+                match bottom {
+                    LambdaConf::TermConf(t) => match *t {
+                        Term::App(_t1, t2) => {
+                            LambdaConf::TermConf(Box::new(Term::App(new_term, t2)))
+                        }
+                        _ => return None,
+                    },
+                    _ => return None,
+                }
+            }
+            // crl o < V1 T2 > => < V1 T2' > if o < T2 > => < T2' > .
+            LambdaRule::CbvAppRight => {
+                let new_term = match top {
+                    LambdaConf::TermConf(t) => t,
+                    _ => return None,
+                };
+                match bottom {
+                    LambdaConf::TermConf(t) => match *t {
+                        Term::App(t1, _t2) if t1.is_value() => {
+                            LambdaConf::TermConf(Box::new(Term::App(t1, new_term)))
+                        }
+                        _ => return None,
+                    },
+                    _ => return None,
+                }
+            }
+            // rl o < (\X. T) V > => < T[V / X] > .
+            LambdaRule::CbvBeta => match bottom {
+                LambdaConf::TermConf(t) => match *t {
+                    Term::App(t1, t2) if t2.is_value() => match *t1 {
+                        Term::Lam(x, body) => {
+                            LambdaConf::TermConf(Box::new(body.substitute(&x, &t2)))
+                        }
+                        _ => return None,
+                    },
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            // rl o < (\X. T) T2 > => < T[T2 / X] > .
+            LambdaRule::CbnBeta => match bottom {
+                LambdaConf::TermConf(t) => match *t {
+                    Term::App(t1, t2) => match *t1 {
+                        Term::Lam(x, body) => {
+                            LambdaConf::TermConf(Box::new(body.substitute(&x, &t2)))
+                        }
+                        _ => return None,
+                    },
+                    _ => return None,
+                },
+                _ => return None,
+            },
+        };
+        Some(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(s: &str) -> Term {
+        parse(s.to_string()).unwrap()
+    }
+
+    // the stepper after reducing a term with a strategy until it stops
+    fn reduce(s: &str, strategy: Strategy) -> Stack<LambdaRule> {
+        let mut stack = Stack::<LambdaRule>::create_from_string(s.to_string()).unwrap();
+        stack.run(&LambdaRule::rules_for(strategy), 100);
+        stack
+    }
+
+    #[test]
+    fn test_beta() {
+        for strategy in [Strategy::CallByValue, Strategy::CallByName] {
+            let stack = reduce("(\\x. x x) (\\y. y)", strategy);
+            assert_eq!(*stack.top(), LambdaConf::TermConf(Box::new(term("\\y. y"))));
+            assert_eq!(stack.trace().len(), 3);
+        }
+    }
+
+    #[test]
+    fn test_capture() {
+        // the bound y is renamed so that the free y it is applied to stays free
+        let expected = Term::Lam("y'".to_string(), Box::new(Term::Var("y".to_string())));
+        assert_eq!(
+            term("\\y. x").substitute("x", &Term::Var("y".to_string())),
+            expected
+        );
+        assert_eq!(
+            *reduce("(\\x. \\y. x) y", Strategy::CallByName).top(),
+            LambdaConf::TermConf(Box::new(expected))
+        );
+        // a variable is not a value, so by value the application is stuck
+        let stack = reduce("(\\x. \\y. x) y", Strategy::CallByValue);
+        assert_eq!(stack.trace().len(), 1);
+        // a binder shadowing the variable stops the substitution
+        assert_eq!(
+            term("\\x. x").substitute("x", &term("\\z. z")),
+            term("\\x. x")
+        );
+        // no binder is renamed where there is nothing to replace, so none is captured
+        assert_eq!(
+            term("\\y. y").substitute("y'", &Term::Var("y".to_string())),
+            term("\\y. y")
+        );
+        assert_eq!(
+            *reduce("((\\x. \\y. x (\\x. \\y. y)) y) y", Strategy::CallByName).top(),
+            LambdaConf::TermConf(Box::new(term("y (\\x. \\y. y)")))
+        );
+    }
+
+    #[test]
+    fn test_strategies() {
+        // by name the diverging argument is dropped, by value it is evaluated forever
+        let program = "(\\x. \\y. y) ((\\z. z z) (\\z. z z))";
+        let stack = reduce(program, Strategy::CallByName);
+        assert_eq!(*stack.top(), LambdaConf::TermConf(Box::new(term("\\y. y"))));
+        let stack = reduce(program, Strategy::CallByValue);
+        assert!(stack.cycle().is_some());
+        assert_eq!(*stack.top(), LambdaConf::TermConf(Box::new(term(program))));
+        // by value the argument is reduced before it is copied, by name it is copied as it is
+        let program = "(\\x. x x) ((\\y. y) (\\z. z))";
+        assert_eq!(reduce(program, Strategy::CallByValue).trace().len(), 4);
+        assert_eq!(reduce(program, Strategy::CallByName).trace().len(), 5);
+    }
+}
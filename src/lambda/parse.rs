@@ -0,0 +1,86 @@
+use nom::{
+    branch::alt, bytes::complete::tag, character::complete::alpha1,
+    character::complete::multispace0, multi::many1, sequence::delimited, sequence::tuple, IResult,
+};
+
+use crate::lambda::ast::Term;
+
+fn var(input: &str) -> IResult<&str, Term> {
+    let (input, var) = delimited(multispace0, alpha1, multispace0)(input)?;
+    Ok((input, Term::Var(var.to_string())))
+}
+
+fn parenth(input: &str) -> IResult<&str, Term> {
+    delimited(
+        multispace0,
+        delimited(tag("("), term, tag(")")),
+        multispace0,
+    )(input)
+}
+
+fn lambda_sign(input: &str) -> IResult<&str, &str> {
+    delimited(multispace0, alt((tag("\\"), tag("λ"))), multispace0)(input)
+}
+
+fn lam(input: &str) -> IResult<&str, Term> {
+    let (input, (_, x, _, t)) = tuple((
+        lambda_sign,
+        alpha1,
+        delimited(multispace0, tag("."), multispace0),
+        term,
+    ))(input)?;
+    Ok((input, Term::Lam(x.to_string(), Box::new(t))))
+}
+
+fn atom(input: &str) -> IResult<&str, Term> {
+    alt((parenth, lam, var))(input)
+}
+
+// application is left associative: t1 t2 t3 = (t1 t2) t3
+fn app(input: &str) -> IResult<&str, Term> {
+    let (input, terms) = many1(atom)(input)?;
+    Ok((
+        input,
+        terms
+            .into_iter()
+            .reduce(|acc, x| Term::App(Box::new(acc), Box::new(x)))
+            .expect("many1 returns at least one term"),
+    ))
+}
+
+fn term(input: &str) -> IResult<&str, Term> {
+    app(input)
+}
+
+pub fn parse(input: String) -> Option<Term> {
+    match delimited(multispace0, term, multispace0)(&input) {
+        Ok(("", x)) => Some(x),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_app() {
+        match parse("x y z".to_string()) {
+            Some(Term::App(t1, t3)) => match (*t1, *t3) {
+                (Term::App(_, _), Term::Var(z)) if z == "z" => {}
+                _ => panic!(),
+            },
+            _ => panic!(),
+        };
+    }
+    #[test]
+    fn test_lam() {
+        match parse("(\\x. x x) (\\y. y)".to_string()) {
+            Some(Term::App(t1, t2)) => match (*t1, *t2) {
+                (Term::Lam(x, _), Term::Lam(y, _)) if x == "x" && y == "y" => {}
+                _ => panic!(),
+            },
+            _ => panic!(),
+        };
+    }
+}
@@ -2,9 +2,30 @@
 #![cfg_attr(not(debug_assertions), deny(warnings))] // Forbid warnings in release builds
 #![warn(clippy::all, rust_2018_idioms)]
 
+mod absint;
 mod app;
 mod ast;
+mod bmc;
+mod cfg;
+mod cost;
+mod coverage;
+mod dataflow;
+mod derivation;
+mod equiv;
+mod hoare;
+mod lambda;
+mod ltl;
+mod optimize;
 mod parse;
+mod pretty;
+mod sat;
+mod security;
+mod slice;
+mod stack;
+mod symbolic;
+mod typing;
+mod vm;
+mod wp;
 pub use app::TemplateApp;
 
 // ----------------------------------------------------------------------------
@@ -0,0 +1,529 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::ast::BExp;
+use crate::ast::Configuration;
+use crate::ast::Pgm;
+use crate::ast::Rule;
+use crate::stack::RewriteRule;
+use crate::stack::Stack;
+
+// the largest number of configurations explored before giving up
+pub const STATE_LIMIT: usize = 10000;
+
+// LTL over the configurations of a program, with atoms evaluated in their state
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Ltl {
+    True,
+    False,
+    Atom(BExp),
+    Not(Box<Ltl>),
+    And(Box<Ltl>, Box<Ltl>),
+    Or(Box<Ltl>, Box<Ltl>),
+    Implies(Box<Ltl>, Box<Ltl>),
+    Next(Box<Ltl>),
+    Until(Box<Ltl>, Box<Ltl>),
+    Release(Box<Ltl>, Box<Ltl>),
+    Always(Box<Ltl>),
+    Eventually(Box<Ltl>),
+}
+
+impl fmt::Display for Ltl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ltl::True => write!(f, "True"),
+            Ltl::False => write!(f, "False"),
+            Ltl::Atom(b) => write!(f, "{}", b),
+            Ltl::Not(p) => write!(f, "~ {}", p),
+            Ltl::And(p, q) => write!(f, "({} /\\ {})", p, q),
+            Ltl::Or(p, q) => write!(f, "({} \\/ {})", p, q),
+            Ltl::Implies(p, q) => write!(f, "({} -> {})", p, q),
+            Ltl::Next(p) => write!(f, "O {}", p),
+            Ltl::Until(p, q) => write!(f, "({} U {})", p, q),
+            Ltl::Release(p, q) => write!(f, "({} R {})", p, q),
+            Ltl::Always(p) => write!(f, "[] {}", p),
+            Ltl::Eventually(p) => write!(f, "<> {}", p),
+        }
+    }
+}
+
+impl Ltl {
+    // negation normal form, using only atoms, their negations, /\, \/, O, U and R
+    fn nnf(&self, negated: bool) -> Ltl {
+        let b = Box::new;
+        match (self, negated) {
+            (Ltl::True, false) | (Ltl::False, true) => Ltl::True,
+            (Ltl::True, true) | (Ltl::False, false) => Ltl::False,
+            (Ltl::Atom(a), false) => Ltl::Atom(a.clone()),
+            (Ltl::Atom(a), true) => Ltl::Not(b(Ltl::Atom(a.clone()))),
+            (Ltl::Not(p), negated) => p.nnf(!negated),
+            (Ltl::And(p, q), false) | (Ltl::Or(p, q), true) => {
+                Ltl::And(b(p.nnf(negated)), b(q.nnf(negated)))
+            }
+            (Ltl::Or(p, q), false) | (Ltl::And(p, q), true) => {
+                Ltl::Or(b(p.nnf(negated)), b(q.nnf(negated)))
+            }
+            (Ltl::Implies(p, q), negated) => {
+                Ltl::Or(b(Ltl::Not(p.clone())), q.clone()).nnf(negated)
+            }
+            (Ltl::Next(p), negated) => Ltl::Next(b(p.nnf(negated))),
+            (Ltl::Until(p, q), false) | (Ltl::Release(p, q), true) => {
+                Ltl::Until(b(p.nnf(negated)), b(q.nnf(negated)))
+            }
+            (Ltl::Release(p, q), false) | (Ltl::Until(p, q), true) => {
+                Ltl::Release(b(p.nnf(negated)), b(q.nnf(negated)))
+            }
+            // [] p = False R p and <> p = True U p
+            (Ltl::Always(p), negated) => Ltl::Release(b(Ltl::False), p.clone()).nnf(negated),
+            (Ltl::Eventually(p), negated) => Ltl::Until(b(Ltl::True), p.clone()).nnf(negated),
+        }
+    }
+}
+
+fn holds(b: &BExp, conf: &Configuration) -> bool {
+    conf.bindings()
+        .is_some_and(|sigma| b.evaluate(&sigma) == Some(true))
+}
+
+// a node of the generalized Büchi automaton built by the tableau construction of
+// Gerth, Peled, Vardi and Wolper; node 0 is the initial pseudo-node
+#[derive(Clone, Debug)]
+struct Node {
+    incoming: HashSet<usize>,
+    old: HashSet<Ltl>,
+    next: HashSet<Ltl>,
+}
+
+impl Node {
+    // the literals of the node hold in the configuration it reads
+    fn accepts(&self, conf: &Configuration) -> bool {
+        self.old.iter().all(|f| match f {
+            Ltl::Atom(b) => holds(b, conf),
+            Ltl::Not(p) => match &**p {
+                Ltl::Atom(b) => !holds(b, conf),
+                _ => true,
+            },
+            _ => true,
+        })
+    }
+}
+
+fn expand(
+    mut new: Vec<Ltl>,
+    mut old: HashSet<Ltl>,
+    mut next: HashSet<Ltl>,
+    incoming: HashSet<usize>,
+    nodes: &mut Vec<Node>,
+) {
+    let f = match new.pop() {
+        None => {
+            match nodes.iter_mut().find(|n| n.old == old && n.next == next) {
+                Some(n) => n.incoming.extend(incoming),
+                None => {
+                    nodes.push(Node {
+                        incoming,
+                        old,
+                        next: next.clone(),
+                    });
+                    let id = nodes.len();
+                    expand(
+                        next.into_iter().collect(),
+                        HashSet::new(),
+                        HashSet::new(),
+                        vec![id].into_iter().collect(),
+                        nodes,
+                    );
+                }
+            }
+            return;
+        }
+        Some(f) if old.contains(&f) => return expand(new, old, next, incoming, nodes),
+        Some(f) => f,
+    };
+    old.insert(f.clone());
+    match f {
+        Ltl::False => (),
+        Ltl::True => expand(new, old, next, incoming, nodes),
+        Ltl::Atom(ref b) => {
+            if !old.contains(&Ltl::Not(Box::new(Ltl::Atom(b.clone())))) {
+                expand(new, old, next, incoming, nodes)
+            }
+        }
+        Ltl::Not(ref p) => {
+            if !old.contains(p) {
+                expand(new, old, next, incoming, nodes)
+            }
+        }
+        Ltl::And(p, q) => {
+            new.push(*p);
+            new.push(*q);
+            expand(new, old, next, incoming, nodes)
+        }
+        Ltl::Or(p, q) => {
+            let mut new2 = new.clone();
+            new.push(*p);
+            new2.push(*q);
+            expand(new, old.clone(), next.clone(), incoming.clone(), nodes);
+            expand(new2, old, next, incoming, nodes)
+        }
+        Ltl::Next(p) => {
+            next.insert(*p);
+            expand(new, old, next, incoming, nodes)
+        }
+        // p U q is q, or p now and p U q next
+        Ltl::Until(ref p, ref q) => {
+            let mut new2 = new.clone();
+            new.push((**p).clone());
+            let mut next1 = next.clone();
+            next1.insert(f.clone());
+            new2.push((**q).clone());
+            expand(new, old.clone(), next1, incoming.clone(), nodes);
+            expand(new2, old, next, incoming, nodes)
+        }
+        // p R q is q and p, or q now and p R q next
+        Ltl::Release(ref p, ref q) => {
+            let mut new2 = new.clone();
+            new.push((**q).clone());
+            let mut next1 = next.clone();
+            next1.insert(f.clone());
+            new2.push((**p).clone());
+            new2.push((**q).clone());
+            expand(new, old.clone(), next1, incoming.clone(), nodes);
+            expand(new2, old, next, incoming, nodes)
+        }
+        _ => unreachable!("the formula is in negation normal form"),
+    }
+}
+
+fn untils(f: &Ltl, acc: &mut Vec<Ltl>) {
+    match f {
+        Ltl::Not(p) | Ltl::Next(p) | Ltl::Always(p) | Ltl::Eventually(p) => untils(p, acc),
+        Ltl::And(p, q) | Ltl::Or(p, q) | Ltl::Implies(p, q) | Ltl::Release(p, q) => {
+            untils(p, acc);
+            untils(q, acc);
+        }
+        Ltl::Until(p, q) => {
+            if !acc.contains(f) {
+                acc.push(f.clone());
+            }
+            untils(p, acc);
+            untils(q, acc);
+        }
+        _ => (),
+    }
+}
+
+// the reachable configurations, where a final or stuck configuration steps to itself
+struct Graph {
+    confs: Vec<Configuration>,
+    edges: Vec<Vec<(usize, Vec<Rule>)>>,
+}
+
+fn explore(pgm: &Pgm) -> Result<Graph, String> {
+    let rules = Rule::list_of_rules();
+    let init = Configuration::PgmConf(Box::new(pgm.clone()));
+    let mut ids: HashMap<Configuration, usize> = HashMap::new();
+    ids.insert(init.clone(), 0);
+    let mut graph = Graph {
+        confs: vec![init],
+        edges: vec![],
+    };
+    let mut i = 0;
+    while i < graph.confs.len() {
+        let mut edges = vec![];
+        for (chain, conf) in Stack::<Rule>::create(graph.confs[i].clone()).successors(&rules) {
+            let j = match ids.get(&conf) {
+                Some(j) => *j,
+                None => {
+                    if graph.confs.len() == STATE_LIMIT {
+                        return Err(format!(
+                            "More than {} reachable configurations",
+                            STATE_LIMIT
+                        ));
+                    }
+                    ids.insert(conf.clone(), graph.confs.len());
+                    graph.confs.push(conf);
+                    graph.confs.len() - 1
+                }
+            };
+            if !edges.iter().any(|(k, _chain)| *k == j) {
+                edges.push((j, chain));
+            }
+        }
+        if edges.is_empty() {
+            edges.push((i, vec![]));
+        }
+        graph.edges.push(edges);
+        i += 1;
+    }
+    Ok(graph)
+}
+
+// the strongly connected components, numbered by Kosaraju's algorithm
+fn components(succ: &[Vec<usize>]) -> Vec<usize> {
+    let n = succ.len();
+    let mut order = vec![];
+    let mut seen = vec![false; n];
+    for root in 0..n {
+        if seen[root] {
+            continue;
+        }
+        seen[root] = true;
+        let mut stack = vec![(root, 0)];
+        while let Some((v, i)) = stack.pop() {
+            match succ[v].get(i) {
+                Some(&w) => {
+                    stack.push((v, i + 1));
+                    if !seen[w] {
+                        seen[w] = true;
+                        stack.push((w, 0));
+                    }
+                }
+                None => order.push(v),
+            }
+        }
+    }
+    let mut pred = vec![vec![]; n];
+    for (v, ws) in succ.iter().enumerate() {
+        for &w in ws {
+            pred[w].push(v);
+        }
+    }
+    let mut comp = vec![usize::MAX; n];
+    for (c, &root) in order.iter().rev().enumerate() {
+        if comp[root] != usize::MAX {
+            continue;
+        }
+        comp[root] = c;
+        let mut stack = vec![root];
+        while let Some(v) = stack.pop() {
+            for &w in &pred[v] {
+                if comp[w] == usize::MAX {
+                    comp[w] = c;
+                    stack.push(w);
+                }
+            }
+        }
+    }
+    comp
+}
+
+// a shortest path of at least one step from one of the sources to a target, staying inside allowed
+fn path(
+    succ: &[Vec<usize>],
+    sources: &[usize],
+    allowed: impl Fn(usize) -> bool,
+    target: impl Fn(usize) -> bool,
+) -> Option<Vec<usize>> {
+    let mut parent: HashMap<usize, Option<usize>> = HashMap::new();
+    let mut queue = VecDeque::new();
+    for &s in sources {
+        for &w in &succ[s] {
+            if allowed(w) && !parent.contains_key(&w) {
+                parent.insert(w, Some(s));
+                queue.push_back(w);
+            }
+        }
+    }
+    while let Some(v) = queue.pop_front() {
+        if target(v) {
+            let mut p = vec![v];
+            let mut v = v;
+            while let Some(Some(u)) = parent.get(&v) {
+                p.push(*u);
+                if sources.contains(u) {
+                    break;
+                }
+                v = *u;
+            }
+            p.reverse();
+            return Some(p);
+        }
+        for &w in &succ[v] {
+            if allowed(w) && !parent.contains_key(&w) {
+                parent.insert(w, Some(v));
+                queue.push_back(w);
+            }
+        }
+    }
+    None
+}
+
+// an infinite run violating the formula: the configurations of the prefix followed by the loop,
+// which starts at loop_start and returns to it, with the rules deriving every step
+#[derive(Clone, Debug)]
+pub struct Counterexample {
+    confs: Vec<Configuration>,
+    chains: Vec<Vec<Rule>>,
+    loop_start: usize,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, conf) in self.confs.iter().enumerate() {
+            if i == self.loop_start {
+                writeln!(f, "-- loop starts here --")?;
+            }
+            writeln!(f, "{}: {}", i, conf)?;
+        }
+        write!(f, "-- back to {} --", self.loop_start)
+    }
+}
+
+impl Counterexample {
+    // the run replayed in the stepper, going once around the loop
+    pub fn to_stack(&self) -> Stack<Rule> {
+        let mut stack = Stack::create(self.confs[0].clone());
+        for rule in self.chains.iter().flatten() {
+            stack.apply_rule(rule.clone());
+        }
+        stack
+    }
+}
+
+// None if every run of the program satisfies the formula, otherwise a counterexample
+pub fn check(pgm: &Pgm, formula: &Ltl) -> Result<Option<Counterexample>, String> {
+    let graph = explore(pgm)?;
+    let negation = formula.nnf(true);
+    let mut nodes = vec![];
+    expand(
+        vec![negation.clone()],
+        HashSet::new(),
+        HashSet::new(),
+        vec![0].into_iter().collect(),
+        &mut nodes,
+    );
+    let mut goals = vec![];
+    untils(&negation, &mut goals);
+    // node n is in acceptance set i if it fulfils or does not promise the i-th until
+    let accepting: Vec<Vec<bool>> = goals
+        .iter()
+        .map(|u| match u {
+            Ltl::Until(_p, q) => nodes
+                .iter()
+                .map(|n| n.old.contains(q) || !n.old.contains(u))
+                .collect(),
+            _ => unreachable!(),
+        })
+        .collect();
+
+    // the product of the configuration graph and the automaton
+    let mut ids: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut states = vec![];
+    let mut initial = vec![];
+    for (n, node) in nodes.iter().enumerate() {
+        if node.incoming.contains(&0) && node.accepts(&graph.confs[0]) {
+            ids.insert((0, n), states.len());
+            initial.push(states.len());
+            states.push((0, n));
+        }
+    }
+    let mut succ: Vec<Vec<usize>> = vec![];
+    let mut i = 0;
+    while i < states.len() {
+        let (c, n) = states[i];
+        let mut ws = vec![];
+        for (d, _chain) in &graph.edges[c] {
+            for (m, node) in nodes.iter().enumerate() {
+                if node.incoming.contains(&(n + 1)) && node.accepts(&graph.confs[*d]) {
+                    let w = *ids.entry((*d, m)).or_insert_with(|| {
+                        states.push((*d, m));
+                        states.len() - 1
+                    });
+                    ws.push(w);
+                }
+            }
+        }
+        succ.push(ws);
+        i += 1;
+    }
+
+    // every product state is reachable, so a violation is a cyclic component meeting every acceptance set
+    let comp = components(&succ);
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (v, c) in comp.iter().enumerate() {
+        members.entry(*c).or_default().push(v);
+    }
+    let fair = members.values().find(|vs| {
+        let cyclic = vs.len() > 1 || succ[vs[0]].contains(&vs[0]);
+        cyclic
+            && accepting
+                .iter()
+                .all(|set| vs.iter().any(|&w| set[states[w].1]))
+    });
+    let start = match fair {
+        Some(vs) => vs[0],
+        None => return Ok(None),
+    };
+
+    let mut run = if initial.contains(&start) {
+        vec![start]
+    } else {
+        path(&succ, &initial, |_w| true, |w| w == start).expect("start is reachable")
+    };
+    let loop_start = run.len() - 1;
+    let in_comp = |w: usize| comp[w] == comp[start];
+    for set in &accepting {
+        let here = *run.last().expect("the run is not empty");
+        if set[states[here].1] {
+            continue;
+        }
+        let p = path(&succ, &[here], in_comp, |w| set[states[w].1]).expect("the component is fair");
+        run.extend(p.into_iter().skip(1));
+    }
+    let here = *run.last().expect("the run is not empty");
+    let back = path(&succ, &[here], in_comp, |w| w == start).expect("the component is cyclic");
+    run.extend(back.into_iter().skip(1));
+    // the last state is the loop start again, which the display marks instead
+    run.pop();
+
+    let confs: Vec<usize> = run.iter().map(|&v| states[v].0).collect();
+    let chains = confs
+        .windows(2)
+        .map(|w| {
+            let (_d, chain) = graph.edges[w[0]]
+                .iter()
+                .find(|(d, _chain)| *d == w[1])
+                .expect("consecutive configurations are connected");
+            chain.clone()
+        })
+        .collect();
+    Ok(Some(Counterexample {
+        confs: confs.iter().map(|&c| graph.confs[c].clone()).collect(),
+        chains,
+        loop_start,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+    use crate::parse::parse_ltl;
+
+    fn check_str(pgm: &str, f: &str) -> Option<Counterexample> {
+        check(
+            &parse(pgm.to_string()).unwrap(),
+            &parse_ltl(f.to_string()).unwrap(),
+        )
+        .unwrap()
+    }
+    #[test]
+    fn test_safety() {
+        let pgm = "int x; while (x <= 9) { x = x + 1; }";
+        assert!(check_str(pgm, "[] (x <= 10)").is_none());
+        let cex = check_str(pgm, "[] (x <= 5)").unwrap();
+        assert!(cex.to_string().contains("0 |-> 6"));
+        let stack = cex.to_stack();
+        assert_eq!(stack.top(), cex.confs.last().unwrap());
+    }
+    #[test]
+    fn test_liveness() {
+        let pgm = "int x, y; { x = 1; } [] { while (true) { y = 1; } }";
+        assert!(check_str(pgm, "<> (1 <= y) \\/ <> (1 <= x)").is_none());
+        let cex = check_str(pgm, "<> (1 <= x)").unwrap();
+        assert!(cex.loop_start > 0);
+    }
+}
@@ -0,0 +1,463 @@
+use std::fmt;
+
+use crate::ast::AExp;
+use crate::ast::BExp;
+use crate::ast::Block;
+use crate::ast::Pgm;
+use crate::ast::Span;
+use crate::ast::Stmt;
+
+// rounds of all passes before giving up on reaching a fixpoint
+pub const ROUNDS: usize = 10;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Rewrite {
+    pub pass: &'static str,
+    pub before: String,
+    pub after: String,
+}
+
+impl fmt::Display for Rewrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {} => {}", self.pass, self.before, self.after)
+    }
+}
+
+fn one_line<T: fmt::Display>(t: &T) -> String {
+    t.to_string()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn block_stmt(b: Block) -> Stmt {
+    Stmt::StmtBlock(Box::new(b), Span::default())
+}
+
+fn empty() -> Stmt {
+    block_stmt(Block::EmptyBlock)
+}
+
+// the variables a statement may assign
+fn assigned(s: &Stmt) -> Vec<String> {
+    let blocks = |bs: &[&Block]| {
+        bs.iter()
+            .flat_map(|b| match b {
+                Block::EmptyBlock => vec![],
+                Block::BlockStmt(s, _) => assigned(s),
+            })
+            .collect::<Vec<String>>()
+    };
+    match s {
+        Stmt::StmtBlock(b, _) => blocks(&[b]),
+        Stmt::Assign(x, _, _) | Stmt::New(x, _) => vec![x.clone()],
+        Stmt::Sequence(s1, s2, _) => [assigned(s1), assigned(s2)].concat(),
+        Stmt::IfThenElse(_, b1, b2, _) | Stmt::Choice(b1, b2, _) => blocks(&[b1, b2]),
+        Stmt::IfThen(_b, b1, _) | Stmt::While(_b, b1, _) | Stmt::DoWhile(b1, _b, _) => {
+            blocks(&[b1])
+        }
+        Stmt::TryCatch(b1, x, b2, _) => [vec![x.clone()], blocks(&[b1, b2])].concat(),
+        Stmt::For(s1, _b, s2, b1, _) => [assigned(s1), assigned(s2), blocks(&[b1])].concat(),
+        Stmt::Throw(_, _) | Stmt::AssignDeref(_, _, _) => vec![],
+    }
+}
+
+// a pointer assignment may write any variable
+fn writes_memory(s: &Stmt) -> bool {
+    match s {
+        Stmt::AssignDeref(_, _, _) => true,
+        Stmt::StmtBlock(b, _) => writes_memory_block(b),
+        Stmt::Sequence(s1, s2, _) => writes_memory(s1) || writes_memory(s2),
+        Stmt::IfThenElse(_, b1, b2, _) | Stmt::Choice(b1, b2, _) | Stmt::TryCatch(b1, _, b2, _) => {
+            writes_memory_block(b1) || writes_memory_block(b2)
+        }
+        Stmt::IfThen(_, b1, _) | Stmt::While(_, b1, _) | Stmt::DoWhile(b1, _, _) => {
+            writes_memory_block(b1)
+        }
+        Stmt::For(s1, _, s2, b1, _) => {
+            writes_memory(s1) || writes_memory(s2) || writes_memory_block(b1)
+        }
+        _ => false,
+    }
+}
+
+fn writes_memory_block(b: &Block) -> bool {
+    match b {
+        Block::EmptyBlock => false,
+        Block::BlockStmt(s, _) => writes_memory(s),
+    }
+}
+
+// x |-> a for x = a ; where a is a variable or a constant
+type Copies = Vec<(String, AExp)>;
+
+fn kill(copies: &mut Copies, xs: &[String]) {
+    copies.retain(|(x, a)| !xs.contains(x) && !a.vars().iter().any(|y| xs.contains(y)));
+}
+
+fn meet(c1: Copies, c2: &[(String, AExp)]) -> Copies {
+    c1.into_iter().filter(|copy| c2.contains(copy)).collect()
+}
+
+#[derive(Default)]
+struct Optimizer {
+    log: Vec<Rewrite>,
+}
+
+impl Optimizer {
+    fn record<T: fmt::Display + PartialEq>(&mut self, pass: &'static str, before: &T, after: &T) {
+        if before != after {
+            self.log.push(Rewrite {
+                pass,
+                before: one_line(before),
+                after: one_line(after),
+            });
+        }
+    }
+
+    // constant folding, the arithmetic of AExp::simplify but keeping every && since the
+    // semantics has no rules for it and dropping one would let a stuck program finish
+    fn fold_bexp(b: &BExp) -> BExp {
+        match b {
+            BExp::LessThanEq(a1, a2, span) => match (a1.simplify(), a2.simplify()) {
+                (AExp::Int(i1, _), AExp::Int(i2, _)) => BExp::Bool(i1 <= i2, *span),
+                (a1, a2) => BExp::LessThanEq(Box::new(a1), Box::new(a2), *span),
+            },
+            BExp::Negation(b, span) => match Optimizer::fold_bexp(b) {
+                BExp::Bool(b, _) => BExp::Bool(!b, *span),
+                b => BExp::Negation(Box::new(b), *span),
+            },
+            BExp::And(b1, b2, span) => BExp::And(
+                Box::new(Optimizer::fold_bexp(b1)),
+                Box::new(Optimizer::fold_bexp(b2)),
+                *span,
+            ),
+            BExp::Bool(b, span) => BExp::Bool(*b, *span),
+        }
+    }
+    fn fold_a(&mut self, a: &AExp) -> Box<AExp> {
+        let folded = a.simplify();
+        self.record("constant folding", a, &folded);
+        Box::new(folded)
+    }
+    fn fold_b(&mut self, b: &BExp) -> Box<BExp> {
+        let folded = Optimizer::fold_bexp(b);
+        self.record("constant folding", b, &folded);
+        Box::new(folded)
+    }
+    fn fold(&mut self, s: &Stmt) -> Stmt {
+        match s {
+            Stmt::StmtBlock(b, _) => block_stmt(self.fold_block(b)),
+            Stmt::Assign(x, a, span) => Stmt::Assign(x.clone(), self.fold_a(a), *span),
+            Stmt::Sequence(s1, s2, span) => {
+                Stmt::Sequence(Box::new(self.fold(s1)), Box::new(self.fold(s2)), *span)
+            }
+            Stmt::IfThenElse(b, b1, b2, span) => Stmt::IfThenElse(
+                self.fold_b(b),
+                Box::new(self.fold_block(b1)),
+                Box::new(self.fold_block(b2)),
+                *span,
+            ),
+            Stmt::While(b, b1, span) => {
+                Stmt::While(self.fold_b(b), Box::new(self.fold_block(b1)), *span)
+            }
+            Stmt::Choice(b1, b2, span) => Stmt::Choice(
+                Box::new(self.fold_block(b1)),
+                Box::new(self.fold_block(b2)),
+                *span,
+            ),
+            Stmt::Throw(a, span) => Stmt::Throw(self.fold_a(a), *span),
+            Stmt::TryCatch(b1, x, b2, span) => Stmt::TryCatch(
+                Box::new(self.fold_block(b1)),
+                x.clone(),
+                Box::new(self.fold_block(b2)),
+                *span,
+            ),
+            Stmt::New(x, span) => Stmt::New(x.clone(), *span),
+            Stmt::AssignDeref(a1, a2, span) => {
+                Stmt::AssignDeref(self.fold_a(a1), self.fold_a(a2), *span)
+            }
+            Stmt::IfThen(b, b1, span) => {
+                Stmt::IfThen(self.fold_b(b), Box::new(self.fold_block(b1)), *span)
+            }
+            Stmt::DoWhile(b1, b, span) => {
+                Stmt::DoWhile(Box::new(self.fold_block(b1)), self.fold_b(b), *span)
+            }
+            Stmt::For(s1, b, s2, b1, span) => Stmt::For(
+                Box::new(self.fold(s1)),
+                self.fold_b(b),
+                Box::new(self.fold(s2)),
+                Box::new(self.fold_block(b1)),
+                *span,
+            ),
+        }
+    }
+    fn fold_block(&mut self, b: &Block) -> Block {
+        match b {
+            Block::EmptyBlock => Block::EmptyBlock,
+            Block::BlockStmt(s, span) => Block::BlockStmt(Box::new(self.fold(s)), *span),
+        }
+    }
+
+    // dead-branch elimination of conditions that are already constant
+    fn prune(&mut self, s: &Stmt) -> Stmt {
+        let pruned = match s {
+            Stmt::IfThenElse(b, b1, b2, span) => match **b {
+                BExp::Bool(true, _) => block_stmt(self.prune_block(b1)),
+                BExp::Bool(false, _) => block_stmt(self.prune_block(b2)),
+                _ => Stmt::IfThenElse(
+                    b.clone(),
+                    Box::new(self.prune_block(b1)),
+                    Box::new(self.prune_block(b2)),
+                    *span,
+                ),
+            },
+            Stmt::IfThen(b, b1, span) => match **b {
+                BExp::Bool(true, _) => block_stmt(self.prune_block(b1)),
+                BExp::Bool(false, _) => empty(),
+                _ => Stmt::IfThen(b.clone(), Box::new(self.prune_block(b1)), *span),
+            },
+            Stmt::While(b, b1, span) => match **b {
+                BExp::Bool(false, _) => empty(),
+                _ => Stmt::While(b.clone(), Box::new(self.prune_block(b1)), *span),
+            },
+            Stmt::DoWhile(b1, b, span) => match **b {
+                BExp::Bool(false, _) => block_stmt(self.prune_block(b1)),
+                _ => Stmt::DoWhile(Box::new(self.prune_block(b1)), b.clone(), *span),
+            },
+            Stmt::For(s1, b, s2, b1, span) => match **b {
+                BExp::Bool(false, _) => self.prune(s1),
+                _ => Stmt::For(
+                    Box::new(self.prune(s1)),
+                    b.clone(),
+                    Box::new(self.prune(s2)),
+                    Box::new(self.prune_block(b1)),
+                    *span,
+                ),
+            },
+            Stmt::StmtBlock(b, _) => return block_stmt(self.prune_block(b)),
+            Stmt::Sequence(s1, s2, span) => {
+                return Stmt::Sequence(Box::new(self.prune(s1)), Box::new(self.prune(s2)), *span)
+            }
+            Stmt::Choice(b1, b2, span) => {
+                return Stmt::Choice(
+                    Box::new(self.prune_block(b1)),
+                    Box::new(self.prune_block(b2)),
+                    *span,
+                )
+            }
+            Stmt::TryCatch(b1, x, b2, span) => {
+                return Stmt::TryCatch(
+                    Box::new(self.prune_block(b1)),
+                    x.clone(),
+                    Box::new(self.prune_block(b2)),
+                    *span,
+                )
+            }
+            s => return s.clone(),
+        };
+        if !matches!(
+            (s, &pruned),
+            (Stmt::IfThenElse(_, _, _, _), Stmt::IfThenElse(_, _, _, _))
+                | (Stmt::IfThen(_, _, _), Stmt::IfThen(_, _, _))
+                | (Stmt::While(_, _, _), Stmt::While(_, _, _))
+                | (Stmt::DoWhile(_, _, _), Stmt::DoWhile(_, _, _))
+                | (Stmt::For(_, _, _, _, _), Stmt::For(_, _, _, _, _))
+        ) {
+            self.record("dead-branch elimination", s, &pruned);
+        }
+        pruned
+    }
+    fn prune_block(&mut self, b: &Block) -> Block {
+        match b {
+            Block::EmptyBlock => Block::EmptyBlock,
+            Block::BlockStmt(s, span) => Block::BlockStmt(Box::new(self.prune(s)), *span),
+        }
+    }
+
+    // copy propagation forwards through the program, copies holds at the start of s
+    // and is updated to what holds at its end
+    fn substitute_a(copies: &[(String, AExp)], a: &AExp) -> AExp {
+        copies
+            .iter()
+            .fold(a.clone(), |a, (x, c)| a.substitute(x, c))
+    }
+    fn substitute_b(copies: &[(String, AExp)], b: &BExp) -> BExp {
+        copies
+            .iter()
+            .fold(b.clone(), |b, (x, c)| b.substitute(x, c))
+    }
+    fn propagate_b(&mut self, copies: &[(String, AExp)], b: &BExp) -> Box<BExp> {
+        let after = Optimizer::substitute_b(copies, b);
+        self.record("copy propagation", b, &after);
+        Box::new(after)
+    }
+    fn propagate(&mut self, copies: &mut Copies, s: &Stmt) -> Stmt {
+        match s {
+            Stmt::StmtBlock(b, _) => block_stmt(self.propagate_block(copies, b)),
+            Stmt::Assign(x, a, span) => {
+                let after = Stmt::Assign(
+                    x.clone(),
+                    Box::new(Optimizer::substitute_a(copies, a)),
+                    *span,
+                );
+                self.record("copy propagation", s, &after);
+                kill(copies, std::slice::from_ref(x));
+                if let Stmt::Assign(x, a, _) = &after {
+                    match &**a {
+                        AExp::Id(y, _) if y != x => copies.push((x.clone(), *a.clone())),
+                        AExp::Int(_i, _) => copies.push((x.clone(), *a.clone())),
+                        _ => (),
+                    }
+                }
+                after
+            }
+            Stmt::Sequence(s1, s2, span) => {
+                let s1 = self.propagate(copies, s1);
+                let s2 = self.propagate(copies, s2);
+                Stmt::Sequence(Box::new(s1), Box::new(s2), *span)
+            }
+            Stmt::IfThenElse(b, b1, b2, span) => {
+                let b = self.propagate_b(copies, b);
+                let mut copies2 = copies.clone();
+                let b1 = self.propagate_block(copies, b1);
+                let b2 = self.propagate_block(&mut copies2, b2);
+                *copies = meet(copies.clone(), &copies2);
+                Stmt::IfThenElse(b, Box::new(b1), Box::new(b2), *span)
+            }
+            Stmt::IfThen(b, b1, span) => {
+                let b = self.propagate_b(copies, b);
+                let copies2 = copies.clone();
+                let b1 = self.propagate_block(copies, b1);
+                *copies = meet(copies.clone(), &copies2);
+                Stmt::IfThen(b, Box::new(b1), *span)
+            }
+            Stmt::Choice(b1, b2, span) => {
+                let mut copies2 = copies.clone();
+                let b1 = self.propagate_block(copies, b1);
+                let b2 = self.propagate_block(&mut copies2, b2);
+                *copies = meet(copies.clone(), &copies2);
+                Stmt::Choice(Box::new(b1), Box::new(b2), *span)
+            }
+            // only the copies that no iteration can change hold at the head of a loop
+            Stmt::While(b, b1, span) => {
+                self.kill_loop(copies, s);
+                let b = self.propagate_b(copies, b);
+                let b1 = self.propagate_block(&mut copies.clone(), b1);
+                Stmt::While(b, Box::new(b1), *span)
+            }
+            Stmt::DoWhile(b1, b, span) => {
+                self.kill_loop(copies, s);
+                let b1 = self.propagate_block(&mut copies.clone(), b1);
+                let b = self.propagate_b(copies, b);
+                Stmt::DoWhile(Box::new(b1), b, *span)
+            }
+            Stmt::For(s1, b, s2, b1, span) => {
+                let s1 = self.propagate(copies, s1);
+                self.kill_loop(copies, s);
+                let b = self.propagate_b(copies, b);
+                let mut body = copies.clone();
+                let b1 = self.propagate_block(&mut body, b1);
+                let s2 = self.propagate(&mut body, s2);
+                Stmt::For(Box::new(s1), b, Box::new(s2), Box::new(b1), *span)
+            }
+            // a throw may leave the try block anywhere
+            Stmt::TryCatch(b1, x, b2, span) => {
+                copies.clear();
+                let b1 = self.propagate_block(copies, b1);
+                copies.clear();
+                let b2 = self.propagate_block(copies, b2);
+                copies.clear();
+                Stmt::TryCatch(Box::new(b1), x.clone(), Box::new(b2), *span)
+            }
+            Stmt::Throw(a, span) => {
+                let after = Stmt::Throw(Box::new(Optimizer::substitute_a(copies, a)), *span);
+                self.record("copy propagation", s, &after);
+                after
+            }
+            Stmt::New(x, _) => {
+                kill(copies, std::slice::from_ref(x));
+                s.clone()
+            }
+            Stmt::AssignDeref(a1, a2, span) => {
+                let after = Stmt::AssignDeref(
+                    Box::new(Optimizer::substitute_a(copies, a1)),
+                    Box::new(Optimizer::substitute_a(copies, a2)),
+                    *span,
+                );
+                self.record("copy propagation", s, &after);
+                copies.clear();
+                after
+            }
+        }
+    }
+    fn kill_loop(&mut self, copies: &mut Copies, s: &Stmt) {
+        if writes_memory(s) {
+            copies.clear();
+        }
+        kill(copies, &assigned(s));
+    }
+    fn propagate_block(&mut self, copies: &mut Copies, b: &Block) -> Block {
+        match b {
+            Block::EmptyBlock => Block::EmptyBlock,
+            Block::BlockStmt(s, span) => {
+                Block::BlockStmt(Box::new(self.propagate(copies, s)), *span)
+            }
+        }
+    }
+}
+
+// copy propagation, constant folding and dead-branch elimination in turn until nothing changes
+pub fn optimize(pgm: &Pgm) -> (Pgm, Vec<Rewrite>) {
+    let Pgm::Program(xl, levels, s) = pgm;
+    let mut optimizer = Optimizer::default();
+    let mut s = s.clone();
+    for _round in 0..ROUNDS {
+        let before = s.clone();
+        s = optimizer.propagate(&mut vec![], &s);
+        s = optimizer.fold(&s);
+        s = optimizer.prune(&s);
+        if s == before {
+            break;
+        }
+    }
+    (Pgm::Program(xl.clone(), levels.clone(), s), optimizer.log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::equiv;
+    use crate::parse::parse;
+
+    fn optimized(s: &str) -> (Pgm, Pgm, Vec<Rewrite>) {
+        let pgm = parse(s.to_string()).unwrap();
+        let (opt, log) = optimize(&pgm);
+        assert!(equiv::check(&pgm, &opt).unwrap().is_none());
+        (pgm, opt, log)
+    }
+    #[test]
+    fn test_passes() {
+        let (_pgm, opt, log) = optimized(
+            "int x, y, z; x = 1; y = x; if (y <= 0) { z = 1; } else { z = y + 2; } while (1 <= 0) { z = 3; }",
+        );
+        let text = opt.to_string();
+        assert!(!text.contains("if") && !text.contains("while"));
+        assert!(text.contains("z = 3 ;"));
+        assert!(log.contains(&Rewrite {
+            pass: "constant folding",
+            before: "(1 <= 0)".to_string(),
+            after: "false".to_string()
+        }));
+        assert!(log.iter().any(|r| r.pass == "dead-branch elimination"));
+    }
+    #[test]
+    fn test_loops() {
+        let (pgm, opt, _log) =
+            optimized("int x, y; y = 1; while (x <= 3) { x = x + y; y = x; } x = y;");
+        assert_eq!(pgm, opt);
+        // a division by zero must still get stuck
+        let (_pgm, opt, _log) = optimized("int x, y; y = 0; x = 1 / y;");
+        assert!(opt.to_string().contains("1 / 0"));
+    }
+}
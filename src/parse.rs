@@ -1,48 +1,246 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Range;
+
 use nom::{
-    branch::alt, bytes::complete::tag, character::complete::alpha1, character::complete::digit1,
-    character::complete::multispace0, multi::many0, multi::separated_list1, sequence::delimited,
-    sequence::preceded, sequence::separated_pair, sequence::terminated, sequence::tuple, IResult,
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::alpha1,
+    character::complete::digit1,
+    character::complete::multispace0,
+    character::complete::multispace1,
+    combinator::{cut, eof, map, not, opt},
+    error::{context, ContextError, ErrorKind},
+    multi::many0,
+    multi::separated_list1,
+    sequence::delimited,
+    sequence::pair,
+    sequence::preceded,
+    sequence::separated_pair,
+    sequence::terminated,
+    sequence::tuple,
+    IResult,
 };
+use nom_locate::LocatedSpan;
 
 use crate::ast::AExp;
 use crate::ast::BExp;
 use crate::ast::Block;
+use crate::ast::Level;
 use crate::ast::Pgm;
+use crate::ast::Span;
 use crate::ast::Stmt;
+use crate::ltl::Ltl;
+
+// the rest of the program together with its byte offset from the start
+type Input<'a> = LocatedSpan<&'a str>;
+
+// the offset of the next token, past any whitespace
+fn token_offset(input: Input<'_>) -> usize {
+    let rest = input.fragment();
+    input.location_offset() + rest.len() - rest.trim_start().len()
+}
+
+// where parsing stopped and what could have come next there; of two alternatives
+// the one that got further wins, and when both stopped at the same place their expectations merge
+#[derive(Clone, PartialEq, Debug)]
+struct Failure {
+    offset: usize,
+    expected: Vec<&'static str>,
+    // the token there was read but is not valid, which no enclosing context can describe better
+    invalid: bool,
+}
+
+impl<'a> nom::error::ParseError<Input<'a>> for Failure {
+    fn from_error_kind(input: Input<'a>, _kind: ErrorKind) -> Self {
+        Failure {
+            offset: token_offset(input),
+            expected: vec![],
+            invalid: false,
+        }
+    }
+    fn append(_input: Input<'a>, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+    fn or(self, other: Self) -> Self {
+        match self.offset.cmp(&other.offset) {
+            Ordering::Greater => self,
+            Ordering::Less => other,
+            Ordering::Equal => {
+                let mut merged = self;
+                for e in other.expected {
+                    if !merged.expected.contains(&e) {
+                        merged.expected.push(e);
+                    }
+                }
+                merged
+            }
+        }
+    }
+}
+
+// a failure inside the context is named after it, unless something more precise got further
+// or the token it stopped at was invalid
+impl<'a> ContextError<Input<'a>> for Failure {
+    fn add_context(input: Input<'a>, ctx: &'static str, other: Self) -> Self {
+        let offset = token_offset(input);
+        if !other.invalid && (other.expected.is_empty() || other.offset <= offset) {
+            Failure {
+                offset,
+                expected: vec![ctx],
+                invalid: false,
+            }
+        } else {
+            other
+        }
+    }
+}
+
+type Res<'a, T> = IResult<Input<'a>, T, Failure>;
+
+// the bytes a parser consumed, without the whitespace around them
+fn spanned<'a, T>(
+    mut parser: impl FnMut(Input<'a>) -> Res<'a, T>,
+) -> impl FnMut(Input<'a>) -> Res<'a, (T, Span)> {
+    move |input| {
+        let (input, _) = multispace0(input)?;
+        let (rest, t) = parser(input)?;
+        let start = input.location_offset();
+        let consumed = &input.fragment()[..rest.location_offset() - start];
+        let end = start + consumed.trim_end().len();
+        Ok((rest, (t, Span { start, end })))
+    }
+}
+
+// a program that does not parse: where, what was expected and what was found instead
+#[derive(Clone, PartialEq, Debug)]
+pub struct ParseError {
+    // the bytes of the token found, empty at the end of the input
+    pub span: Range<usize>,
+    pub line: usize,
+    pub column: usize,
+    pub expected: Vec<String>,
+    pub found: String,
+    // the line of the program the error is on
+    pub text: String,
+}
 
-fn parenth(input: &str) -> IResult<&str, AExp> {
+impl ParseError {
+    fn new(source: &str, failure: Failure) -> ParseError {
+        let start = failure.offset;
+        let rest = &source[start..];
+        let len = match rest.chars().next() {
+            Some(c) if c.is_alphanumeric() => rest
+                .find(|c: char| !c.is_alphanumeric())
+                .unwrap_or(rest.len()),
+            Some(c) => c.len_utf8(),
+            None => 0,
+        };
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        ParseError {
+            span: start..start + len,
+            line: source[..start].matches('\n').count() + 1,
+            column: source[line_start..start].chars().count() + 1,
+            expected: failure.expected.iter().map(|e| e.to_string()).collect(),
+            found: rest[..len].to_string(),
+            text: source[line_start..]
+                .lines()
+                .next()
+                .unwrap_or("")
+                .to_string(),
+        }
+    }
+}
+
+// line 2, column 9: expected `;`, found `}`
+//   x = 1 }
+//         ^
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        let found = if self.found.is_empty() {
+            "the end of the program".to_string()
+        } else {
+            format!("`{}`", self.found)
+        };
+        match self.expected.split_last() {
+            None => writeln!(f, "unexpected {}", found)?,
+            Some((last, [])) => writeln!(f, "expected {}, found {}", last, found)?,
+            Some((last, rest)) => writeln!(
+                f,
+                "expected {} or {}, found {}",
+                rest.join(", "),
+                last,
+                found
+            )?,
+        }
+        // tabs are kept so that the caret lines up
+        let indent = self
+            .text
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        writeln!(f, "{}", self.text)?;
+        write!(
+            f,
+            "{}{}",
+            indent,
+            "^".repeat(self.found.chars().count().max(1))
+        )
+    }
+}
+
+fn parenth(input: Input<'_>) -> Res<'_, AExp> {
     delimited(
         multispace0,
-        delimited(tag("("), aexpr, tag(")")),
+        delimited(tag("("), aexpr, context("`)`", tag(")"))),
         multispace0,
     )(input)
 }
 
-fn var(input: &str) -> IResult<&str, AExp> {
-    let (input, var) = delimited(multispace0, alpha1, multispace0)(input)?;
-    Ok((input, AExp::Id(var.to_string())))
+fn var(input: Input<'_>) -> Res<'_, AExp> {
+    let (input, (var, span)) = spanned(terminated(alpha1, multispace0))(input)?;
+    Ok((input, AExp::Id(var.fragment().to_string(), span)))
 }
-fn int(input: &str) -> IResult<&str, AExp> {
+fn int(input: Input<'_>) -> Res<'_, AExp> {
     // let (input, dig) = nom::number::complete::be_i32(input)?;
     // Ok((input, AExp::Int(dig)))
-    let (input, num) = delimited(multispace0, digit1, multispace0)(input)?;
-    Ok((input, AExp::Int(num.parse().unwrap())))
+    let (rest, (num, span)) = spanned(terminated(digit1, multispace0))(input)?;
+    match num.fragment().parse() {
+        Ok(n) => Ok((rest, AExp::Int(n, span))),
+        // no other alternative reads digits, so a literal out of range fails outright
+        Err(_) => Err(nom::Err::Failure(Failure {
+            offset: token_offset(input),
+            expected: vec!["an integer that fits in 32 bits"],
+            invalid: true,
+        })),
+    }
+}
+
+fn star(input: Input<'_>) -> Res<'_, Input<'_>> {
+    delimited(multispace0, tag("*"), multispace0)(input)
+}
+fn deref(input: Input<'_>) -> Res<'_, AExp> {
+    let (input, (a, span)) = spanned(preceded(star, not_bin))(input)?;
+    Ok((input, AExp::Deref(Box::new(a), span)))
 }
 
-fn not_bin(input: &str) -> IResult<&str, AExp> {
-    (alt((parenth, int, var)))(input)
+fn not_bin(input: Input<'_>) -> Res<'_, AExp> {
+    (alt((parenth, int, var, deref)))(input)
 }
-fn div_extra(input: &str) -> IResult<&str, Vec<AExp>> {
-    many0(preceded(tag("/"), not_bin))(input)
+fn div_extra(input: Input<'_>) -> Res<'_, Vec<(AExp, Span)>> {
+    many0(preceded(tag("/"), spanned(not_bin)))(input)
 }
-fn div(input: &str) -> IResult<&str, AExp> {
+fn div(input: Input<'_>) -> Res<'_, AExp> {
     // let (input, exp1) = alt(parenth, todo!());
-    let (input, (init, extra)) = tuple((not_bin, div_extra))(input)?;
+    let (input, ((init, extra), span)) = spanned(tuple((not_bin, div_extra)))(input)?;
     Ok((
         input,
-        extra
-            .into_iter()
-            .fold(init, |acc, x| AExp::Divide(Box::new(acc), Box::new(x))),
+        extra.into_iter().fold(init, |acc, (x, x_span)| {
+            let span = span.to(x_span);
+            AExp::Divide(Box::new(acc), Box::new(x), span)
+        }),
     ))
 }
 
@@ -50,28 +248,36 @@ fn div(input: &str) -> IResult<&str, AExp> {
 // plus ::= div_exp plus'
 // plus' ::= (+div_exp) plus' | nothing
 
-fn plus_extra(input: &str) -> IResult<&str, Vec<AExp>> {
-    many0(preceded(tag("+"), div))(input)
+fn plus_extra(input: Input<'_>) -> Res<'_, Vec<(AExp, Span)>> {
+    many0(preceded(tag("+"), spanned(div)))(input)
 }
-fn plus(input: &str) -> IResult<&str, AExp> {
-    let (input, (init, extra)) = tuple((div, plus_extra))(input)?;
+fn plus(input: Input<'_>) -> Res<'_, AExp> {
+    let (input, ((init, extra), span)) = spanned(tuple((div, plus_extra)))(input)?;
     Ok((
         input,
-        extra
-            .into_iter()
-            .fold(init, |acc, x| AExp::Plus(Box::new(acc), Box::new(x))),
+        extra.into_iter().fold(init, |acc, (x, x_span)| {
+            let span = span.to(x_span);
+            AExp::Plus(Box::new(acc), Box::new(x), span)
+        }),
     ))
 }
-fn aexpr(input: &str) -> IResult<&str, AExp> {
+fn aexpr(input: Input<'_>) -> Res<'_, AExp> {
     plus(input)
 }
+fn expression(input: Input<'_>) -> Res<'_, AExp> {
+    context("an expression", aexpr)(input)
+}
 
-fn ltexp(input: &str) -> IResult<&str, BExp> {
-    let (input, (left, right)) = separated_pair(aexpr, tag("<="), aexpr)(input)?;
-    Ok((input, (BExp::LessThanEq(Box::new(left), Box::new(right)))))
+fn ltexp(input: Input<'_>) -> Res<'_, BExp> {
+    let (input, ((left, right), span)) =
+        spanned(separated_pair(aexpr, context("`<=`", tag("<=")), aexpr))(input)?;
+    Ok((
+        input,
+        BExp::LessThanEq(Box::new(left), Box::new(right), span),
+    ))
 }
-fn and_extra(input: &str) -> IResult<&str, Vec<BExp>> {
-    many0(preceded(tag("&&"), ltexp))(input)
+fn and_extra(input: Input<'_>) -> Res<'_, Vec<(BExp, Span)>> {
+    many0(preceded(tag("&&"), spanned(bexp_atom)))(input)
 }
 
 // bexp ::= bexp && ltexp | ltexp | ( bexp ) | ! bexp | bool
@@ -80,112 +286,402 @@ fn and_extra(input: &str) -> IResult<&str, Vec<BExp>> {
 //
 //
 
-fn parse_true(input: &str) -> IResult<&str, BExp> {
-    let (input, _b) = delimited(multispace0, tag("true"), multispace0)(input)?;
-    Ok((input, BExp::Bool(true)))
+fn parse_true(input: Input<'_>) -> Res<'_, BExp> {
+    let (input, (_b, span)) = spanned(terminated(tag("true"), multispace0))(input)?;
+    Ok((input, BExp::Bool(true, span)))
 }
 
-fn parse_false(input: &str) -> IResult<&str, BExp> {
-    let (input, _) = delimited(multispace0, tag("false"), multispace0)(input)?;
-    Ok((input, BExp::Bool(false)))
+fn parse_false(input: Input<'_>) -> Res<'_, BExp> {
+    let (input, (_, span)) = spanned(terminated(tag("false"), multispace0))(input)?;
+    Ok((input, BExp::Bool(false, span)))
 }
-fn bool_const(input: &str) -> IResult<&str, BExp> {
+fn bool_const(input: Input<'_>) -> Res<'_, BExp> {
     alt((parse_true, parse_false))(input)
 }
-fn not(input: &str) -> IResult<&str, BExp> {
-    let (input, _) = delimited(multispace0, tag("!"), multispace0)(input)?;
-    let (input, b) = bexp(input)?;
-    Ok((input, BExp::Negation(Box::new(b))))
+fn not_bexp(input: Input<'_>) -> Res<'_, BExp> {
+    let (input, ((_, b), span)) =
+        spanned(pair(delimited(multispace0, tag("!"), multispace0), bexp))(input)?;
+    Ok((input, BExp::Negation(Box::new(b), span)))
 }
-fn bexp_parens(input: &str) -> IResult<&str, BExp> {
+fn bexp_parens(input: Input<'_>) -> Res<'_, BExp> {
     delimited(
-        tag("("),
-        delimited(multispace0, bexp, multispace0),
-        tag(")"),
+        multispace0,
+        delimited(
+            tag("("),
+            delimited(multispace0, bexp, multispace0),
+            context("`)`", tag(")")),
+        ),
+        multispace0,
     )(input)
 }
 
-fn bexp(input: &str) -> IResult<&str, BExp> {
-    let (input, (init, extra)) =
-        tuple((alt((ltexp, bexp_parens, not, bool_const)), and_extra))(input)?;
+fn bexp_atom(input: Input<'_>) -> Res<'_, BExp> {
+    alt((ltexp, bexp_parens, not_bexp, bool_const))(input)
+}
+
+fn bexp(input: Input<'_>) -> Res<'_, BExp> {
+    let (input, ((init, extra), span)) = spanned(tuple((bexp_atom, and_extra)))(input)?;
     Ok((
         input,
-        extra
-            .into_iter()
-            .fold(init, |acc, x| BExp::And(Box::new(acc), Box::new(x))),
+        extra.into_iter().fold(init, |acc, (x, x_span)| {
+            let span = span.to(x_span);
+            BExp::And(Box::new(acc), Box::new(x), span)
+        }),
     ))
 }
+fn condition(input: Input<'_>) -> Res<'_, BExp> {
+    context("a condition", bexp)(input)
+}
+
+// a keyword is not the start of a longer name
+fn keyword<'a>(k: &'static str) -> impl FnMut(Input<'a>) -> Res<'a, Input<'a>> {
+    terminated(preceded(multispace0, tag(k)), not(alpha1))
+}
 
-fn open_bracket(input: &str) -> IResult<&str, &str> {
-    delimited(multispace0, tag("{"), multispace0)(input)
+fn open_bracket(input: Input<'_>) -> Res<'_, Input<'_>> {
+    context("`{`", delimited(multispace0, tag("{"), multispace0))(input)
 }
 
-fn closed_bracket(input: &str) -> IResult<&str, &str> {
-    delimited(multispace0, tag("}"), multispace0)(input)
+fn closed_bracket(input: Input<'_>) -> Res<'_, Input<'_>> {
+    context(
+        "a statement or `}`",
+        delimited(multispace0, tag("}"), multispace0),
+    )(input)
 }
 
-fn seq_list(input: &str) -> IResult<&str, Option<Stmt>> {
+fn seq_list(input: Input<'_>) -> Res<'_, Option<Stmt>> {
     let (input, s) = many0(stmt)(input)?;
-    let new_stmt = s
-        .into_iter()
-        .reduce(|acc, x| Stmt::Sequence(Box::new(acc), Box::new(x)));
+    let new_stmt = s.into_iter().reduce(|acc, x| {
+        let span = acc.span().to(x.span());
+        Stmt::Sequence(Box::new(acc), Box::new(x), span)
+    });
     Ok((input, new_stmt))
 }
-fn block(input: &str) -> IResult<&str, Block> {
-    let (input, s) = delimited(open_bracket, seq_list, closed_bracket)(input)?;
+// once a block is opened its statements and closing bracket must follow
+fn block(input: Input<'_>) -> Res<'_, Block> {
+    let (input, (s, span)) = spanned(preceded(
+        open_bracket,
+        cut(terminated(seq_list, closed_bracket)),
+    ))(input)?;
 
     let new_block = match s {
-        Some(x) => Block::BlockStmt(Box::new(x)),
+        Some(x) => Block::BlockStmt(Box::new(x), span),
         None => Block::EmptyBlock,
     };
 
     Ok((input, (new_block)))
 }
 
-fn semicolon(input: &str) -> IResult<&str, &str> {
-    delimited(multispace0, tag(";"), multispace0)(input)
+fn semicolon(input: Input<'_>) -> Res<'_, Input<'_>> {
+    context("`;`", delimited(multispace0, tag(";"), multispace0))(input)
 }
-fn assign(input: &str) -> IResult<&str, Stmt> {
-    let (input, (v, s)) = terminated(separated_pair(var, tag("="), aexpr), semicolon)(input)?;
-    Ok((input, Stmt::Assign(v.to_string(), Box::new(s))))
+fn equals(input: Input<'_>) -> Res<'_, Input<'_>> {
+    context("`=`", tag("="))(input)
 }
-fn ifthenelse(input: &str) -> IResult<&str, Stmt> {
-    let (input, (_, b, s1, _, s2)) = tuple((tag("if"), bexp, block, tag("else"), block))(input)?;
+// a statement starting with a name that is not a keyword can only be an assignment
+fn assign(input: Input<'_>) -> Res<'_, Stmt> {
+    let (input, ((v, s), span)) =
+        spanned(pair(var, cut(delimited(equals, expression, semicolon))))(input)?;
+    Ok((input, Stmt::Assign(v.to_string(), Box::new(s), span)))
+}
+fn ifthenelse(input: Input<'_>) -> Res<'_, Stmt> {
+    let (input, ((_, (b, s1, s2)), span)) = spanned(pair(
+        keyword("if"),
+        cut(tuple((
+            condition,
+            block,
+            opt(preceded(keyword("else"), cut(block))),
+        ))),
+    ))(input)?;
+    let s = match s2 {
+        Some(s2) => Stmt::IfThenElse(Box::new(b), Box::new(s1), Box::new(s2), span),
+        None => Stmt::IfThen(Box::new(b), Box::new(s1), span),
+    };
+    Ok((input, s))
+}
+
+fn while_loop(input: Input<'_>) -> Res<'_, Stmt> {
+    let (input, ((_, (b, s)), span)) =
+        spanned(pair(keyword("while"), cut(pair(condition, block))))(input)?;
+    Ok((input, Stmt::While(Box::new(b), Box::new(s), span)))
+}
+
+fn do_while(input: Input<'_>) -> Res<'_, Stmt> {
+    let (input, ((_, (s, _, b, _)), span)) = spanned(pair(
+        keyword("do"),
+        cut(tuple((
+            block,
+            context("`while`", keyword("while")),
+            condition,
+            semicolon,
+        ))),
+    ))(input)?;
+    Ok((input, Stmt::DoWhile(Box::new(s), Box::new(b), span)))
+}
+
+fn open_parenth(input: Input<'_>) -> Res<'_, Input<'_>> {
+    context("`(`", delimited(multispace0, tag("("), multispace0))(input)
+}
+
+fn closed_parenth(input: Input<'_>) -> Res<'_, Input<'_>> {
+    context("`)`", delimited(multispace0, tag(")"), multispace0))(input)
+}
+
+fn for_update(input: Input<'_>) -> Res<'_, Stmt> {
+    let (input, ((v, a), span)) = spanned(separated_pair(
+        context("a variable", var),
+        equals,
+        expression,
+    ))(input)?;
+    Ok((input, Stmt::Assign(v.to_string(), Box::new(a), span)))
+}
+
+fn for_loop(input: Input<'_>) -> Res<'_, Stmt> {
+    let (input, ((_, (_, s1, b, _, s2, _, s)), span)) = spanned(pair(
+        keyword("for"),
+        cut(tuple((
+            open_parenth,
+            context("an assignment", assign),
+            condition,
+            semicolon,
+            for_update,
+            closed_parenth,
+            block,
+        ))),
+    ))(input)?;
     Ok((
         input,
-        Stmt::IfThenElse(Box::new(b), Box::new(s1), Box::new(s2)),
+        Stmt::For(Box::new(s1), Box::new(b), Box::new(s2), Box::new(s), span),
     ))
 }
 
-fn while_loop(input: &str) -> IResult<&str, Stmt> {
-    let (input, (_, b, s)) = tuple((tag("while"), bexp, block))(input)?;
-    Ok((input, Stmt::While(Box::new(b), Box::new(s))))
+fn choice_extra(input: Input<'_>) -> Res<'_, Vec<(Block, Span)>> {
+    many0(preceded(tag("[]"), cut(spanned(block))))(input)
+}
+// a block on its own is the first branch of a choice
+fn choice(input: Input<'_>) -> Res<'_, Stmt> {
+    let (input, ((s1, (_, s2, extra)), span)) = spanned(pair(
+        block,
+        cut(tuple((context("`[]`", tag("[]")), block, choice_extra))),
+    ))(input)?;
+    let first = match extra.first() {
+        Some((_, first)) => Span {
+            start: span.start,
+            end: first.start,
+        },
+        None => span,
+    };
+    Ok((
+        input,
+        extra.into_iter().fold(
+            Stmt::Choice(Box::new(s1), Box::new(s2), first),
+            |acc, (x, x_span)| {
+                let span = acc.span().to(x_span);
+                Stmt::Choice(
+                    Box::new(Block::BlockStmt(Box::new(acc), span)),
+                    Box::new(x),
+                    span,
+                )
+            },
+        ),
+    ))
+}
+
+fn new(input: Input<'_>) -> Res<'_, Stmt> {
+    let (input, ((v, _, _, _), span)) =
+        spanned(tuple((var, tag("="), keyword("new"), semicolon)))(input)?;
+    Ok((input, Stmt::New(v.to_string(), span)))
+}
+
+fn assign_deref(input: Input<'_>) -> Res<'_, Stmt> {
+    let (input, ((a1, a2), span)) = spanned(pair(
+        preceded(star, not_bin),
+        cut(delimited(equals, expression, semicolon)),
+    ))(input)?;
+    Ok((input, Stmt::AssignDeref(Box::new(a1), Box::new(a2), span)))
+}
+
+fn throw(input: Input<'_>) -> Res<'_, Stmt> {
+    let (input, ((_, (a, _)), span)) =
+        spanned(pair(keyword("throw"), cut(pair(expression, semicolon))))(input)?;
+    Ok((input, Stmt::Throw(Box::new(a), span)))
+}
+
+fn try_catch(input: Input<'_>) -> Res<'_, Stmt> {
+    let (input, ((_, (s1, _, x, s2)), span)) = spanned(pair(
+        keyword("try"),
+        cut(tuple((
+            block,
+            context("`catch`", keyword("catch")),
+            delimited(open_parenth, context("a variable", var), closed_parenth),
+            block,
+        ))),
+    ))(input)?;
+    Ok((
+        input,
+        Stmt::TryCatch(Box::new(s1), x.to_string(), Box::new(s2), span),
+    ))
 }
 
-fn stmt(input: &str) -> IResult<&str, Stmt> {
-    alt((assign, ifthenelse, while_loop))(input)
+// the statements introduced by a keyword come first, so that it is not taken for a variable
+fn stmt(input: Input<'_>) -> Res<'_, Stmt> {
+    alt((
+        ifthenelse,
+        while_loop,
+        do_while,
+        for_loop,
+        throw,
+        try_catch,
+        choice,
+        new,
+        assign_deref,
+        assign,
+    ))(input)
 }
 
-fn pgm(input: &str) -> IResult<&str, Pgm> {
-    let (input, (_, vs, _, s)) = tuple((
-        tag("int"),
-        separated_list1(tag(","), var),
+// X or high X or low X
+fn decl(input: Input<'_>) -> Res<'_, (Level, AExp)> {
+    let level = delimited(
+        multispace0,
+        alt((
+            map(tag("high"), |_| Level::High),
+            map(tag("low"), |_| Level::Low),
+        )),
+        multispace1,
+    );
+    context(
+        "a variable",
+        alt((pair(level, var), map(var, |x| (Level::Low, x)))),
+    )(input)
+}
+
+fn pgm(input: Input<'_>) -> Res<'_, Pgm> {
+    let (input, (_, decls, _, (s, span))) = tuple((
+        context("`int`", keyword("int")),
+        separated_list1(tag(","), decl),
         semicolon,
-        seq_list,
+        spanned(seq_list),
     ))(input)?;
     let s = match s {
         Some(s) => s,
-        None => Stmt::StmtBlock(Box::new(Block::EmptyBlock)),
+        None => Stmt::StmtBlock(Box::new(Block::EmptyBlock), span),
+    };
+    Ok((
+        input,
+        Pgm::Program(
+            decls.iter().map(|(_level, x)| x.to_string()).collect(),
+            decls.iter().map(|(level, _x)| *level).collect(),
+            s,
+        ),
+    ))
+}
+
+pub fn parse(input: String) -> Result<Pgm, ParseError> {
+    let result = terminated(
+        delimited(multispace0, pgm, multispace0),
+        context("a statement", eof),
+    )(Input::new(&input));
+    match result {
+        Ok((_rest, x)) => Ok(x),
+        Err(nom::Err::Error(e)) | Err(nom::Err::Failure(e)) => Err(ParseError::new(&input, e)),
+        Err(nom::Err::Incomplete(_needed)) => Err(ParseError::new(
+            &input,
+            Failure {
+                offset: input.len(),
+                expected: vec![],
+                invalid: false,
+            },
+        )),
+    }
+}
+
+pub fn parse_bexp(input: String) -> Option<BExp> {
+    match delimited(multispace0, bexp, multispace0)(Input::new(&input)) {
+        Ok((rest, x)) if rest.fragment().is_empty() => Some(x),
+        _ => None,
+    }
+}
+
+// ltl ::= disj -> ltl | disj
+// disj ::= conj \/ disj | conj
+// conj ::= binary /\ conj | binary
+// binary ::= unary U binary | unary R binary | unary
+// unary ::= ~ unary | [] unary | <> unary | O unary | bexp | ( ltl )
+
+fn ltl_op<'a>(op: &'static str) -> impl FnMut(Input<'a>) -> Res<'a, Input<'a>> {
+    delimited(multispace0, tag(op), multispace0)
+}
+
+fn ltl_prefix(input: Input<'_>) -> Res<'_, Ltl> {
+    let (input, op) = alt((ltl_op("~"), ltl_op("[]"), ltl_op("<>"), ltl_op("O ")))(input)?;
+    let (input, f) = ltl_unary(input)?;
+    let f = Box::new(f);
+    let f = match *op.fragment() {
+        "~" => Ltl::Not(f),
+        "[]" => Ltl::Always(f),
+        "<>" => Ltl::Eventually(f),
+        _ => Ltl::Next(f),
     };
+    Ok((input, f))
+}
+
+fn ltl_atom(input: Input<'_>) -> Res<'_, Ltl> {
+    let (input, b) = bexp(input)?;
+    Ok((input, Ltl::Atom(b)))
+}
+
+fn ltl_parens(input: Input<'_>) -> Res<'_, Ltl> {
+    delimited(ltl_op("("), ltl, ltl_op(")"))(input)
+}
+
+fn ltl_unary(input: Input<'_>) -> Res<'_, Ltl> {
+    alt((ltl_prefix, ltl_atom, ltl_parens))(input)
+}
+
+fn ltl_binary(input: Input<'_>) -> Res<'_, Ltl> {
+    let (input, f1) = ltl_unary(input)?;
+    match tuple((alt((ltl_op("U"), ltl_op("R"))), ltl_binary))(input) {
+        Ok((input, (op, f2))) if *op.fragment() == "U" => {
+            Ok((input, Ltl::Until(Box::new(f1), Box::new(f2))))
+        }
+        Ok((input, (_, f2))) => Ok((input, Ltl::Release(Box::new(f1), Box::new(f2)))),
+        Err(_) => Ok((input, f1)),
+    }
+}
+
+fn ltl_conj(input: Input<'_>) -> Res<'_, Ltl> {
+    let (input, (init, extra)) =
+        tuple((ltl_binary, many0(preceded(ltl_op("/\\"), ltl_binary))))(input)?;
     Ok((
         input,
-        Pgm::Program(vs.iter().map(|x| x.to_string()).collect(), s),
+        extra
+            .into_iter()
+            .fold(init, |acc, x| Ltl::And(Box::new(acc), Box::new(x))),
     ))
 }
 
-pub fn parse(input: String) -> Option<Pgm> {
-    match delimited(multispace0, pgm, multispace0)(&input) {
-        Ok(("", x)) => Some(x),
+fn ltl_disj(input: Input<'_>) -> Res<'_, Ltl> {
+    let (input, (init, extra)) =
+        tuple((ltl_conj, many0(preceded(ltl_op("\\/"), ltl_conj))))(input)?;
+    Ok((
+        input,
+        extra
+            .into_iter()
+            .fold(init, |acc, x| Ltl::Or(Box::new(acc), Box::new(x))),
+    ))
+}
+
+fn ltl(input: Input<'_>) -> Res<'_, Ltl> {
+    let (input, f1) = ltl_disj(input)?;
+    match preceded(ltl_op("->"), ltl)(input) {
+        Ok((input, f2)) => Ok((input, Ltl::Implies(Box::new(f1), Box::new(f2)))),
+        Err(_) => Ok((input, f1)),
+    }
+}
+
+pub fn parse_ltl(input: String) -> Option<Ltl> {
+    match delimited(multispace0, ltl, multispace0)(Input::new(&input)) {
+        Ok((rest, x)) if rest.fragment().is_empty() => Some(x),
         _ => None,
     }
 }
@@ -196,17 +692,17 @@ mod tests {
 
     #[test]
     fn test_arith1() {
-        match aexpr("1") {
-            Ok((_, AExp::Int(1))) => {}
+        match aexpr(Input::new("1")) {
+            Ok((_, AExp::Int(1, _))) => {}
             _ => panic!(),
         };
     }
 
     #[test]
     fn test_arith2() {
-        match aexpr("1 + 2") {
-            Ok((_, AExp::Plus(x, y))) => match (*x, *y) {
-                (AExp::Int(1), AExp::Int(2)) => {}
+        match aexpr(Input::new("1 + 2")) {
+            Ok((_, AExp::Plus(x, y, _))) => match (*x, *y) {
+                (AExp::Int(1, _), AExp::Int(2, _)) => {}
                 _ => panic!(),
             },
             _ => panic!(),
@@ -214,10 +710,10 @@ mod tests {
     }
     #[test]
     fn test_arith3() {
-        match aexpr("1 + 2 + 3") {
-            Ok((_, AExp::Plus(x, y))) => match (*x, *y) {
-                (AExp::Plus(x, y), AExp::Int(3)) => match (*x, *y) {
-                    (AExp::Int(1), AExp::Int(2)) => {}
+        match aexpr(Input::new("1 + 2 + 3")) {
+            Ok((_, AExp::Plus(x, y, _))) => match (*x, *y) {
+                (AExp::Plus(x, y, _), AExp::Int(3, _)) => match (*x, *y) {
+                    (AExp::Int(1, _), AExp::Int(2, _)) => {}
                     _ => panic!(),
                 },
                 _ => panic!(),
@@ -227,27 +723,27 @@ mod tests {
     }
     #[test]
     fn test_arith4() {
-        match aexpr("1 / 3 + (2 + x ) + 3 / 1") {
-            Ok((_, AExp::Plus(x, y))) => {
+        match aexpr(Input::new("1 / 3 + (2 + x ) + 3 / 1")) {
+            Ok((_, AExp::Plus(x, y, _))) => {
                 match *y {
-                    AExp::Divide(x, y) => match (*x, *y) {
-                        (AExp::Int(3), AExp::Int(1)) => {}
+                    AExp::Divide(x, y, _) => match (*x, *y) {
+                        (AExp::Int(3, _), AExp::Int(1, _)) => {}
                         _ => panic!(),
                     },
                     _ => panic!(),
                 }
                 match *x {
-                    AExp::Plus(x, y) => {
+                    AExp::Plus(x, y, _) => {
                         match *y {
-                            AExp::Plus(x, y) => match (*x, *y) {
-                                (AExp::Int(2), AExp::Id(z)) if z == "x" => {}
+                            AExp::Plus(x, y, _) => match (*x, *y) {
+                                (AExp::Int(2, _), AExp::Id(z, _)) if z == "x" => {}
                                 _ => panic!(),
                             },
                             _ => panic!(),
                         }
                         match *x {
-                            AExp::Divide(x, y) => match (*x, *y) {
-                                (AExp::Int(1), AExp::Int(3)) => {}
+                            AExp::Divide(x, y, _) => match (*x, *y) {
+                                (AExp::Int(1, _), AExp::Int(3, _)) => {}
                                 _ => panic!(),
                             },
                             _ => panic!(),
@@ -261,13 +757,13 @@ mod tests {
     }
     #[test]
     fn test_stmt2() {
-        match seq_list("x = 1 ; x = 1; ") {
-            Ok((_, Some(Stmt::Sequence(s1, s2)))) => {
+        match seq_list(Input::new("x = 1 ; x = 1; ")) {
+            Ok((_, Some(Stmt::Sequence(s1, s2, _)))) => {
                 match *s1 {
-                    Stmt::Assign(v, n) => {
+                    Stmt::Assign(v, n, _) => {
                         if v == "x" {
                             match *n {
-                                AExp::Int(1) => {}
+                                AExp::Int(1, _) => {}
                                 _ => panic!(),
                             }
                         }
@@ -275,10 +771,10 @@ mod tests {
                     _ => panic!(),
                 }
                 match *s2 {
-                    Stmt::Assign(v, n) => {
+                    Stmt::Assign(v, n, _) => {
                         if v == "x" {
                             match *n {
-                                AExp::Int(1) => {}
+                                AExp::Int(1, _) => {}
                                 _ => panic!(),
                             }
                         }
@@ -292,11 +788,11 @@ mod tests {
     }
     #[test]
     fn test_stmt1() {
-        match stmt("x = 1 ; ") {
-            Ok((_, Stmt::Assign(v, n))) => {
+        match stmt(Input::new("x = 1 ; ")) {
+            Ok((_, Stmt::Assign(v, n, _))) => {
                 if v == "x" {
                     match *n {
-                        AExp::Int(1) => {}
+                        AExp::Int(1, _) => {}
                         _ => panic!(),
                     }
                 }
@@ -306,20 +802,169 @@ mod tests {
         };
     }
     #[test]
+    #[allow(clippy::collapsible_match)]
     fn test_pgm1() {
         match parse("int x, y; x = 1 ; ".to_string()) {
-            Some(Pgm::Program(v, s)) if v == vec!["x".to_string(), "y".to_string()] => match s {
-                Stmt::Assign(v, n) => {
-                    if v == "x" {
-                        match *n {
-                            AExp::Int(1) => {}
-                            _ => panic!(),
+            Ok(Pgm::Program(v, _levels, s)) if v == vec!["x".to_string(), "y".to_string()] => {
+                match s {
+                    Stmt::Assign(v, n, _) => {
+                        if v == "x" {
+                            match *n {
+                                AExp::Int(1, _) => {}
+                                _ => panic!(),
+                            }
                         }
                     }
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        };
+    }
+    #[test]
+    fn test_choice() {
+        match stmt(Input::new("{ x = 1; } [] { x = 2; }")) {
+            Ok((rest, Stmt::Choice(s1, s2, _))) if rest.fragment().is_empty() => match (*s1, *s2) {
+                (Block::BlockStmt(_, _), Block::BlockStmt(_, _)) => {}
+                _ => panic!(),
+            },
+            _ => panic!(),
+        };
+    }
+    #[test]
+    fn test_try_catch() {
+        match stmt(Input::new("try { throw 1 + 1; } catch (x) { y = x; }")) {
+            Ok((rest, Stmt::TryCatch(s1, x, _s2, _))) if rest.fragment().is_empty() && x == "x" => {
+                match *s1 {
+                    Block::BlockStmt(s1, _) => match *s1 {
+                        Stmt::Throw(_, _) => {}
+                        _ => panic!(),
+                    },
+                    _ => panic!(),
                 }
+            }
+            _ => panic!(),
+        };
+    }
+    #[test]
+    fn test_pointers() {
+        match seq_list(Input::new("p = new; *p = *p + 1;")) {
+            Ok((rest, Some(Stmt::Sequence(s1, s2, _)))) if rest.fragment().is_empty() => {
+                match (*s1, *s2) {
+                    (Stmt::New(p, _), Stmt::AssignDeref(a1, a2, _)) if p == "p" => match (*a1, *a2)
+                    {
+                        (AExp::Id(_, _), AExp::Plus(x, _, _)) => match *x {
+                            AExp::Deref(_, _) => {}
+                            _ => panic!(),
+                        },
+                        _ => panic!(),
+                    },
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        };
+    }
+    #[test]
+    fn test_sugar() {
+        match seq_list(Input::new("if (x <= 1) { x = 1; } do { x = x + 1; } while x <= 3; for (i = 0; i <= 3; i = i + 1) { }")) {
+            Ok((rest, Some(Stmt::Sequence(s1, s2, _)))) if rest.fragment().is_empty() => match (*s1, *s2) {
+                (Stmt::Sequence(s1, s2, _), Stmt::For(_, _, _, _, _)) => match (*s1, *s2) {
+                    (Stmt::IfThen(_, _, _), Stmt::DoWhile(_, _, _)) => {}
+                    _ => panic!(),
+                },
                 _ => panic!(),
             },
             _ => panic!(),
         };
     }
+    #[test]
+    fn test_levels() {
+        match parse("int high h, low l, x, highest; x = 1;".to_string()) {
+            Ok(Pgm::Program(xl, levels, _s)) => {
+                assert_eq!(xl, vec!["h", "l", "x", "highest"]);
+                assert_eq!(
+                    levels,
+                    vec![Level::High, Level::Low, Level::Low, Level::Low]
+                );
+            }
+            Err(_e) => panic!("labelled declarations should parse"),
+        }
+    }
+    #[test]
+    fn test_errors() {
+        let e = parse("int x, y;\n  x = 1\n  y = 2;".to_string()).unwrap_err();
+        assert_eq!((e.line, e.column, e.span.clone()), (3, 3, 20..21));
+        assert_eq!(e.expected, vec!["`;`"]);
+        assert_eq!(
+            e.to_string(),
+            "line 3, column 3: expected `;`, found `y`\n  y = 2;\n  ^"
+        );
+        let e = parse("int x; while (x <= 1) { x = x + 1; ".to_string()).unwrap_err();
+        assert_eq!(e.expected, vec!["a statement or `}`"]);
+        assert_eq!(e.found, "");
+        let e = parse("int x; if x < 1 { }".to_string()).unwrap_err();
+        assert_eq!(
+            (e.expected, e.found),
+            (vec!["`<=`".to_string()], "<".to_string())
+        );
+        let e = parse("int x; try { } catch { }".to_string()).unwrap_err();
+        assert_eq!(e.expected, vec!["`(`"]);
+        let e = parse("int x; x = ;".to_string()).unwrap_err();
+        assert_eq!(
+            (e.expected, e.found),
+            (vec!["an expression".to_string()], ";".to_string())
+        );
+        let e = parse("int x; 3 = x;".to_string()).unwrap_err();
+        assert_eq!((e.expected, e.column), (vec!["a statement".to_string()], 8));
+        let e = parse("int x; x = 99999999999;".to_string()).unwrap_err();
+        assert_eq!(
+            (e.expected, e.found),
+            (
+                vec!["an integer that fits in 32 bits".to_string()],
+                "99999999999".to_string()
+            )
+        );
+    }
+    #[test]
+    fn test_spans() {
+        let source = "int x, y;\n  x = 1 + (y);\n  while (x <= 3) { x = x / 2; }";
+        let text = |span: Span| &source[span.start..span.end];
+        match parse(source.to_string()) {
+            Ok(Pgm::Program(_xl, _levels, Stmt::Sequence(s1, s2, span))) => {
+                assert_eq!(text(span), &source[12..]);
+                match (*s1, *s2) {
+                    (Stmt::Assign(_, a, span1), Stmt::While(b, s, span2)) => {
+                        assert_eq!(text(span1), "x = 1 + (y);");
+                        assert_eq!(text(a.span()), "1 + (y)");
+                        assert_eq!(text(span2), "while (x <= 3) { x = x / 2; }");
+                        assert_eq!(text(b.span()), "x <= 3");
+                        match *s {
+                            Block::BlockStmt(s, span) => {
+                                assert_eq!(text(span), "{ x = x / 2; }");
+                                assert_eq!(text(s.span()), "x = x / 2;");
+                            }
+                            _ => panic!(),
+                        }
+                    }
+                    _ => panic!(),
+                }
+            }
+            _ => panic!(),
+        }
+    }
+    #[test]
+    fn test_ltl() {
+        let f = parse_ltl("[] (x <= 10) /\\ <> (y <= x) -> O ~ true U x <= 1".to_string());
+        match f {
+            Some(Ltl::Implies(f1, f2)) => match (*f1, *f2) {
+                (Ltl::And(a, e), Ltl::Until(n, _b)) => match (*a, *e, *n) {
+                    (Ltl::Always(_), Ltl::Eventually(_), Ltl::Next(_)) => {}
+                    _ => panic!(),
+                },
+                _ => panic!(),
+            },
+            _ => panic!(),
+        }
+    }
 }
@@ -0,0 +1,352 @@
+use crate::ast::AExp;
+use crate::ast::BExp;
+use crate::ast::Block;
+use crate::ast::Level;
+use crate::ast::Pgm;
+use crate::ast::Stmt;
+
+// programs in the concrete syntax of the parser, which reads them back as the same tree;
+// Display keeps the notation of the semantics
+
+const INDENT: &str = "  ";
+
+// how tightly an arithmetic expression binds: + and / associate to the left
+// and / binds tighter, while * applies to an atom
+#[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
+enum Prec {
+    Sum,
+    Product,
+    Atom,
+}
+
+fn prec(a: &AExp) -> Prec {
+    match a {
+        AExp::Plus(_, _, _) => Prec::Sum,
+        AExp::Divide(_, _, _) => Prec::Product,
+        AExp::Id(_, _) | AExp::Int(_, _) | AExp::Deref(_, _) => Prec::Atom,
+    }
+}
+
+fn aexp(a: &AExp, at: Prec) -> String {
+    let s = match a {
+        AExp::Plus(a1, a2, _) => format!("{} + {}", aexp(a1, Prec::Sum), aexp(a2, Prec::Product)),
+        AExp::Divide(a1, a2, _) => {
+            format!("{} / {}", aexp(a1, Prec::Product), aexp(a2, Prec::Atom))
+        }
+        AExp::Id(x, _) => x.clone(),
+        AExp::Int(i, _) => i.to_string(),
+        AExp::Deref(a, _) => format!("*{}", aexp(a, Prec::Atom)),
+    };
+    if prec(a) < at {
+        format!("({})", s)
+    } else {
+        s
+    }
+}
+
+// ! takes everything after it, so a negation is bracketed as an operand of &&,
+// whose right operand is an atom
+fn bexp(b: &BExp, operand: bool) -> String {
+    match b {
+        BExp::LessThanEq(a1, a2, _) => {
+            format!("{} <= {}", aexp(a1, Prec::Sum), aexp(a2, Prec::Sum))
+        }
+        BExp::Negation(b, _) if operand => format!("(!{})", bexp(b, false)),
+        BExp::Negation(b, _) => format!("!{}", bexp(b, false)),
+        BExp::And(b1, b2, _) => {
+            let right = match **b2 {
+                BExp::And(_, _, _) => format!("({})", bexp(b2, false)),
+                _ => bexp(b2, true),
+            };
+            format!("{} && {}", bexp(b1, true), right)
+        }
+        BExp::Bool(b, _) => b.to_string(),
+    }
+}
+
+fn condition(b: &BExp) -> String {
+    format!("({})", bexp(b, false))
+}
+
+// the statements of a block one per line, followed by the closing bracket
+fn block(b: &Block, depth: usize, out: &mut String) {
+    out.push_str("{\n");
+    if let Block::BlockStmt(s, _) = b {
+        stmt(s, depth + 1, out);
+    }
+    out.push_str(&INDENT.repeat(depth));
+    out.push('}');
+}
+
+fn stmt(s: &Stmt, depth: usize, out: &mut String) {
+    let indent = INDENT.repeat(depth);
+    match s {
+        Stmt::StmtBlock(b, _) => {
+            if let Block::BlockStmt(s, _) = &**b {
+                stmt(s, depth, out);
+            }
+            return;
+        }
+        Stmt::Sequence(s1, s2, _) => {
+            stmt(s1, depth, out);
+            stmt(s2, depth, out);
+            return;
+        }
+        _ => out.push_str(&indent),
+    }
+    match s {
+        Stmt::Assign(x, a, _) => out.push_str(&format!("{} = {};", x, aexp(a, Prec::Sum))),
+        Stmt::New(x, _) => out.push_str(&format!("{} = new;", x)),
+        Stmt::AssignDeref(a1, a2, _) => out.push_str(&format!(
+            "*{} = {};",
+            aexp(a1, Prec::Atom),
+            aexp(a2, Prec::Sum)
+        )),
+        Stmt::Throw(a, _) => out.push_str(&format!("throw {};", aexp(a, Prec::Sum))),
+        Stmt::IfThenElse(b, b1, b2, _) => {
+            out.push_str(&format!("if {} ", condition(b)));
+            block(b1, depth, out);
+            out.push_str(" else ");
+            block(b2, depth, out);
+        }
+        Stmt::IfThen(b, b1, _) => {
+            out.push_str(&format!("if {} ", condition(b)));
+            block(b1, depth, out);
+        }
+        Stmt::While(b, b1, _) => {
+            out.push_str(&format!("while {} ", condition(b)));
+            block(b1, depth, out);
+        }
+        Stmt::DoWhile(b1, b, _) => {
+            out.push_str("do ");
+            block(b1, depth, out);
+            out.push_str(&format!(" while {};", condition(b)));
+        }
+        // the parts of the header are assignments, the update without its semicolon
+        Stmt::For(s1, b, s2, b1, _) => {
+            let mut init = String::new();
+            stmt(s1, 0, &mut init);
+            let mut update = String::new();
+            stmt(s2, 0, &mut update);
+            out.push_str(&format!(
+                "for ({} {}; {}) ",
+                init.trim_end(),
+                bexp(b, false),
+                update.trim_end().trim_end_matches(';')
+            ));
+            block(b1, depth, out);
+        }
+        Stmt::Choice(b1, b2, _) => {
+            block(b1, depth, out);
+            out.push_str(" [] ");
+            block(b2, depth, out);
+        }
+        Stmt::TryCatch(b1, x, b2, _) => {
+            out.push_str("try ");
+            block(b1, depth, out);
+            out.push_str(&format!(" catch ({}) ", x));
+            block(b2, depth, out);
+        }
+        Stmt::StmtBlock(_, _) | Stmt::Sequence(_, _, _) => (),
+    }
+    out.push('\n');
+}
+
+pub fn pretty(pgm: &Pgm) -> String {
+    let Pgm::Program(xl, levels, s) = pgm;
+    let decls = xl
+        .iter()
+        .zip(levels)
+        .map(|(x, level)| match level {
+            Level::Low => x.clone(),
+            Level::High => format!("high {}", x),
+        })
+        .collect::<Vec<String>>();
+    let mut out = format!("int {};\n", decls.join(", "));
+    stmt(s, 0, &mut out);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Span;
+    use crate::parse::parse;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_pretty() {
+        let pgm = parse(
+            "int x, high p; x = (1 + 2) / (x / 3) + 4; p = new; *(p + 0) = **p; if (!x <= 1 && (true && !false)) { } else { throw x; } for (x = 0; x <= 3; x = x + 1) { { x = 1; } [] { } }".to_string(),
+        )
+        .unwrap();
+        let text = pretty(&pgm);
+        assert_eq!(
+            text,
+            "int x, high p;\n\
+             x = (1 + 2) / (x / 3) + 4;\n\
+             p = new;\n\
+             *(p + 0) = **p;\n\
+             if (!x <= 1 && (true && (!false))) {\n\
+             } else {\n\
+             \x20 throw x;\n\
+             }\n\
+             for (x = 0; x <= 3; x = x + 1) {\n\
+             \x20 {\n\
+             \x20   x = 1;\n\
+             \x20 } [] {\n\
+             \x20 }\n\
+             }\n"
+        );
+        assert_eq!(parse(text).unwrap(), pgm);
+    }
+
+    // the trees the parser builds: sequences nest to the left,
+    // the parts of a for loop are assignments and integers are not negative
+    fn arb_aexp() -> impl Strategy<Value = AExp> {
+        let leaf = prop_oneof![
+            (0..1000i32).prop_map(|i| AExp::Int(i, Span::default())),
+            prop::sample::select(vec!["x", "y", "z"])
+                .prop_map(|x| AExp::Id(x.to_string(), Span::default())),
+        ];
+        leaf.prop_recursive(4, 16, 2, |a| {
+            prop_oneof![
+                (a.clone(), a.clone()).prop_map(|(a1, a2)| AExp::Plus(
+                    Box::new(a1),
+                    Box::new(a2),
+                    Span::default()
+                )),
+                (a.clone(), a.clone()).prop_map(|(a1, a2)| AExp::Divide(
+                    Box::new(a1),
+                    Box::new(a2),
+                    Span::default()
+                )),
+                a.prop_map(|a| AExp::Deref(Box::new(a), Span::default())),
+            ]
+        })
+    }
+
+    fn arb_bexp() -> impl Strategy<Value = BExp> {
+        let leaf = prop_oneof![
+            any::<bool>().prop_map(|b| BExp::Bool(b, Span::default())),
+            (arb_aexp(), arb_aexp()).prop_map(|(a1, a2)| BExp::LessThanEq(
+                Box::new(a1),
+                Box::new(a2),
+                Span::default()
+            )),
+        ];
+        leaf.prop_recursive(3, 8, 2, |b| {
+            prop_oneof![
+                (b.clone(), b.clone()).prop_map(|(b1, b2)| BExp::And(
+                    Box::new(b1),
+                    Box::new(b2),
+                    Span::default()
+                )),
+                b.prop_map(|b| BExp::Negation(Box::new(b), Span::default())),
+            ]
+        })
+    }
+
+    fn assign() -> impl Strategy<Value = Stmt> {
+        (prop::sample::select(vec!["x", "y", "z"]), arb_aexp())
+            .prop_map(|(x, a)| Stmt::Assign(x.to_string(), Box::new(a), Span::default()))
+    }
+
+    fn seq(stmts: Vec<Stmt>) -> Option<Stmt> {
+        stmts
+            .into_iter()
+            .reduce(|acc, s| Stmt::Sequence(Box::new(acc), Box::new(s), Span::default()))
+    }
+
+    fn arb_stmt() -> impl Strategy<Value = Stmt> {
+        let leaf = prop_oneof![
+            assign(),
+            prop::sample::select(vec!["x", "y", "z"])
+                .prop_map(|x| Stmt::New(x.to_string(), Span::default())),
+            (arb_aexp(), arb_aexp()).prop_map(|(a1, a2)| Stmt::AssignDeref(
+                Box::new(a1),
+                Box::new(a2),
+                Span::default()
+            )),
+            arb_aexp().prop_map(|a| Stmt::Throw(Box::new(a), Span::default())),
+        ];
+        leaf.prop_recursive(3, 24, 3, |s| {
+            let block = prop::collection::vec(s, 0..3).prop_map(|stmts| match seq(stmts) {
+                Some(s) => Block::BlockStmt(Box::new(s), Span::default()),
+                None => Block::EmptyBlock,
+            });
+            prop_oneof![
+                (arb_bexp(), block.clone(), block.clone()).prop_map(
+                    |(b, b1, b2)| Stmt::IfThenElse(
+                        Box::new(b),
+                        Box::new(b1),
+                        Box::new(b2),
+                        Span::default()
+                    )
+                ),
+                (arb_bexp(), block.clone()).prop_map(|(b, b1)| Stmt::IfThen(
+                    Box::new(b),
+                    Box::new(b1),
+                    Span::default()
+                )),
+                (arb_bexp(), block.clone()).prop_map(|(b, b1)| Stmt::While(
+                    Box::new(b),
+                    Box::new(b1),
+                    Span::default()
+                )),
+                (block.clone(), arb_bexp()).prop_map(|(b1, b)| Stmt::DoWhile(
+                    Box::new(b1),
+                    Box::new(b),
+                    Span::default()
+                )),
+                (assign(), arb_bexp(), assign(), block.clone()).prop_map(|(s1, b, s2, b1)| {
+                    Stmt::For(
+                        Box::new(s1),
+                        Box::new(b),
+                        Box::new(s2),
+                        Box::new(b1),
+                        Span::default(),
+                    )
+                }),
+                (block.clone(), block.clone()).prop_map(|(b1, b2)| Stmt::Choice(
+                    Box::new(b1),
+                    Box::new(b2),
+                    Span::default()
+                )),
+                (block.clone(), block).prop_map(|(b1, b2)| Stmt::TryCatch(
+                    Box::new(b1),
+                    "e".to_string(),
+                    Box::new(b2),
+                    Span::default(),
+                )),
+            ]
+        })
+    }
+
+    fn arb_pgm() -> impl Strategy<Value = Pgm> {
+        (prop::collection::vec(arb_stmt(), 0..4), any::<bool>()).prop_map(|(stmts, high)| {
+            let s = seq(stmts).unwrap_or(Stmt::StmtBlock(
+                Box::new(Block::EmptyBlock),
+                Span::default(),
+            ));
+            let level = if high { Level::High } else { Level::Low };
+            Pgm::Program(
+                vec![
+                    "x".to_string(),
+                    "y".to_string(),
+                    "z".to_string(),
+                    "e".to_string(),
+                ],
+                vec![Level::Low, level, Level::Low, Level::Low],
+                s,
+            )
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(pgm in arb_pgm()) {
+            prop_assert_eq!(parse(pretty(&pgm)).unwrap(), pgm);
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::ops::Not;
+
+// variable v is the literal 2v, its negation 2v + 1
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Lit(usize);
+
+impl Lit {
+    pub fn var(self) -> usize {
+        self.0 >> 1
+    }
+    pub fn negated(self) -> bool {
+        self.0 & 1 == 1
+    }
+}
+
+impl Not for Lit {
+    type Output = Lit;
+
+    fn not(self) -> Lit {
+        Lit(self.0 ^ 1)
+    }
+}
+
+// a variable ordered by its activity in the heap of decision candidates
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Candidate(f64, usize);
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Candidate) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Candidate) -> Ordering {
+        self.0.total_cmp(&other.0).then(other.1.cmp(&self.1))
+    }
+}
+
+// conflict-driven clause learning with two watched literals, first-UIP learning,
+// activity-based decisions, phase saving and geometric restarts
+#[derive(Clone, Debug, Default)]
+pub struct Solver {
+    clauses: Vec<Vec<Lit>>,
+    // the clauses watching each literal, which is always one of their first two
+    watches: Vec<Vec<usize>>,
+    values: Vec<Option<bool>>,
+    level: Vec<usize>,
+    reason: Vec<Option<usize>>,
+    phase: Vec<bool>,
+    activity: Vec<f64>,
+    increment: f64,
+    heap: BinaryHeap<Candidate>,
+    trail: Vec<Lit>,
+    // where each decision level starts on the trail
+    levels: Vec<usize>,
+    propagated: usize,
+    // false once the clauses are known to be unsatisfiable
+    ok: bool,
+}
+
+impl Solver {
+    pub fn new() -> Solver {
+        Solver {
+            increment: 1.0,
+            ok: true,
+            ..Solver::default()
+        }
+    }
+    pub fn new_var(&mut self) -> Lit {
+        let v = self.values.len();
+        self.values.push(None);
+        self.level.push(0);
+        self.reason.push(None);
+        self.phase.push(false);
+        self.activity.push(0.0);
+        self.watches.push(vec![]);
+        self.watches.push(vec![]);
+        self.heap.push(Candidate(0.0, v));
+        Lit(2 * v)
+    }
+    pub fn vars(&self) -> usize {
+        self.values.len()
+    }
+    fn value(&self, lit: Lit) -> Option<bool> {
+        self.values[lit.var()].map(|b| b != lit.negated())
+    }
+    // the value of a literal in the model found by the last successful solve
+    pub fn model(&self, lit: Lit) -> bool {
+        self.value(lit).unwrap_or(false)
+    }
+    fn assign(&mut self, lit: Lit, reason: Option<usize>) {
+        let v = lit.var();
+        self.values[v] = Some(!lit.negated());
+        self.level[v] = self.levels.len();
+        self.reason[v] = reason;
+        self.trail.push(lit);
+    }
+    // clauses are added before solving, at decision level 0
+    pub fn add_clause(&mut self, lits: &[Lit]) {
+        self.backtrack(0);
+        let mut clause: Vec<Lit> = vec![];
+        for lit in lits {
+            match self.value(*lit) {
+                Some(true) => return,
+                Some(false) => (),
+                None if clause.contains(&!*lit) => return,
+                None if clause.contains(lit) => (),
+                None => clause.push(*lit),
+            }
+        }
+        match clause.len() {
+            0 => self.ok = false,
+            1 => {
+                self.assign(clause[0], None);
+                if self.propagate().is_some() {
+                    self.ok = false;
+                }
+            }
+            _ => {
+                self.watch(self.clauses.len(), &clause);
+                self.clauses.push(clause);
+            }
+        }
+    }
+    fn watch(&mut self, c: usize, clause: &[Lit]) {
+        self.watches[clause[0].0].push(c);
+        self.watches[clause[1].0].push(c);
+    }
+    // unit propagation from the unpropagated part of the trail, a conflicting clause if any
+    fn propagate(&mut self) -> Option<usize> {
+        while self.propagated < self.trail.len() {
+            let falsified = !self.trail[self.propagated];
+            self.propagated += 1;
+            let watching = std::mem::take(&mut self.watches[falsified.0]);
+            let mut kept = Vec::with_capacity(watching.len());
+            let mut conflict = None;
+            for (i, c) in watching.iter().enumerate() {
+                if conflict.is_some() {
+                    kept.extend_from_slice(&watching[i..]);
+                    break;
+                }
+                let values = &self.values;
+                let value = |lit: Lit| values[lit.var()].map(|b| b != lit.negated());
+                let clause = &mut self.clauses[*c];
+                if clause[0] == falsified {
+                    clause.swap(0, 1);
+                }
+                let other = clause[0];
+                if value(other) == Some(true) {
+                    kept.push(*c);
+                    continue;
+                }
+                let replacement = (2..clause.len()).find(|k| value(clause[*k]) != Some(false));
+                match replacement {
+                    Some(k) => {
+                        clause.swap(1, k);
+                        let lit = clause[1];
+                        self.watches[lit.0].push(*c);
+                    }
+                    None => {
+                        kept.push(*c);
+                        match self.value(other) {
+                            Some(false) => conflict = Some(*c),
+                            _ => self.assign(other, Some(*c)),
+                        }
+                    }
+                }
+            }
+            self.watches[falsified.0] = kept;
+            if conflict.is_some() {
+                return conflict;
+            }
+        }
+        None
+    }
+    fn bump(&mut self, v: usize) {
+        self.activity[v] += self.increment;
+        if self.activity[v] > 1e100 {
+            for a in self.activity.iter_mut() {
+                *a *= 1e-100;
+            }
+            self.increment *= 1e-100;
+            self.heap = (0..self.vars())
+                .map(|v| Candidate(self.activity[v], v))
+                .collect();
+        } else {
+            self.heap.push(Candidate(self.activity[v], v));
+        }
+    }
+    // the first-UIP clause learnt from a conflict, asserting at the level it returns
+    fn analyze(&mut self, conflict: usize) -> (Vec<Lit>, usize) {
+        let mut seen = vec![false; self.vars()];
+        let mut learnt = vec![Lit(0)];
+        let mut pending = 0;
+        let mut clause = self.clauses[conflict].clone();
+        let mut i = self.trail.len();
+        loop {
+            let falsified = clause
+                .iter()
+                .filter(|lit| self.value(**lit) == Some(false))
+                .cloned()
+                .collect::<Vec<Lit>>();
+            for lit in falsified {
+                let v = lit.var();
+                if !seen[v] && self.level[v] > 0 {
+                    seen[v] = true;
+                    self.bump(v);
+                    if self.level[v] == self.levels.len() {
+                        pending += 1;
+                    } else {
+                        learnt.push(lit);
+                    }
+                }
+            }
+            // the latest literal of the current level that took part
+            let p = loop {
+                i -= 1;
+                if seen[self.trail[i].var()] {
+                    break self.trail[i];
+                }
+            };
+            seen[p.var()] = false;
+            pending -= 1;
+            if pending == 0 {
+                learnt[0] = !p;
+                break;
+            }
+            clause =
+                self.clauses[self.reason[p.var()].expect("implied at the current level")].clone();
+        }
+        self.increment *= 1.05;
+        // the second watch is the literal of the highest level below the current one
+        let mut back = 0;
+        if learnt.len() > 1 {
+            let k = (1..learnt.len())
+                .max_by_key(|k| self.level[learnt[*k].var()])
+                .expect("nonempty range");
+            learnt.swap(1, k);
+            back = self.level[learnt[1].var()];
+        }
+        (learnt, back)
+    }
+    fn backtrack(&mut self, level: usize) {
+        if self.levels.len() > level {
+            for lit in self.trail.drain(self.levels[level]..) {
+                let v = lit.var();
+                self.values[v] = None;
+                self.reason[v] = None;
+                self.phase[v] = !lit.negated();
+                self.heap.push(Candidate(self.activity[v], v));
+            }
+            self.levels.truncate(level);
+            self.propagated = self.trail.len();
+        }
+    }
+    fn decide(&mut self) -> Option<Lit> {
+        while let Some(Candidate(_a, v)) = self.heap.pop() {
+            if self.values[v].is_none() {
+                let lit = Lit(2 * v);
+                return Some(if self.phase[v] { lit } else { !lit });
+            }
+        }
+        None
+    }
+    // true if the clauses are satisfiable, when model gives the values found
+    pub fn solve(&mut self) -> bool {
+        if !self.ok {
+            return false;
+        }
+        self.backtrack(0);
+        if self.propagate().is_some() {
+            self.ok = false;
+            return false;
+        }
+        let mut conflicts = 0;
+        let mut restart = 100;
+        loop {
+            match self.propagate() {
+                Some(conflict) => {
+                    if self.levels.is_empty() {
+                        self.ok = false;
+                        return false;
+                    }
+                    conflicts += 1;
+                    let (learnt, back) = self.analyze(conflict);
+                    self.backtrack(back);
+                    if learnt.len() == 1 {
+                        self.assign(learnt[0], None);
+                    } else {
+                        let c = self.clauses.len();
+                        self.watch(c, &learnt);
+                        self.assign(learnt[0], Some(c));
+                        self.clauses.push(learnt);
+                    }
+                }
+                None => {
+                    if conflicts >= restart {
+                        conflicts = 0;
+                        restart += restart / 2;
+                        self.backtrack(0);
+                        continue;
+                    }
+                    match self.decide() {
+                        Some(lit) => {
+                            self.levels.push(self.trail.len());
+                            self.assign(lit, None);
+                        }
+                        None => return true,
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_solve() {
+        let mut s = Solver::new();
+        let x = (0..3).map(|_i| s.new_var()).collect::<Vec<Lit>>();
+        s.add_clause(&[x[0], x[1]]);
+        s.add_clause(&[!x[0], x[2]]);
+        s.add_clause(&[!x[1], x[2]]);
+        s.add_clause(&[!x[2], !x[0]]);
+        assert!(s.solve());
+        assert!(!s.model(x[0]) && s.model(x[1]) && s.model(x[2]));
+        s.add_clause(&[!x[1]]);
+        assert!(!s.solve());
+    }
+    // n + 1 pigeons do not fit in n holes, which needs clause learning to refute quickly
+    #[test]
+    fn test_pigeonhole() {
+        let n = 6;
+        let mut s = Solver::new();
+        let p = (0..=n)
+            .map(|_pigeon| (0..n).map(|_hole| s.new_var()).collect::<Vec<Lit>>())
+            .collect::<Vec<_>>();
+        for holes in &p {
+            s.add_clause(holes);
+        }
+        for h in 0..n {
+            for (i, first) in p.iter().enumerate() {
+                for second in &p[i + 1..] {
+                    s.add_clause(&[!first[h], !second[h]]);
+                }
+            }
+        }
+        assert!(!s.solve());
+    }
+}
@@ -0,0 +1,466 @@
+use std::fmt;
+
+use crate::ast::AExp;
+use crate::ast::BExp;
+use crate::ast::Block;
+use crate::ast::Level;
+use crate::ast::Pgm;
+use crate::ast::Rule;
+use crate::ast::Span;
+use crate::ast::Stmt;
+use crate::derivation::Derivation;
+use crate::derivation::InferenceRule;
+use crate::equiv::Bindings;
+use crate::equiv::Rng;
+use crate::stack::RewriteRule;
+use crate::stack::Stack;
+use crate::stack::RUN_LIMIT;
+use crate::vm::show_memory;
+
+// Gamma maps every declared variable to its label, pc is the label of the context
+#[derive(Clone, Debug)]
+pub enum SecJudgment {
+    AExpAt(Vec<(String, Level)>, AExp, Level),
+    BExpAt(Vec<(String, Level)>, BExp, Level),
+    StmtSecure(Vec<(String, Level)>, Level, Stmt),
+    PgmSecure(Pgm),
+}
+
+fn show_gamma(gamma: &[(String, Level)]) -> String {
+    format!(
+        "{{{}}}",
+        gamma
+            .iter()
+            .map(|(x, level)| format!("{} : {}", x, level))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+impl fmt::Display for SecJudgment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecJudgment::AExpAt(gamma, a, level) => {
+                write!(f, "{} |- {} : {}", show_gamma(gamma), a, level)
+            }
+            SecJudgment::BExpAt(gamma, b, level) => {
+                write!(f, "{} |- {} : {}", show_gamma(gamma), b, level)
+            }
+            SecJudgment::StmtSecure(gamma, pc, s) => {
+                write!(f, "{}, {} |- {} secure", show_gamma(gamma), pc, s)
+            }
+            SecJudgment::PgmSecure(p) => {
+                write!(f, "|- {} secure", p)
+            }
+        }
+    }
+}
+
+fn lookup(gamma: &[(String, Level)], x: &str) -> Option<Level> {
+    gamma
+        .iter()
+        .find(|(y, _level)| y == x)
+        .map(|(_y, level)| *level)
+}
+
+// the least label of a guard, reads through pointers may see any variable so they are high
+fn aexp_level(gamma: &[(String, Level)], a: &AExp) -> Level {
+    match a {
+        AExp::Plus(a1, a2, _) | AExp::Divide(a1, a2, _) => {
+            aexp_level(gamma, a1).max(aexp_level(gamma, a2))
+        }
+        AExp::Id(x, _) => lookup(gamma, x).unwrap_or(Level::Low),
+        AExp::Int(_i, _) => Level::Low,
+        AExp::Deref(_a, _) => Level::High,
+    }
+}
+
+fn bexp_level(gamma: &[(String, Level)], b: &BExp) -> Level {
+    match b {
+        BExp::LessThanEq(a1, a2, _) => aexp_level(gamma, a1).max(aexp_level(gamma, a2)),
+        BExp::Negation(b, _) => bexp_level(gamma, b),
+        BExp::And(b1, b2, _) => bexp_level(gamma, b1).max(bexp_level(gamma, b2)),
+        BExp::Bool(_b, _) => Level::Low,
+    }
+}
+
+fn block_stmt(b: Block) -> Stmt {
+    Stmt::StmtBlock(Box::new(b), Span::default())
+}
+
+// runs the checker to completion, returning the first goal that cannot be derived
+pub fn check(pgm: &Pgm) -> Result<Derivation<SecRule>, String> {
+    let mut derivation = Derivation::create(SecJudgment::PgmSecure(pgm.clone()));
+    if derivation.auto() {
+        Ok(derivation)
+    } else {
+        match derivation.current_goal() {
+            Some(goal) => Err(format!("Insecure flow: cannot derive {}", goal)),
+            None => Err("Insecure flow".to_string()),
+        }
+    }
+}
+
+// the rules of Volpano and Smith: explicit flows are caught by the assignment rule and
+// implicit flows by raising pc to the label of the guard, written pc' = pc \/ Gamma(B)
+#[derive(Clone, PartialEq, Debug)]
+pub enum SecRule {
+    // Gamma |- I : L .
+    Int,
+    // Gamma |- X : L if Gamma(X) <= L .
+    Variable,
+    // Gamma |- A1 + A2 : L if Gamma |- A1 : L /\ Gamma |- A2 : L .
+    Plus,
+    // Gamma |- A1 / A2 : L if Gamma |- A1 : L /\ Gamma |- A2 : L .
+    Divide,
+    // Gamma |- * A : high if Gamma |- A : high .
+    Deref,
+    // Gamma |- T : L .
+    Bool,
+    // Gamma |- A1 <= A2 : L if Gamma |- A1 : L /\ Gamma |- A2 : L .
+    LessThan,
+    // Gamma |- ! B : L if Gamma |- B : L .
+    Negate,
+    // Gamma |- B1 && B2 : L if Gamma |- B1 : L /\ Gamma |- B2 : L .
+    And,
+    // Gamma, pc |- {} secure .
+    EmptyBlock,
+    // Gamma, pc |- {S} secure if Gamma, pc |- S secure .
+    Block,
+    // Gamma, pc |- X = A ; secure if pc <= Gamma(X) /\ Gamma |- A : Gamma(X) .
+    Assignment,
+    // Gamma, pc |- S1 S2 secure if Gamma, pc |- S1 secure /\ Gamma, pc |- S2 secure .
+    Sequence,
+    // Gamma, pc |- if (B) S1 else S2 secure if Gamma |- B : pc' /\ Gamma, pc' |- S1 secure /\ Gamma, pc' |- S2 secure .
+    Conditional,
+    // Gamma, pc |- if (B) S secure if Gamma |- B : pc' /\ Gamma, pc' |- S secure .
+    IfThen,
+    // Gamma, pc |- while (B) S secure if Gamma |- B : pc' /\ Gamma, pc' |- S secure .
+    Loop,
+    // Gamma, pc |- do S while (B) ; secure if Gamma, pc' |- S secure /\ Gamma |- B : pc' .
+    DoWhile,
+    // Gamma, pc |- for (S1 B ; S2) S secure if Gamma, pc |- S1 secure /\ Gamma |- B : pc' /\ Gamma, pc' |- S2 secure /\ Gamma, pc' |- S secure .
+    For,
+    // Gamma, pc |- S1 [] S2 secure if Gamma, pc |- S1 secure /\ Gamma, pc |- S2 secure .
+    Choice,
+    // Gamma, low |- throw A ; secure if Gamma |- A : low .
+    Throw,
+    // Gamma, pc |- try S1 catch (X) S2 secure if X in Gamma /\ Gamma, pc |- S1 secure /\ Gamma, pc |- S2 secure .
+    TryCatch,
+    // Gamma, low |- X = new ; secure if X in Gamma .
+    New,
+    // Gamma, low |- * A1 = A2 ; secure if Gamma |- A1 : low /\ Gamma |- A2 : low .
+    AssignDeref,
+    // |- int Xl ; S secure if Xl, low |- S secure .
+    Program,
+}
+
+impl InferenceRule for SecRule {
+    type Judgment = SecJudgment;
+
+    fn list_of_rules() -> Vec<SecRule> {
+        vec![
+            SecRule::Int,
+            SecRule::Variable,
+            SecRule::Plus,
+            SecRule::Divide,
+            SecRule::Deref,
+            SecRule::Bool,
+            SecRule::LessThan,
+            SecRule::Negate,
+            SecRule::And,
+            SecRule::EmptyBlock,
+            SecRule::Block,
+            SecRule::Assignment,
+            SecRule::Sequence,
+            SecRule::Conditional,
+            SecRule::IfThen,
+            SecRule::Loop,
+            SecRule::DoWhile,
+            SecRule::For,
+            SecRule::Choice,
+            SecRule::Throw,
+            SecRule::TryCatch,
+            SecRule::New,
+            SecRule::AssignDeref,
+            SecRule::Program,
+        ]
+    }
+
+    fn get_description(&self) -> String {
+        match self {
+            SecRule::Int => "Gamma |- I : L".to_string(),
+            SecRule::Variable => "Gamma |- X : L if Gamma(X) <= L".to_string(),
+            SecRule::Plus => "Gamma |- A1 + A2 : L if Gamma |- A1 : L /\\ Gamma |- A2 : L".to_string(),
+            SecRule::Divide => "Gamma |- A1 / A2 : L if Gamma |- A1 : L /\\ Gamma |- A2 : L".to_string(),
+            SecRule::Deref => "Gamma |- * A : high if Gamma |- A : high".to_string(),
+            SecRule::Bool => "Gamma |- T : L".to_string(),
+            SecRule::LessThan => "Gamma |- A1 <= A2 : L if Gamma |- A1 : L /\\ Gamma |- A2 : L".to_string(),
+            SecRule::Negate => "Gamma |- ! B : L if Gamma |- B : L".to_string(),
+            SecRule::And => "Gamma |- B1 && B2 : L if Gamma |- B1 : L /\\ Gamma |- B2 : L".to_string(),
+            SecRule::EmptyBlock => "Gamma, pc |- {} secure".to_string(),
+            SecRule::Block => "Gamma, pc |- {S} secure if Gamma, pc |- S secure".to_string(),
+            SecRule::Assignment => "Gamma, pc |- X = A ; secure if pc <= Gamma(X) /\\ Gamma |- A : Gamma(X)".to_string(),
+            SecRule::Sequence => "Gamma, pc |- S1 S2 secure if Gamma, pc |- S1 secure /\\ Gamma, pc |- S2 secure".to_string(),
+            SecRule::Conditional => "Gamma, pc |- if (B) S1 else S2 secure if Gamma |- B : pc' /\\ Gamma, pc' |- S1 secure /\\ Gamma, pc' |- S2 secure where pc' = pc \\/ Gamma(B)".to_string(),
+            SecRule::IfThen => "Gamma, pc |- if (B) S secure if Gamma |- B : pc' /\\ Gamma, pc' |- S secure where pc' = pc \\/ Gamma(B)".to_string(),
+            SecRule::Loop => "Gamma, pc |- while (B) S secure if Gamma |- B : pc' /\\ Gamma, pc' |- S secure where pc' = pc \\/ Gamma(B)".to_string(),
+            SecRule::DoWhile => "Gamma, pc |- do S while (B) ; secure if Gamma, pc' |- S secure /\\ Gamma |- B : pc' where pc' = pc \\/ Gamma(B)".to_string(),
+            SecRule::For => "Gamma, pc |- for (S1 B ; S2) S secure if Gamma, pc |- S1 secure /\\ Gamma |- B : pc' /\\ Gamma, pc' |- S2 secure /\\ Gamma, pc' |- S secure where pc' = pc \\/ Gamma(B)".to_string(),
+            SecRule::Choice => "Gamma, pc |- S1 [] S2 secure if Gamma, pc |- S1 secure /\\ Gamma, pc |- S2 secure".to_string(),
+            SecRule::Throw => "Gamma, low |- throw A ; secure if Gamma |- A : low".to_string(),
+            SecRule::TryCatch => "Gamma, pc |- try S1 catch (X) S2 secure if X in Gamma /\\ Gamma, pc |- S1 secure /\\ Gamma, pc |- S2 secure".to_string(),
+            SecRule::New => "Gamma, low |- X = new ; secure if X in Gamma".to_string(),
+            SecRule::AssignDeref => "Gamma, low |- * A1 = A2 ; secure if Gamma |- A1 : low /\\ Gamma |- A2 : low".to_string(),
+            SecRule::Program => "|- int Xl ; S secure if Xl, low |- S secure".to_string(),
+        }
+    }
+
+    fn get_label(&self) -> String {
+        match self {
+            SecRule::Int => "Integer".to_string(),
+            SecRule::Variable => "Variable".to_string(),
+            SecRule::Plus => "Plus".to_string(),
+            SecRule::Divide => "Divide".to_string(),
+            SecRule::Deref => "Dereference".to_string(),
+            SecRule::Bool => "Boolean".to_string(),
+            SecRule::LessThan => "Less Than".to_string(),
+            SecRule::Negate => "Negate".to_string(),
+            SecRule::And => "And".to_string(),
+            SecRule::EmptyBlock => "Empty Block".to_string(),
+            SecRule::Block => "Block".to_string(),
+            SecRule::Assignment => "Assignment".to_string(),
+            SecRule::Sequence => "Sequence".to_string(),
+            SecRule::Conditional => "Conditional".to_string(),
+            SecRule::IfThen => "If Then".to_string(),
+            SecRule::Loop => "Loop".to_string(),
+            SecRule::DoWhile => "Do While".to_string(),
+            SecRule::For => "For".to_string(),
+            SecRule::Choice => "Choice".to_string(),
+            SecRule::Throw => "Throw".to_string(),
+            SecRule::TryCatch => "Try Catch".to_string(),
+            SecRule::New => "New".to_string(),
+            SecRule::AssignDeref => "Pointer Assignment".to_string(),
+            SecRule::Program => "Program".to_string(),
+        }
+    }
+
+    fn premises(&self, judgment: &SecJudgment) -> Option<Vec<SecJudgment>> {
+        use SecJudgment::*;
+        let premises = match (self, judgment.clone()) {
+            (SecRule::Int, AExpAt(_gamma, AExp::Int(_i, _), _level)) => vec![],
+            (SecRule::Variable, AExpAt(gamma, AExp::Id(x, _), level))
+                if lookup(&gamma, &x).is_some_and(|l| l <= level) =>
+            {
+                vec![]
+            }
+            (SecRule::Plus, AExpAt(gamma, AExp::Plus(a1, a2, _), level))
+            | (SecRule::Divide, AExpAt(gamma, AExp::Divide(a1, a2, _), level)) => {
+                vec![AExpAt(gamma.clone(), *a1, level), AExpAt(gamma, *a2, level)]
+            }
+            (SecRule::Deref, AExpAt(gamma, AExp::Deref(a, _), Level::High)) => {
+                vec![AExpAt(gamma, *a, Level::High)]
+            }
+            (SecRule::Bool, BExpAt(_gamma, BExp::Bool(_b, _), _level)) => vec![],
+            (SecRule::LessThan, BExpAt(gamma, BExp::LessThanEq(a1, a2, _), level)) => {
+                vec![AExpAt(gamma.clone(), *a1, level), AExpAt(gamma, *a2, level)]
+            }
+            (SecRule::Negate, BExpAt(gamma, BExp::Negation(b, _), level)) => {
+                vec![BExpAt(gamma, *b, level)]
+            }
+            (SecRule::And, BExpAt(gamma, BExp::And(b1, b2, _), level)) => {
+                vec![BExpAt(gamma.clone(), *b1, level), BExpAt(gamma, *b2, level)]
+            }
+            (SecRule::EmptyBlock, StmtSecure(_gamma, _pc, Stmt::StmtBlock(b, _))) => match *b {
+                Block::EmptyBlock => vec![],
+                _ => return None,
+            },
+            (SecRule::Block, StmtSecure(gamma, pc, Stmt::StmtBlock(b, _))) => match *b {
+                Block::BlockStmt(s, _) => vec![StmtSecure(gamma, pc, *s)],
+                _ => return None,
+            },
+            (SecRule::Assignment, StmtSecure(gamma, pc, Stmt::Assign(x, a, _))) => {
+                match lookup(&gamma, &x) {
+                    Some(level) if pc <= level => vec![AExpAt(gamma, *a, level)],
+                    _ => return None,
+                }
+            }
+            (SecRule::Sequence, StmtSecure(gamma, pc, Stmt::Sequence(s1, s2, _))) => {
+                vec![
+                    StmtSecure(gamma.clone(), pc, *s1),
+                    StmtSecure(gamma, pc, *s2),
+                ]
+            }
+            (SecRule::Conditional, StmtSecure(gamma, pc, Stmt::IfThenElse(b, s1, s2, _))) => {
+                let pc = pc.max(bexp_level(&gamma, &b));
+                vec![
+                    BExpAt(gamma.clone(), *b, pc),
+                    StmtSecure(gamma.clone(), pc, block_stmt(*s1)),
+                    StmtSecure(gamma, pc, block_stmt(*s2)),
+                ]
+            }
+            (SecRule::IfThen, StmtSecure(gamma, pc, Stmt::IfThen(b, s, _)))
+            | (SecRule::Loop, StmtSecure(gamma, pc, Stmt::While(b, s, _))) => {
+                let pc = pc.max(bexp_level(&gamma, &b));
+                vec![
+                    BExpAt(gamma.clone(), *b, pc),
+                    StmtSecure(gamma, pc, block_stmt(*s)),
+                ]
+            }
+            (SecRule::DoWhile, StmtSecure(gamma, pc, Stmt::DoWhile(s, b, _))) => {
+                let pc = pc.max(bexp_level(&gamma, &b));
+                vec![
+                    StmtSecure(gamma.clone(), pc, block_stmt(*s)),
+                    BExpAt(gamma, *b, pc),
+                ]
+            }
+            (SecRule::For, StmtSecure(gamma, pc, Stmt::For(s1, b, s2, s, _))) => {
+                let pc_b = pc.max(bexp_level(&gamma, &b));
+                vec![
+                    StmtSecure(gamma.clone(), pc, *s1),
+                    BExpAt(gamma.clone(), *b, pc_b),
+                    StmtSecure(gamma.clone(), pc_b, *s2),
+                    StmtSecure(gamma, pc_b, block_stmt(*s)),
+                ]
+            }
+            (SecRule::Choice, StmtSecure(gamma, pc, Stmt::Choice(s1, s2, _))) => vec![
+                StmtSecure(gamma.clone(), pc, block_stmt(*s1)),
+                StmtSecure(gamma, pc, block_stmt(*s2)),
+            ],
+            (SecRule::Throw, StmtSecure(gamma, Level::Low, Stmt::Throw(a, _))) => {
+                vec![AExpAt(gamma, *a, Level::Low)]
+            }
+            (SecRule::TryCatch, StmtSecure(gamma, pc, Stmt::TryCatch(s1, x, s2, _)))
+                if lookup(&gamma, &x).is_some() =>
+            {
+                vec![
+                    StmtSecure(gamma.clone(), pc, block_stmt(*s1)),
+                    StmtSecure(gamma, pc, block_stmt(*s2)),
+                ]
+            }
+            (SecRule::New, StmtSecure(gamma, Level::Low, Stmt::New(x, _)))
+                if lookup(&gamma, &x).is_some() =>
+            {
+                vec![]
+            }
+            (SecRule::AssignDeref, StmtSecure(gamma, Level::Low, Stmt::AssignDeref(a1, a2, _))) => {
+                vec![
+                    AExpAt(gamma.clone(), *a1, Level::Low),
+                    AExpAt(gamma, *a2, Level::Low),
+                ]
+            }
+            (SecRule::Program, PgmSecure(Pgm::Program(xl, levels, s))) => {
+                vec![StmtSecure(
+                    xl.into_iter().zip(levels).collect(),
+                    Level::Low,
+                    s,
+                )]
+            }
+            _ => return None,
+        };
+        Some(premises)
+    }
+}
+
+pub const TRIALS: usize = 50;
+
+// two runs from states that agree on the low variables but end with different low values
+#[derive(Clone, PartialEq, Debug)]
+pub struct Leak {
+    pub start: (Bindings, Bindings),
+    pub low: (Bindings, Bindings),
+}
+
+impl fmt::Display for Leak {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "starting from {} the low variables end as {}\nstarting from {} the low variables end as {}",
+            show_memory(&self.start.0),
+            show_memory(&self.low.0),
+            show_memory(&self.start.1),
+            show_memory(&self.low.1)
+        )
+    }
+}
+
+// the final values of the low variables, None if the run does not finish
+fn low_outputs(pgm: &Pgm, start: &[(String, i32)]) -> Option<Bindings> {
+    let Pgm::Program(_xl, levels, _s) = pgm;
+    let mut stack = Stack::<Rule>::create_from_state(pgm, start);
+    stack.run(&Rule::list_of_rules(), RUN_LIMIT);
+    let sigma = stack.top().final_state()?;
+    Some(
+        sigma
+            .into_iter()
+            .zip(levels)
+            .filter(|(_binding, level)| **level == Level::Low)
+            .map(|(binding, _level)| binding)
+            .collect(),
+    )
+}
+
+// runs the program on pairs of random states that differ only in the high variables,
+// returning how many pairs both finished with the same low values, or the first leak;
+// pairs where a run gets stuck or does not finish are skipped, as the type system
+// does not promise anything about termination
+pub fn noninterference(pgm: &Pgm, trials: usize) -> Result<usize, Leak> {
+    let Pgm::Program(xl, levels, _s) = pgm;
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    let mut agreed = 0;
+    for _trial in 0..trials {
+        let start1 = xl
+            .iter()
+            .map(|x| (x.clone(), rng.next()))
+            .collect::<Vec<_>>();
+        let start2 = start1
+            .iter()
+            .zip(levels)
+            .map(|((x, i), level)| match level {
+                Level::Low => (x.clone(), *i),
+                Level::High => (x.clone(), rng.next()),
+            })
+            .collect::<Vec<_>>();
+        if let (Some(low1), Some(low2)) = (low_outputs(pgm, &start1), low_outputs(pgm, &start2)) {
+            if low1 != low2 {
+                return Err(Leak {
+                    start: (start1, start2),
+                    low: (low1, low2),
+                });
+            }
+            agreed += 1;
+        }
+    }
+    Ok(agreed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+
+    #[test]
+    fn test_flows() {
+        let explicit = parse("int high h, l; l = h + 1;".to_string()).unwrap();
+        assert!(check(&explicit).is_err());
+        assert!(noninterference(&explicit, TRIALS).is_err());
+        let implicit =
+            parse("int high h, l; if (h <= 0) { l = 1; } else { l = 0; }".to_string()).unwrap();
+        assert!(check(&implicit).unwrap_err().contains("high |- l = 1 ;"));
+        assert!(noninterference(&implicit, TRIALS).is_err());
+        // the address q gets reveals how many news ran before it
+        let allocation =
+            parse("int high h, high p, q; if (h <= 0) { p = new; } q = new;".to_string()).unwrap();
+        assert!(check(&allocation).is_err());
+    }
+    #[test]
+    fn test_secure() {
+        let pgm = parse(
+            "int high h, l; h = l + h; while (l <= 3) { l = l + 1; } if (h <= l) { h = 0; }"
+                .to_string(),
+        )
+        .unwrap();
+        assert!(check(&pgm).is_ok());
+        assert!(noninterference(&pgm, TRIALS).is_ok_and(|agreed| agreed > 0));
+    }
+}
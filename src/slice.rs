@@ -0,0 +1,496 @@
+use std::collections::BTreeSet;
+
+use crate::ast::AExp;
+use crate::ast::BExp;
+use crate::ast::Block;
+use crate::ast::Pgm;
+use crate::ast::Span;
+use crate::ast::Stmt;
+use crate::cost::head;
+
+// the variables whose values may matter, "*" standing for the memory behind pointers
+// beyond the variables, which pointers can reach too
+type Relevant = BTreeSet<String>;
+
+const MEMORY: &str = "*";
+
+fn empty() -> Stmt {
+    Stmt::StmtBlock(Box::new(Block::EmptyBlock), Span::default())
+}
+
+fn is_empty(s: &Stmt) -> bool {
+    matches!(s, Stmt::StmtBlock(b, _) if **b == Block::EmptyBlock)
+}
+
+fn seq(s1: Stmt, s2: Stmt) -> Stmt {
+    match () {
+        _ if is_empty(&s1) => s2,
+        _ if is_empty(&s2) => s1,
+        _ => Stmt::Sequence(Box::new(s1), Box::new(s2), Span::default()),
+    }
+}
+
+fn block(s: Stmt) -> Block {
+    match s {
+        Stmt::StmtBlock(b, _) => *b,
+        s => Block::BlockStmt(Box::new(s), Span::default()),
+    }
+}
+
+// the statements of a program in order, each indented under the one containing it;
+// sequences and blocks are not listed, the parts of a for loop come in the order they are written
+fn listing(s: &Stmt, depth: usize, out: &mut Vec<(usize, String)>) {
+    let listing_block = |b: &Block, out: &mut Vec<(usize, String)>| {
+        if let Block::BlockStmt(s, _) = b {
+            listing(s, depth + 1, out);
+        }
+    };
+    match s {
+        Stmt::StmtBlock(b, _) => {
+            if let Block::BlockStmt(s, _) = &**b {
+                listing(s, depth, out);
+            }
+        }
+        Stmt::Sequence(s1, s2, _) => {
+            listing(s1, depth, out);
+            listing(s2, depth, out);
+        }
+        _ => {
+            out.push((depth, head(s)));
+            match s {
+                Stmt::IfThenElse(_, b1, b2, _)
+                | Stmt::Choice(b1, b2, _)
+                | Stmt::TryCatch(b1, _, b2, _) => {
+                    listing_block(b1, out);
+                    listing_block(b2, out);
+                }
+                Stmt::IfThen(_, b1, _) | Stmt::While(_, b1, _) | Stmt::DoWhile(b1, _, _) => {
+                    listing_block(b1, out)
+                }
+                Stmt::For(s1, _b, s2, b1, _) => {
+                    listing(s1, depth + 1, out);
+                    listing(s2, depth + 1, out);
+                    listing_block(b1, out);
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+fn count(s: &Stmt) -> usize {
+    let mut out = vec![];
+    listing(s, 0, &mut out);
+    out.len()
+}
+
+fn count_block(b: &Block) -> usize {
+    match b {
+        Block::EmptyBlock => 0,
+        Block::BlockStmt(s, _) => count(s),
+    }
+}
+
+// where an exception raised inside a try block goes: the catch variable, what is relevant
+// at the start of the handler, and whether anything of the handler is kept
+struct Handler {
+    var: String,
+    relevant: Relevant,
+    kept: bool,
+}
+
+// Weiser's backward slicing over the syntax: each statement is sliced with what is relevant
+// after it, giving what is relevant before it, loops by iterating to a fixpoint
+struct Slicer {
+    // the declared variables, which sit at the first locations of the memory
+    vars: Vec<String>,
+    var: String,
+    point: usize,
+    kept: BTreeSet<usize>,
+}
+
+// the sliced statement, what is relevant before it,
+// and whether it is kept because of something it contains
+type Sliced = (Stmt, Relevant, bool);
+
+impl Slicer {
+    // a read through a pointer may read the memory or any variable
+    fn reads(&self, a: &AExp) -> Relevant {
+        let mut r = a.vars().into_iter().collect::<Relevant>();
+        if a.derefs() {
+            r.insert(MEMORY.to_string());
+            r.extend(self.vars.iter().cloned());
+        }
+        r
+    }
+    fn reads_b(&self, b: &BExp) -> Relevant {
+        let mut r = b.vars().into_iter().collect::<Relevant>();
+        if b.derefs() {
+            r.insert(MEMORY.to_string());
+            r.extend(self.vars.iter().cloned());
+        }
+        r
+    }
+    fn block(&mut self, b: &Block, i: usize, after: &Relevant, h: Option<&Handler>) -> Sliced {
+        match b {
+            Block::EmptyBlock => (empty(), after.clone(), false),
+            Block::BlockStmt(s, _) => self.stmt(s, i, after, h),
+        }
+    }
+    // statement i is the first one listed in s
+    fn stmt(&mut self, s: &Stmt, i: usize, after: &Relevant, h: Option<&Handler>) -> Sliced {
+        let (sliced, mut before, keep) = match s {
+            Stmt::StmtBlock(b, _) => return self.block(b, i, after, h),
+            Stmt::Sequence(s1, s2, _) => {
+                let (t2, r1, k2) = self.stmt(s2, i + count(s1), after, h);
+                let (t1, r0, k1) = self.stmt(s1, i, &r1, h);
+                return (seq(t1, t2), r0, k1 || k2);
+            }
+            Stmt::Assign(x, a, _) if after.contains(x) => {
+                let mut before = after.clone();
+                before.remove(x);
+                before.extend(self.reads(a));
+                (s.clone(), before, true)
+            }
+            // the location handed out depends on every earlier new, so a new reads and writes
+            // the allocation counter, which is part of the memory
+            Stmt::New(x, _) if after.contains(x) || after.contains(MEMORY) => {
+                let mut before = after.clone();
+                before.remove(x);
+                before.insert(MEMORY.to_string());
+                (s.clone(), before, true)
+            }
+            // the location written is not known, it may be any variable or the memory,
+            // so nothing stops being relevant
+            Stmt::AssignDeref(a1, a2, _) if !after.is_empty() => {
+                let mut before = after.clone();
+                before.extend(self.reads(a1));
+                before.extend(self.reads(a2));
+                (s.clone(), before, true)
+            }
+            Stmt::Assign(..) | Stmt::New(_, _) | Stmt::AssignDeref(..) => {
+                (empty(), after.clone(), false)
+            }
+            // control goes to the handler, or leaves the program, rather than to what follows
+            Stmt::Throw(a, _) => {
+                let mut target = Relevant::new();
+                let mut handled = false;
+                if let Some(h) = h {
+                    target = h.relevant.clone();
+                    if target.remove(&h.var) {
+                        target.extend(self.reads(a));
+                    }
+                    handled = h.kept;
+                }
+                if after.is_empty() && target.is_empty() && !handled {
+                    (empty(), after.clone(), false)
+                } else {
+                    (s.clone(), target, true)
+                }
+            }
+            Stmt::IfThenElse(_, b1, b2, _) | Stmt::Choice(b1, b2, _) => {
+                let (t1, r1, k1) = self.block(b1, i + 1, after, h);
+                let (t2, r2, k2) = self.block(b2, i + 1 + count_block(b1), after, h);
+                let mut before = r1.union(&r2).cloned().collect::<Relevant>();
+                let (t1, t2) = (Box::new(block(t1)), Box::new(block(t2)));
+                let sliced = match s {
+                    Stmt::IfThenElse(b, _, _, _) => {
+                        before.extend(self.reads_b(b));
+                        Stmt::IfThenElse(b.clone(), t1, t2, Span::default())
+                    }
+                    _ => Stmt::Choice(t1, t2, Span::default()),
+                };
+                (sliced, before, k1 || k2)
+            }
+            Stmt::IfThen(b, b1, _) => {
+                let (t1, r1, k1) = self.block(b1, i + 1, after, h);
+                let mut before = r1;
+                before.extend(self.reads_b(b));
+                (
+                    Stmt::IfThen(b.clone(), Box::new(block(t1)), Span::default()),
+                    before,
+                    k1,
+                )
+            }
+            Stmt::While(b, b1, _) => {
+                let (t1, head, k) = self.loop_head(b, after, |slicer, head| {
+                    let (t1, r1, k1) = slicer.block(b1, i + 1, head, h);
+                    (t1, r1, k1)
+                });
+                (
+                    Stmt::While(b.clone(), Box::new(block(t1)), Span::default()),
+                    head,
+                    k,
+                )
+            }
+            // the body comes before the first test
+            Stmt::DoWhile(b1, b, _) => {
+                let mut test = after.clone();
+                loop {
+                    let (t1, r1, k1) = self.block(b1, i + 1, &test, h);
+                    let mut next = after.clone();
+                    if k1 {
+                        next.extend(self.reads_b(b));
+                        next.extend(r1.iter().cloned());
+                    }
+                    if next == test {
+                        break (
+                            Stmt::DoWhile(Box::new(block(t1)), b.clone(), Span::default()),
+                            r1,
+                            k1,
+                        );
+                    }
+                    test = next;
+                }
+            }
+            Stmt::For(s1, b, s2, b1, _) => {
+                let j = i + 1 + count(s1);
+                let ((t2, tb), head, k) = self.loop_head(b, after, |slicer, head| {
+                    let (t2, r2, k2) = slicer.stmt(s2, j, head, h);
+                    let (tb, rb, kb) = slicer.block(b1, j + count(s2), &r2, h);
+                    ((t2, tb), rb, kb || k2)
+                });
+                let (t1, r0, k1) = self.stmt(s1, i + 1, &head, h);
+                if k {
+                    let t = Stmt::For(
+                        Box::new(t1),
+                        b.clone(),
+                        Box::new(t2),
+                        Box::new(block(tb)),
+                        Span::default(),
+                    );
+                    (t, r0, true)
+                } else {
+                    (t1, r0, k1)
+                }
+            }
+            Stmt::TryCatch(b1, x, b2, _) => {
+                let (t2, rh, k2) = self.block(b2, i + 1 + count_block(b1), after, h);
+                let handler = Handler {
+                    var: x.clone(),
+                    relevant: rh,
+                    kept: k2,
+                };
+                let (t1, r1, k1) = self.block(b1, i + 1, after, Some(&handler));
+                let t = Stmt::TryCatch(
+                    Box::new(block(t1)),
+                    x.clone(),
+                    Box::new(block(t2)),
+                    Span::default(),
+                );
+                (t, r1, k1 || k2)
+            }
+        };
+        let kept = keep || i == self.point;
+        if keep {
+            self.kept.insert(i);
+        }
+        if i == self.point {
+            before.insert(self.var.clone());
+        }
+        if kept {
+            (sliced, before, true)
+        } else {
+            (empty(), after.clone(), false)
+        }
+    }
+    // what is relevant at the head of a loop: what is relevant after it,
+    // and once the body is kept, what the test and the body read
+    fn loop_head<T>(
+        &mut self,
+        b: &BExp,
+        after: &Relevant,
+        mut body: impl FnMut(&mut Slicer, &Relevant) -> (T, Relevant, bool),
+    ) -> (T, Relevant, bool) {
+        let mut head = after.clone();
+        loop {
+            let (t, r, k) = body(self, &head);
+            let mut next = after.clone();
+            if k {
+                next.extend(self.reads_b(b));
+                next.extend(r);
+            }
+            if next == head {
+                return (t, head, k);
+            }
+            head = next;
+        }
+    }
+}
+
+// the statements that may affect a variable at a point, the points being the statements
+// as listed followed by the end of the program
+#[derive(Clone, PartialEq, Debug)]
+pub struct Slice {
+    // each statement listed with whether it is in the slice
+    pub listing: Vec<(usize, String, bool)>,
+    pub point: usize,
+    // the program with only the slice, which computes the same value for the variable
+    pub pgm: Pgm,
+}
+
+pub fn points(pgm: &Pgm) -> Vec<String> {
+    let Pgm::Program(_xl, _levels, s) = pgm;
+    let mut out = vec![];
+    listing(s, 0, &mut out);
+    out.into_iter()
+        .map(|(depth, label)| format!("{}{}", "  ".repeat(depth), label))
+        .chain(std::iter::once("end of the program".to_string()))
+        .collect()
+}
+
+pub fn slice(pgm: &Pgm, x: &str, point: usize) -> Result<Slice, String> {
+    let Pgm::Program(xl, levels, s) = pgm;
+    if !xl.iter().any(|y| y == x) {
+        return Err(format!("{} is not declared", x));
+    }
+    let mut out = vec![];
+    listing(s, 0, &mut out);
+    if point > out.len() {
+        return Err(format!("There are only {} points", out.len() + 1));
+    }
+    let mut slicer = Slicer {
+        vars: xl.clone(),
+        var: x.to_string(),
+        point,
+        kept: BTreeSet::new(),
+    };
+    let mut end = Relevant::new();
+    if point == out.len() {
+        end.insert(x.to_string());
+    }
+    let (sliced, _before, _keep) = slicer.stmt(s, 0, &end, None);
+    Ok(Slice {
+        listing: out
+            .into_iter()
+            .enumerate()
+            .map(|(i, (depth, label))| (depth, label, slicer.kept.contains(&i)))
+            .collect(),
+        point,
+        pgm: Pgm::Program(xl.clone(), levels.clone(), sliced),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::equiv::run;
+    use crate::equiv::Outcome;
+    use crate::parse::parse;
+
+    fn kept(s: &Slice) -> Vec<String> {
+        s.listing
+            .iter()
+            .filter(|(_depth, _label, kept)| *kept)
+            .map(|(_depth, label, _kept)| label.clone())
+            .collect()
+    }
+    #[test]
+    fn test_slice() {
+        let pgm = parse(
+            "int n, i, s, p; i = 1; s = 0; p = 1; while (i <= n) { s = s + i; p = p + p; i = i + 1; }"
+                .to_string(),
+        )
+        .unwrap();
+        let sp = slice(&pgm, "s", 7).unwrap();
+        assert_eq!(
+            kept(&sp),
+            vec![
+                "i = 1 ;",
+                "s = 0 ;",
+                "while (i <= n)",
+                "s = (s + i) ;",
+                "i = (i + 1) ;"
+            ]
+        );
+        // the slice computes the same s from every state
+        for n in 0..5 {
+            let start = vec![
+                ("n".to_string(), n),
+                ("i".to_string(), 0),
+                ("s".to_string(), 0),
+                ("p".to_string(), 0),
+            ];
+            let value = |pgm: &Pgm| match run(pgm, &start).0 {
+                Outcome::Finished(sigma) => sigma.into_iter().find(|(x, _i)| x == "s"),
+                _ => None,
+            };
+            assert_eq!(value(&pgm), value(&sp.pgm));
+        }
+        // p at the start of the loop body also depends on how often the loop ran
+        let pp = slice(&pgm, "p", 4).unwrap();
+        assert_eq!(
+            kept(&pp),
+            vec![
+                "i = 1 ;",
+                "p = 1 ;",
+                "while (i <= n)",
+                "p = (p + p) ;",
+                "i = (i + 1) ;"
+            ]
+        );
+    }
+    #[test]
+    fn test_control() {
+        let pgm = parse(
+            "int x, y, z, e; try { if (x <= 0) { throw 1; } else { y = 2; } z = 3; } catch (e) { y = e; } z = y;"
+                .to_string(),
+        )
+        .unwrap();
+        let s = slice(&pgm, "z", 7).unwrap();
+        assert_eq!(
+            kept(&s),
+            vec![
+                "try ... catch (e)",
+                "if (x <= 0)",
+                "throw 1 ;",
+                "y = 2 ;",
+                "y = e ;",
+                "z = y ;"
+            ]
+        );
+        assert!(slice(&pgm, "w", 0).is_err());
+    }
+    #[test]
+    fn test_new() {
+        // q gets a different location if p = new is dropped
+        let pgm = parse("int p, q; p = new; q = new;".to_string()).unwrap();
+        let s = slice(&pgm, "q", 2).unwrap();
+        assert_eq!(kept(&s), vec!["p = new ;", "q = new ;"]);
+        let start = vec![("p".to_string(), 0), ("q".to_string(), 0)];
+        let outcome = run(&pgm, &start).0;
+        assert!(matches!(outcome, Outcome::Finished(_)));
+        assert_eq!(outcome, run(&s.pgm, &start).0);
+    }
+    #[test]
+    fn test_aliasing() {
+        // the variables are at the first locations, so *p = 5 writes x and *0 reads it
+        for (source, x, kept_stmts) in [
+            (
+                "int x, p; p = 0; *p = 5;",
+                "x",
+                vec!["p = 0 ;", "*p = 5 ;"],
+            ),
+            (
+                "int x, y; x = 3; y = *0;",
+                "y",
+                vec!["x = 3 ;", "y = *0 ;"],
+            ),
+        ] {
+            let pgm = parse(source.to_string()).unwrap();
+            let s = slice(&pgm, x, 2).unwrap();
+            assert_eq!(kept(&s), kept_stmts);
+            let start = vec![
+                ("x".to_string(), 0),
+                ("p".to_string(), 0),
+                ("y".to_string(), 0),
+            ];
+            let value = |pgm: &Pgm| match run(pgm, &start).0 {
+                Outcome::Finished(sigma) => sigma.into_iter().find(|(y, _i)| y == x),
+                _ => None,
+            };
+            assert!(value(&pgm).is_some());
+            assert_eq!(value(&pgm), value(&s.pgm));
+        }
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+use crate::ast::Configuration;
+use crate::ast::Pgm;
+use crate::ast::Rule;
+
+// the number of steps after which a run is considered not to terminate
+pub const RUN_LIMIT: usize = 10000;
+
+// a rule of a small-step semantics in the style of the Maude definitions:
+// either an axiom, signalled by returning the dummy configuration from
+// get_next_configuration, or a conditional rule whose premise is the returned configuration
+pub trait RewriteRule: Clone + PartialEq + fmt::Debug {
+    type Conf: Clone + Eq + Hash + fmt::Debug + fmt::Display;
+
+    fn dummy() -> Self::Conf;
+    fn is_dummy(conf: &Self::Conf) -> bool;
+    fn list_of_rules() -> Vec<Self>;
+    fn get_description(&self) -> String;
+    fn get_label(&self) -> String;
+    fn get_next_configuration(&self, conf: Self::Conf) -> Option<Self::Conf>;
+    // bottom is the configuration below the line,
+    // top is the configuration above the line, and we want to return the new top
+    fn reduce_down(&self, bottom: Self::Conf, top: Self::Conf) -> Option<Self::Conf>;
+    // whether the steps taken inside a configuration are charged to it when profiling
+    fn charged(_conf: &Self::Conf) -> bool {
+        false
+    }
+}
+
+// a completed step: the axiom that fired and the innermost charged configuration
+// of the derivation, if any
+#[derive(Clone, Debug)]
+pub struct Cost<R: RewriteRule> {
+    pub axiom: R,
+    pub charged: Option<R::Conf>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Stack<R: RewriteRule> {
+    stack: Vec<R::Conf>,
+    rules: Vec<R>,
+    // the configuration after each completed step, starting with the initial one
+    trace: Vec<R::Conf>,
+    // one entry per step of the trace
+    costs: Vec<Cost<R>>,
+    prev: Option<Box<Stack<R>>>,
+    next: Option<Box<Stack<R>>>,
+}
+
+impl<R: RewriteRule> fmt::Display for Stack<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.stack
+                .iter()
+                .rev()
+                .map(|x| x.to_string())
+                .collect::<Vec<String>>()
+                .join("\n---\n")
+        )
+    }
+}
+impl<R: RewriteRule> Stack<R> {
+    pub fn create(conf: R::Conf) -> Stack<R> {
+        Stack {
+            stack: vec![conf.clone()],
+            rules: vec![],
+            trace: vec![conf],
+            costs: vec![],
+            prev: None,
+            next: None,
+        }
+    }
+    pub fn clear(&mut self) {
+        self.rules.clear();
+        self.stack.drain(1..self.stack.len());
+    }
+    pub fn can_apply_rule(&self, rule: R) -> bool {
+        let last = self.stack.last().expect("oops");
+        let next_configuration = rule.get_next_configuration(last.clone());
+        match next_configuration {
+            Some(conf) if R::is_dummy(&conf) => {
+                // an axiom is only applicable if every premise below it can also be reduced,
+                // e.g. an uncaught exception cannot be raised from inside a try block
+                let mut top_conf = R::dummy();
+                let rules = self.rules.iter().chain(std::iter::once(&rule)).rev();
+                for (rule, bottom_conf) in rules.zip(self.stack.iter().rev()) {
+                    match rule.reduce_down(bottom_conf.clone(), top_conf) {
+                        None => return false,
+                        Some(x) => top_conf = x,
+                    }
+                }
+                true
+            }
+            Some(_conf) => true,
+            None => false,
+        }
+    }
+    // true means a sucess apply, false failed to apply rule
+    pub fn apply_rule(&mut self, rule: R) -> bool {
+        let old_rules = self.rules.clone();
+        let old_stack = self.stack.clone();
+        self.rules.push(rule.clone());
+        let last = self.stack.last().expect("oops");
+        let next_configuration = rule.get_next_configuration(last.clone());
+        match next_configuration {
+            Some(conf) if R::is_dummy(&conf) => {
+                let charged = self
+                    .stack
+                    .iter()
+                    .rev()
+                    .find(|conf| R::charged(conf))
+                    .cloned();
+                let mut top_conf = R::dummy();
+                while let Some(rule) = self.rules.pop() {
+                    let bottom_conf = self.stack.pop().expect("failed to pop stack");
+                    match rule.reduce_down(bottom_conf.clone(), top_conf) {
+                        None => {
+                            //restore the stack
+                            self.stack = old_stack;
+                            self.rules = old_rules;
+                            return false;
+                        }
+                        Some(x) => top_conf = x,
+                    }
+                }
+                self.stack.push(top_conf.clone());
+                let old_trace = self.trace.clone();
+                self.trace.push(top_conf);
+                let old_costs = self.costs.clone();
+                self.costs.push(Cost {
+                    axiom: rule,
+                    charged,
+                });
+
+                self.prev = Some(Box::new(Stack {
+                    stack: old_stack,
+                    rules: old_rules,
+                    trace: old_trace,
+                    costs: old_costs,
+                    prev: self.prev.clone(),
+                    next: None,
+                }))
+            }
+            Some(conf) => {
+                self.stack.push(conf);
+
+                self.prev = Some(Box::new(Stack {
+                    stack: old_stack,
+                    rules: old_rules,
+                    trace: self.trace.clone(),
+                    costs: self.costs.clone(),
+                    prev: self.prev.clone(),
+                    next: None,
+                }))
+            }
+            None => {
+                self.rules.pop();
+                return false;
+            }
+        }
+        true
+    }
+    pub fn top(&self) -> &R::Conf {
+        self.stack.last().expect("oops")
+    }
+    pub fn trace(&self) -> &[R::Conf] {
+        &self.trace
+    }
+    pub fn costs(&self) -> &[Cost<R>] {
+        &self.costs
+    }
+    // the cost of a step found by derive_steps, replaying its congruences from the top
+    fn charge(&self, chain: &[R]) -> Cost<R> {
+        let mut confs = self.stack.clone();
+        let (axiom, congruences) = chain.split_last().expect("a step ends with an axiom");
+        for rule in congruences {
+            let last = confs.last().expect("oops").clone();
+            confs.extend(rule.get_next_configuration(last));
+        }
+        Cost {
+            axiom: axiom.clone(),
+            charged: confs.into_iter().rev().find(|conf| R::charged(conf)),
+        }
+    }
+    // the derivations of full steps from the top of the stack: congruences are tried in
+    // the order of rules until an axiom applies and the whole chain reduces down,
+    // stopping at the first one unless all are wanted
+    fn derive_steps(&self, rules: &[R], all: bool) -> Vec<(Vec<R>, R::Conf)> {
+        fn search<R: RewriteRule>(
+            confs: &mut Vec<R::Conf>,
+            chain: &mut Vec<R>,
+            rules: &[R],
+            all: bool,
+            found: &mut Vec<(Vec<R>, R::Conf)>,
+        ) {
+            let last = confs.last().expect("oops").clone();
+            for rule in rules {
+                if !all && !found.is_empty() {
+                    return;
+                }
+                match rule.get_next_configuration(last.clone()) {
+                    Some(conf) if R::is_dummy(&conf) => {
+                        chain.push(rule.clone());
+                        let reduced = chain.iter().rev().zip(confs.iter().rev()).try_fold(
+                            R::dummy(),
+                            |top_conf, (rule, bottom_conf)| {
+                                rule.reduce_down(bottom_conf.clone(), top_conf)
+                            },
+                        );
+                        if let Some(conf) = reduced {
+                            found.push((chain.clone(), conf));
+                        }
+                        chain.pop();
+                    }
+                    Some(conf) => {
+                        confs.push(conf);
+                        chain.push(rule.clone());
+                        search(confs, chain, rules, all, found);
+                        confs.pop();
+                        chain.pop();
+                    }
+                    None => (),
+                }
+            }
+        }
+        let mut confs = self.stack.clone();
+        let mut chain = self.rules.clone();
+        let mut found = vec![];
+        search(&mut confs, &mut chain, rules, all, &mut found);
+        found
+            .into_iter()
+            .map(|(mut chain, conf)| (chain.split_off(self.rules.len()), conf))
+            .collect()
+    }
+    fn find_step(&self, rules: &[R]) -> Option<(Vec<R>, R::Conf)> {
+        self.derive_steps(rules, false).into_iter().next()
+    }
+    // every full step from the top of the stack, with the rules that derive it
+    pub fn successors(&self, rules: &[R]) -> Vec<(Vec<R>, R::Conf)> {
+        self.derive_steps(rules, true)
+    }
+    // applies the rules of the first full step found, false if the configuration is stuck
+    pub fn step(&mut self, rules: &[R]) -> bool {
+        match self.find_step(rules) {
+            Some((chain, _conf)) => chain.into_iter().all(|rule| self.apply_rule(rule)),
+            None => false,
+        }
+    }
+    // takes at most limit steps, recorded as a single entry of the undo history,
+    // and stops early when a configuration repeats since the run can then never finish;
+    // returns the number of steps taken
+    pub fn run(&mut self, rules: &[R], limit: usize) -> usize {
+        let old_stack = self.stack.clone();
+        let old_rules = self.rules.clone();
+        let old_trace = self.trace.clone();
+        let old_costs = self.costs.clone();
+        let mut visited: HashMap<R::Conf, usize> = self.trace.iter().cloned().zip(0..).collect();
+        let mut steps = 0;
+        while steps < limit {
+            match self.find_step(rules) {
+                Some((chain, conf)) => {
+                    let cost = self.charge(&chain);
+                    self.costs.push(cost);
+                    self.stack = vec![conf.clone()];
+                    self.rules.clear();
+                    self.trace.push(conf.clone());
+                    steps += 1;
+                    if visited.insert(conf, self.trace.len() - 1).is_some() {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+        if steps > 0 {
+            self.prev = Some(Box::new(Stack {
+                stack: old_stack,
+                rules: old_rules,
+                trace: old_trace,
+                costs: old_costs,
+                prev: self.prev.take(),
+                next: None,
+            }));
+            self.next = None;
+        }
+        steps
+    }
+    // the first step that re-enters an identical configuration, and the step that first reached it
+    pub fn cycle(&self) -> Option<(usize, usize)> {
+        let mut visited: HashMap<&R::Conf, usize> = HashMap::new();
+        for (step, conf) in self.trace.iter().enumerate() {
+            if let Some(first) = visited.insert(conf, step) {
+                return Some((first, step));
+            }
+        }
+        None
+    }
+    pub fn pop(&mut self) {
+        if self.stack.len() > 1 {
+            self.stack.pop();
+            self.rules.pop();
+        }
+    }
+    pub fn create_from_prev(stack: &mut Stack<R>, prev_stack: Stack<R>) -> Stack<R> {
+        Stack {
+            stack: prev_stack.stack.clone(),
+            rules: prev_stack.rules.clone(),
+            trace: prev_stack.trace.clone(),
+            costs: prev_stack.costs.clone(),
+            prev: prev_stack.prev,
+            next: Some(Box::new(stack.clone())),
+        }
+    }
+    pub fn create_from_next(stack: &mut Stack<R>, next_stack: Stack<R>) -> Stack<R> {
+        Stack {
+            stack: next_stack.stack.clone(),
+            rules: next_stack.rules.clone(),
+            trace: next_stack.trace.clone(),
+            costs: next_stack.costs.clone(),
+            prev: Some(Box::new(stack.clone())),
+            next: next_stack.next,
+        }
+    }
+    pub fn undo(&mut self) {
+        let prev = self.clone();
+        match prev.prev {
+            None => (),
+            Some(b) => {
+                let ns = Stack::create_from_prev(self, *b);
+                self.stack = ns.stack.clone();
+                self.rules = ns.rules.clone();
+                self.trace = ns.trace.clone();
+                self.costs = ns.costs.clone();
+                self.prev = ns.prev.clone();
+                self.next = ns.next;
+            }
+        }
+    }
+    pub fn redo(&mut self) {
+        let next = self.clone();
+        match next.next {
+            None => (),
+            Some(box1) => {
+                let ns = Stack::create_from_next(self, *box1);
+                self.stack = ns.stack.clone();
+                self.rules = ns.rules.clone();
+                self.trace = ns.trace.clone();
+                self.costs = ns.costs.clone();
+                self.prev = ns.prev.clone();
+                self.next = ns.next;
+            }
+        }
+    }
+}
+
+// the final state of a run of the small-step semantics, None if it does not finish
+pub fn run(pgm: &Pgm) -> Option<Vec<(String, i32)>> {
+    let mut stack = Stack::create(Configuration::PgmConf(Box::new(pgm.clone())));
+    stack.run(&Rule::list_of_rules(), RUN_LIMIT);
+    stack.top().final_state()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Rule;
+
+    #[test]
+    fn test_cycle() {
+        let mut stack =
+            Stack::<Rule>::create_from_string("int x; while (x <= 0) { x = x + 0; }".to_string())
+                .unwrap();
+        assert_eq!(stack.run(&Rule::list_of_rules(), 10000), 11);
+        assert_eq!(stack.cycle(), Some((1, 11)));
+        stack.undo();
+        assert_eq!(stack.cycle(), None);
+    }
+    #[test]
+    fn test_executing() {
+        let source = "int x;\nx = 1;\nwhile (x <= 1) { x = x + 1; }";
+        let mut stack = Stack::<Rule>::create_from_string(source.to_string()).unwrap();
+        let mut executing = vec![];
+        loop {
+            if let Some(span) = stack.top().executing() {
+                let text = &source[span.start..span.end];
+                if executing.last() != Some(&text) {
+                    executing.push(text);
+                }
+            }
+            if !stack.step(&Rule::list_of_rules()) {
+                break;
+            }
+        }
+        assert_eq!(
+            executing,
+            vec![
+                "x = 1;",
+                "while (x <= 1) { x = x + 1; }",
+                "x = x + 1;",
+                "while (x <= 1) { x = x + 1; }",
+            ]
+        );
+    }
+}
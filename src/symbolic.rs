@@ -0,0 +1,389 @@
+use std::fmt;
+
+use crate::ast::AExp;
+use crate::ast::BExp;
+use crate::ast::Block;
+use crate::ast::Pgm;
+use crate::ast::Span;
+use crate::ast::Stmt;
+use crate::bmc::satisfiable;
+use crate::parse::parse;
+use crate::stack::RewriteRule;
+use crate::stack::Stack;
+
+// a symbolic store mapping each variable to a term over the initial values x', y', ...
+// and the path condition Phi under which the current path is taken
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct SymState(Vec<(String, AExp)>, BExp);
+
+impl fmt::Display for SymState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}], {}",
+            self.0
+                .iter()
+                .map(|(x, a)| format!("{} |-> {}", x, a))
+                .collect::<Vec<String>>()
+                .join(", "),
+            self.1
+        )
+    }
+}
+
+impl SymState {
+    fn create_state(xl: Vec<String>) -> SymState {
+        let store = xl
+            .into_iter()
+            .map(|x| {
+                let symbol = AExp::Id(format!("{}'", x), Span::default());
+                (x, symbol)
+            })
+            .collect();
+        SymState(store, BExp::Bool(true, Span::default()))
+    }
+    // Sigma(A), None if A mentions an undeclared variable
+    fn eval_aexp(&self, a: &AExp) -> Option<AExp> {
+        a.vars().iter().try_fold(a.clone(), |acc, x| {
+            let (_x, v) = self.0.iter().find(|(y, _v)| y == x)?;
+            Some(acc.substitute(x, v))
+        })
+    }
+    fn eval_bexp(&self, b: &BExp) -> Option<BExp> {
+        b.vars().iter().try_fold(b.clone(), |acc, x| {
+            let (_x, v) = self.0.iter().find(|(y, _v)| y == x)?;
+            Some(acc.substitute(x, v))
+        })
+    }
+    fn update(&self, x: &str, a: AExp) -> Option<SymState> {
+        let mut store = self.0.clone();
+        store.iter_mut().find(|(y, _v)| y == x)?.1 = a.simplify();
+        Some(SymState(store, self.1.clone()))
+    }
+    // Phi && B, None if no values of the symbols satisfy it
+    fn assume(&self, b: BExp) -> Option<SymState> {
+        let phi = BExp::And(Box::new(self.1.clone()), Box::new(b), Span::default()).simplify();
+        if feasible(&phi) {
+            Some(SymState(self.0.clone(), phi))
+        } else {
+            None
+        }
+    }
+}
+
+// the decision procedure: bit-blast Phi and ask the solver for values of the symbols;
+// a condition it cannot encode might hold, so its path is kept
+pub fn feasible(phi: &BExp) -> bool {
+    satisfiable(phi).unwrap_or(true)
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum SymConf {
+    StmtConf(Box<Stmt>, SymState),
+    PgmConf(Box<Pgm>),
+    Dummy,
+}
+
+impl fmt::Display for SymConf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymConf::StmtConf(s, sigma) => {
+                write!(f, "< {}, {} >", s, sigma)
+            }
+            SymConf::PgmConf(p) => {
+                write!(f, "< {} >", p)
+            }
+            SymConf::Dummy => {
+                write!(f, "Error this shouldn't be here")
+            }
+        }
+    }
+}
+
+impl Stack<SymRule> {
+    pub fn create_from_string(s: String) -> Result<Stack<SymRule>, String> {
+        let pgm = parse(s).map_err(|e| e.to_string())?;
+        Ok(Stack::create(SymConf::PgmConf(Box::new(pgm))))
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum SymRule {
+    // rl o < {S},Sigma,Phi > => < S,Sigma,Phi > .
+    BlockStatement,
+    // crl o < X = A ;,Sigma,Phi > => < {},Sigma[Sigma(A) / X],Phi > if Sigma(X) =/=Bool undefined .
+    Assignment,
+    // crl o < S1 S2,Sigma,Phi > => < S1' S2,Sigma',Phi' > if o < S1,Sigma,Phi > => < S1',Sigma',Phi' > .
+    Sequence,
+    // rl o < {} S2,Sigma,Phi > => < S2,Sigma,Phi > .
+    EmptyBlock,
+    // crl o < if (B) S1 else S2,Sigma,Phi > => < S1,Sigma,Phi && Sigma(B) > if feasible(Phi && Sigma(B)) .
+    ConditionalTrue,
+    // crl o < if (B) S1 else S2,Sigma,Phi > => < S2,Sigma,Phi && !Sigma(B) > if feasible(Phi && !Sigma(B)) .
+    ConditionalFalse,
+    // rl o < while (B) S,Sigma,Phi > => < if (B) {S while (B) S} else {},Sigma,Phi > .
+    Loop,
+    // rl o < S1 [] S2,Sigma,Phi > => < S1,Sigma,Phi > .
+    ChoiceLeft,
+    // rl o < S1 [] S2,Sigma,Phi > => < S2,Sigma,Phi > .
+    ChoiceRight,
+    // rl o < if (B) S,Sigma,Phi > => < if (B) S else {},Sigma,Phi > .
+    IfThen,
+    // rl o < do S while (B) ;,Sigma,Phi > => < {S while (B) S},Sigma,Phi > .
+    DoWhile,
+    // rl o < for (S1 B ; S2) S,Sigma,Phi > => < {S1 while (B) {S S2}},Sigma,Phi > .
+    For,
+    // rl o < int Xl ; S > => < S,(Xl |-> Xl'),true > .
+    Top,
+}
+
+impl RewriteRule for SymRule {
+    type Conf = SymConf;
+
+    fn dummy() -> SymConf {
+        SymConf::Dummy
+    }
+
+    fn is_dummy(conf: &SymConf) -> bool {
+        matches!(conf, SymConf::Dummy)
+    }
+
+    fn list_of_rules() -> Vec<SymRule> {
+        vec![
+            SymRule::BlockStatement,
+            SymRule::Assignment,
+            SymRule::Sequence,
+            SymRule::EmptyBlock,
+            SymRule::ConditionalTrue,
+            SymRule::ConditionalFalse,
+            SymRule::Loop,
+            SymRule::ChoiceLeft,
+            SymRule::ChoiceRight,
+            SymRule::IfThen,
+            SymRule::DoWhile,
+            SymRule::For,
+            SymRule::Top,
+        ]
+    }
+
+    fn get_description(&self) -> String {
+        match self {
+            SymRule::BlockStatement => "rl o < {S},Sigma,Phi > => < S,Sigma,Phi >".to_string(),
+            SymRule::Assignment => "crl o < X = A ;,Sigma,Phi > => < {},Sigma[Sigma(A) / X],Phi > if Sigma(X) =/=Bool undefined".to_string(),
+            SymRule::Sequence => "crl o < S1 S2,Sigma,Phi > => < S1' S2,Sigma',Phi' > if o < S1,Sigma,Phi > => < S1',Sigma',Phi' >".to_string(),
+            SymRule::EmptyBlock => "rl o < {} S2,Sigma,Phi > => < S2,Sigma,Phi >".to_string(),
+            SymRule::ConditionalTrue => "crl o < if (B) S1 else S2,Sigma,Phi > => < S1,Sigma,Phi && Sigma(B) > if feasible(Phi && Sigma(B))".to_string(),
+            SymRule::ConditionalFalse => "crl o < if (B) S1 else S2,Sigma,Phi > => < S2,Sigma,Phi && !Sigma(B) > if feasible(Phi && !Sigma(B))".to_string(),
+            SymRule::Loop => "rl o < while (B) S,Sigma,Phi > => < if (B) {S while (B) S} else {},Sigma,Phi >".to_string(),
+            SymRule::ChoiceLeft => "rl o < S1 [] S2,Sigma,Phi > => < S1,Sigma,Phi >".to_string(),
+            SymRule::ChoiceRight => "rl o < S1 [] S2,Sigma,Phi > => < S2,Sigma,Phi >".to_string(),
+            SymRule::IfThen => "rl o < if (B) S,Sigma,Phi > => < if (B) S else {},Sigma,Phi >".to_string(),
+            SymRule::DoWhile => "rl o < do S while (B) ;,Sigma,Phi > => < {S while (B) S},Sigma,Phi >".to_string(),
+            SymRule::For => "rl o < for (S1 B ; S2) S,Sigma,Phi > => < {S1 while (B) {S S2}},Sigma,Phi >".to_string(),
+            SymRule::Top => "rl o < int Xl ; S > => < S,(Xl |-> Xl'),true >".to_string(),
+        }
+    }
+
+    fn get_label(&self) -> String {
+        match self {
+            SymRule::BlockStatement => "Block Statement".to_string(),
+            SymRule::Assignment => "Assignment".to_string(),
+            SymRule::Sequence => "Sequence".to_string(),
+            SymRule::EmptyBlock => "Empty Block".to_string(),
+            SymRule::ConditionalTrue => "Conditional True".to_string(),
+            SymRule::ConditionalFalse => "Conditional False".to_string(),
+            SymRule::Loop => "Loop".to_string(),
+            SymRule::ChoiceLeft => "Choice Left".to_string(),
+            SymRule::ChoiceRight => "Choice Right".to_string(),
+            SymRule::IfThen => "If Then".to_string(),
+            SymRule::DoWhile => "Do While".to_string(),
+            SymRule::For => "For".to_string(),
+            SymRule::Top => "Top".to_string(),
+        }
+    }
+
+    fn get_next_configuration(&self, conf: SymConf) -> Option<SymConf> {
+        let ret = match self {
+            // crl o < S1 S2,Sigma,Phi > => < S1' S2,Sigma',Phi' > if o < S1,Sigma,Phi > => < S1',Sigma',Phi' > .
+            SymRule::Sequence => match conf {
+                SymConf::StmtConf(x, sigma) => match *x {
+                    Stmt::Sequence(s1, _s2, _) => SymConf::StmtConf(s1, sigma),
+                    _ => return None,
+                },
+                _ => return None,
+            },
+            _ => SymConf::Dummy,
+        };
+        Some(ret)
+    }
+
+    fn reduce_down(&self, bottom: SymConf, top: SymConf) -> Option<SymConf> {
+        let (s, sigma) = match (self, bottom) {
+            (SymRule::Top, SymConf::PgmConf(p)) => match *p {
+                Pgm::Program(xl, _levels, s) => {
+                    return Some(SymConf::StmtConf(s.into(), SymState::create_state(xl)))
+                }
+            },
+            (_, SymConf::StmtConf(s, sigma)) => (*s, sigma),
+            _ => return None,
+        };
+        let empty = || {
+            Box::new(Stmt::StmtBlock(
+                Box::new(Block::EmptyBlock),
+                Span::default(),
+            ))
+        };
+        let x = match (self, s) {
+            (SymRule::BlockStatement, Stmt::StmtBlock(b, _)) => match *b {
+                Block::BlockStmt(s, _) => SymConf::StmtConf(s, sigma),
+                Block::EmptyBlock => return None,
+            },
+            (SymRule::Assignment, Stmt::Assign(x, a, _)) => {
+                let v = sigma.eval_aexp(&a)?;
+                SymConf::StmtConf(empty(), sigma.update(&x, v)?)
+            }
+            (SymRule::Sequence, Stmt::Sequence(_s1, s2, _)) => match top {
+                SymConf::StmtConf(s1_prime, sigma_prime) => SymConf::StmtConf(
+                    Box::new(Stmt::Sequence(s1_prime, s2, Span::default())),
+                    sigma_prime,
+                ),
+                _ => return None,
+            },
+            (SymRule::EmptyBlock, Stmt::Sequence(s1, s2, _)) => match *s1 {
+                Stmt::StmtBlock(b, _) if *b == Block::EmptyBlock => SymConf::StmtConf(s2, sigma),
+                _ => return None,
+            },
+            (SymRule::ConditionalTrue, Stmt::IfThenElse(b, s1, _s2, _)) => {
+                let b = sigma.eval_bexp(&b)?;
+                SymConf::StmtConf(
+                    Box::new(Stmt::StmtBlock(s1, Span::default())),
+                    sigma.assume(b)?,
+                )
+            }
+            (SymRule::ConditionalFalse, Stmt::IfThenElse(b, _s1, s2, _)) => {
+                let b = sigma.eval_bexp(&b)?;
+                let not_b = BExp::Negation(Box::new(b), Span::default());
+                SymConf::StmtConf(
+                    Box::new(Stmt::StmtBlock(s2, Span::default())),
+                    sigma.assume(not_b)?,
+                )
+            }
+            (SymRule::Loop, Stmt::While(b, s, _)) => {
+                let body = Stmt::Sequence(
+                    Box::new(Stmt::StmtBlock(s.clone(), Span::default())),
+                    Box::new(Stmt::While(b.clone(), s, Span::default())),
+                    Span::default(),
+                );
+                let unrolled = Stmt::IfThenElse(
+                    b,
+                    Box::new(Block::BlockStmt(Box::new(body), Span::default())),
+                    Box::new(Block::EmptyBlock),
+                    Span::default(),
+                );
+                SymConf::StmtConf(Box::new(unrolled), sigma)
+            }
+            (SymRule::ChoiceLeft, Stmt::Choice(s1, _s2, _)) => {
+                SymConf::StmtConf(Box::new(Stmt::StmtBlock(s1, Span::default())), sigma)
+            }
+            (SymRule::ChoiceRight, Stmt::Choice(_s1, s2, _)) => {
+                SymConf::StmtConf(Box::new(Stmt::StmtBlock(s2, Span::default())), sigma)
+            }
+            (SymRule::IfThen, Stmt::IfThen(b, s, _)) => SymConf::StmtConf(
+                Box::new(Stmt::IfThenElse(
+                    b,
+                    s,
+                    Box::new(Block::EmptyBlock),
+                    Span::default(),
+                )),
+                sigma,
+            ),
+            (SymRule::DoWhile, Stmt::DoWhile(s, b, _)) => {
+                let body = Stmt::Sequence(
+                    Box::new(Stmt::StmtBlock(s.clone(), Span::default())),
+                    Box::new(Stmt::While(b, s, Span::default())),
+                    Span::default(),
+                );
+                SymConf::StmtConf(
+                    Box::new(Stmt::StmtBlock(
+                        Box::new(Block::BlockStmt(Box::new(body), Span::default())),
+                        Span::default(),
+                    )),
+                    sigma,
+                )
+            }
+            (SymRule::For, Stmt::For(s1, b, s2, s, _)) => {
+                let loop_body = Stmt::Sequence(
+                    Box::new(Stmt::StmtBlock(s, Span::default())),
+                    s2,
+                    Span::default(),
+                );
+                let while_loop = Stmt::While(
+                    b,
+                    Box::new(Block::BlockStmt(Box::new(loop_body), Span::default())),
+                    Span::default(),
+                );
+                let body = Stmt::Sequence(s1, Box::new(while_loop), Span::default());
+                SymConf::StmtConf(
+                    Box::new(Stmt::StmtBlock(
+                        Box::new(Block::BlockStmt(Box::new(body), Span::default())),
+                        Span::default(),
+                    )),
+                    sigma,
+                )
+            }
+            _ => return None,
+        };
+        Some(x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse_bexp;
+
+    #[test]
+    fn test_paths() {
+        let mut stack = Stack::<SymRule>::create_from_string(
+            "int x; if (x <= 0) { x = 0 + 1; } else { if (x <= 0) { x = 2; } else {} }".to_string(),
+        )
+        .unwrap();
+        assert!(stack.apply_rule(SymRule::Top));
+        assert!(stack.apply_rule(SymRule::ConditionalFalse));
+        assert!(stack.apply_rule(SymRule::BlockStatement));
+        // x' <= 0 contradicts the path condition
+        assert!(!stack.can_apply_rule(SymRule::ConditionalTrue));
+        assert!(stack.apply_rule(SymRule::ConditionalFalse));
+        stack.undo();
+        stack.undo();
+        stack.undo();
+        assert!(stack.apply_rule(SymRule::ConditionalTrue));
+        assert!(stack.apply_rule(SymRule::BlockStatement));
+        assert!(stack.apply_rule(SymRule::Assignment));
+        assert!(stack.to_string().contains("x |-> 1"));
+        assert!(stack.to_string().contains("(x' <= 0)"));
+    }
+    #[test]
+    fn test_large_constants() {
+        // the branches are reachable only with values far outside any small range
+        let mut stack = Stack::<SymRule>::create_from_string(
+            "int x; if (10 <= x) { if (x <= 100000) { x = 1; } else { x = 2; } } else { }"
+                .to_string(),
+        )
+        .unwrap();
+        assert!(stack.apply_rule(SymRule::Top));
+        assert!(stack.apply_rule(SymRule::ConditionalTrue));
+        assert!(stack.apply_rule(SymRule::BlockStatement));
+        assert!(stack.can_apply_rule(SymRule::ConditionalTrue));
+        assert!(stack.apply_rule(SymRule::ConditionalFalse));
+        assert!(stack.apply_rule(SymRule::BlockStatement));
+        assert!(stack.apply_rule(SymRule::Assignment));
+        assert!(stack.to_string().contains("x |-> 2"));
+        assert!(feasible(
+            &parse_bexp("x <= 2147483647".to_string()).unwrap()
+        ));
+        assert!(!feasible(
+            &parse_bexp("!(x <= 2147483647)".to_string()).unwrap()
+        ));
+    }
+}
@@ -0,0 +1,288 @@
+use std::fmt;
+
+use crate::ast::AExp;
+use crate::ast::BExp;
+use crate::ast::Block;
+use crate::ast::Pgm;
+use crate::ast::Span;
+use crate::ast::Stmt;
+use crate::derivation::Derivation;
+use crate::derivation::InferenceRule;
+
+// Gamma is the list of declared variables, all of which have type int
+#[derive(Clone, Debug)]
+pub enum TypeJudgment {
+    AExpInt(Vec<String>, AExp),
+    BExpBool(Vec<String>, BExp),
+    StmtOk(Vec<String>, Stmt),
+    PgmOk(Pgm),
+}
+
+impl fmt::Display for TypeJudgment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeJudgment::AExpInt(gamma, a) => {
+                write!(f, "{{{}}} |- {} : int", gamma.join(", "), a)
+            }
+            TypeJudgment::BExpBool(gamma, b) => {
+                write!(f, "{{{}}} |- {} : bool", gamma.join(", "), b)
+            }
+            TypeJudgment::StmtOk(gamma, s) => {
+                write!(f, "{{{}}} |- {} ok", gamma.join(", "), s)
+            }
+            TypeJudgment::PgmOk(p) => {
+                write!(f, "|- {} ok", p)
+            }
+        }
+    }
+}
+
+fn block_stmt(b: Block) -> Stmt {
+    Stmt::StmtBlock(Box::new(b), Span::default())
+}
+
+// runs the type checker to completion, returning the first goal that cannot be derived
+pub fn check(pgm: &Pgm) -> Result<Derivation<TypeRule>, String> {
+    let mut derivation = Derivation::create(TypeJudgment::PgmOk(pgm.clone()));
+    if derivation.auto() {
+        Ok(derivation)
+    } else {
+        match derivation.current_goal() {
+            Some(goal) => Err(format!("Type error: cannot derive {}", goal)),
+            None => Err("Type error".to_string()),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum TypeRule {
+    // Gamma |- I : int .
+    Int,
+    // Gamma |- X : int if X in Gamma .
+    Variable,
+    // Gamma |- A1 + A2 : int if Gamma |- A1 : int /\ Gamma |- A2 : int .
+    Plus,
+    // Gamma |- A1 / A2 : int if Gamma |- A1 : int /\ Gamma |- A2 : int .
+    Divide,
+    // Gamma |- * A : int if Gamma |- A : int .
+    Deref,
+    // Gamma |- T : bool .
+    Bool,
+    // Gamma |- A1 <= A2 : bool if Gamma |- A1 : int /\ Gamma |- A2 : int .
+    LessThan,
+    // Gamma |- ! B : bool if Gamma |- B : bool .
+    Negate,
+    // Gamma |- B1 && B2 : bool if Gamma |- B1 : bool /\ Gamma |- B2 : bool .
+    And,
+    // Gamma |- {} ok .
+    EmptyBlock,
+    // Gamma |- {S} ok if Gamma |- S ok .
+    Block,
+    // Gamma |- X = A ; ok if X in Gamma /\ Gamma |- A : int .
+    Assignment,
+    // Gamma |- S1 S2 ok if Gamma |- S1 ok /\ Gamma |- S2 ok .
+    Sequence,
+    // Gamma |- if (B) S1 else S2 ok if Gamma |- B : bool /\ Gamma |- S1 ok /\ Gamma |- S2 ok .
+    Conditional,
+    // Gamma |- if (B) S ok if Gamma |- B : bool /\ Gamma |- S ok .
+    IfThen,
+    // Gamma |- while (B) S ok if Gamma |- B : bool /\ Gamma |- S ok .
+    Loop,
+    // Gamma |- do S while (B) ; ok if Gamma |- S ok /\ Gamma |- B : bool .
+    DoWhile,
+    // Gamma |- for (S1 B ; S2) S ok if Gamma |- S1 ok /\ Gamma |- B : bool /\ Gamma |- S2 ok /\ Gamma |- S ok .
+    For,
+    // Gamma |- S1 [] S2 ok if Gamma |- S1 ok /\ Gamma |- S2 ok .
+    Choice,
+    // Gamma |- throw A ; ok if Gamma |- A : int .
+    Throw,
+    // Gamma |- try S1 catch (X) S2 ok if X in Gamma /\ Gamma |- S1 ok /\ Gamma |- S2 ok .
+    TryCatch,
+    // Gamma |- X = new ; ok if X in Gamma .
+    New,
+    // Gamma |- * A1 = A2 ; ok if Gamma |- A1 : int /\ Gamma |- A2 : int .
+    AssignDeref,
+    // |- int Xl ; S ok if Xl |- S ok .
+    Program,
+}
+
+impl InferenceRule for TypeRule {
+    type Judgment = TypeJudgment;
+
+    fn list_of_rules() -> Vec<TypeRule> {
+        vec![
+            TypeRule::Int,
+            TypeRule::Variable,
+            TypeRule::Plus,
+            TypeRule::Divide,
+            TypeRule::Deref,
+            TypeRule::Bool,
+            TypeRule::LessThan,
+            TypeRule::Negate,
+            TypeRule::And,
+            TypeRule::EmptyBlock,
+            TypeRule::Block,
+            TypeRule::Assignment,
+            TypeRule::Sequence,
+            TypeRule::Conditional,
+            TypeRule::IfThen,
+            TypeRule::Loop,
+            TypeRule::DoWhile,
+            TypeRule::For,
+            TypeRule::Choice,
+            TypeRule::Throw,
+            TypeRule::TryCatch,
+            TypeRule::New,
+            TypeRule::AssignDeref,
+            TypeRule::Program,
+        ]
+    }
+
+    fn get_description(&self) -> String {
+        match self {
+            TypeRule::Int => "Gamma |- I : int".to_string(),
+            TypeRule::Variable => "Gamma |- X : int if X in Gamma".to_string(),
+            TypeRule::Plus => "Gamma |- A1 + A2 : int if Gamma |- A1 : int /\\ Gamma |- A2 : int".to_string(),
+            TypeRule::Divide => "Gamma |- A1 / A2 : int if Gamma |- A1 : int /\\ Gamma |- A2 : int".to_string(),
+            TypeRule::Deref => "Gamma |- * A : int if Gamma |- A : int".to_string(),
+            TypeRule::Bool => "Gamma |- T : bool".to_string(),
+            TypeRule::LessThan => "Gamma |- A1 <= A2 : bool if Gamma |- A1 : int /\\ Gamma |- A2 : int".to_string(),
+            TypeRule::Negate => "Gamma |- ! B : bool if Gamma |- B : bool".to_string(),
+            TypeRule::And => "Gamma |- B1 && B2 : bool if Gamma |- B1 : bool /\\ Gamma |- B2 : bool".to_string(),
+            TypeRule::EmptyBlock => "Gamma |- {} ok".to_string(),
+            TypeRule::Block => "Gamma |- {S} ok if Gamma |- S ok".to_string(),
+            TypeRule::Assignment => "Gamma |- X = A ; ok if X in Gamma /\\ Gamma |- A : int".to_string(),
+            TypeRule::Sequence => "Gamma |- S1 S2 ok if Gamma |- S1 ok /\\ Gamma |- S2 ok".to_string(),
+            TypeRule::Conditional => "Gamma |- if (B) S1 else S2 ok if Gamma |- B : bool /\\ Gamma |- S1 ok /\\ Gamma |- S2 ok".to_string(),
+            TypeRule::IfThen => "Gamma |- if (B) S ok if Gamma |- B : bool /\\ Gamma |- S ok".to_string(),
+            TypeRule::Loop => "Gamma |- while (B) S ok if Gamma |- B : bool /\\ Gamma |- S ok".to_string(),
+            TypeRule::DoWhile => "Gamma |- do S while (B) ; ok if Gamma |- S ok /\\ Gamma |- B : bool".to_string(),
+            TypeRule::For => "Gamma |- for (S1 B ; S2) S ok if Gamma |- S1 ok /\\ Gamma |- B : bool /\\ Gamma |- S2 ok /\\ Gamma |- S ok".to_string(),
+            TypeRule::Choice => "Gamma |- S1 [] S2 ok if Gamma |- S1 ok /\\ Gamma |- S2 ok".to_string(),
+            TypeRule::Throw => "Gamma |- throw A ; ok if Gamma |- A : int".to_string(),
+            TypeRule::TryCatch => "Gamma |- try S1 catch (X) S2 ok if X in Gamma /\\ Gamma |- S1 ok /\\ Gamma |- S2 ok".to_string(),
+            TypeRule::New => "Gamma |- X = new ; ok if X in Gamma".to_string(),
+            TypeRule::AssignDeref => "Gamma |- * A1 = A2 ; ok if Gamma |- A1 : int /\\ Gamma |- A2 : int".to_string(),
+            TypeRule::Program => "|- int Xl ; S ok if Xl |- S ok".to_string(),
+        }
+    }
+
+    fn get_label(&self) -> String {
+        match self {
+            TypeRule::Int => "Integer".to_string(),
+            TypeRule::Variable => "Variable".to_string(),
+            TypeRule::Plus => "Plus".to_string(),
+            TypeRule::Divide => "Divide".to_string(),
+            TypeRule::Deref => "Dereference".to_string(),
+            TypeRule::Bool => "Boolean".to_string(),
+            TypeRule::LessThan => "Less Than".to_string(),
+            TypeRule::Negate => "Negate".to_string(),
+            TypeRule::And => "And".to_string(),
+            TypeRule::EmptyBlock => "Empty Block".to_string(),
+            TypeRule::Block => "Block".to_string(),
+            TypeRule::Assignment => "Assignment".to_string(),
+            TypeRule::Sequence => "Sequence".to_string(),
+            TypeRule::Conditional => "Conditional".to_string(),
+            TypeRule::IfThen => "If Then".to_string(),
+            TypeRule::Loop => "Loop".to_string(),
+            TypeRule::DoWhile => "Do While".to_string(),
+            TypeRule::For => "For".to_string(),
+            TypeRule::Choice => "Choice".to_string(),
+            TypeRule::Throw => "Throw".to_string(),
+            TypeRule::TryCatch => "Try Catch".to_string(),
+            TypeRule::New => "New".to_string(),
+            TypeRule::AssignDeref => "Pointer Assignment".to_string(),
+            TypeRule::Program => "Program".to_string(),
+        }
+    }
+
+    fn premises(&self, judgment: &TypeJudgment) -> Option<Vec<TypeJudgment>> {
+        use TypeJudgment::*;
+        let premises = match (self, judgment.clone()) {
+            (TypeRule::Int, AExpInt(_gamma, AExp::Int(_i, _))) => vec![],
+            (TypeRule::Variable, AExpInt(gamma, AExp::Id(x, _))) if gamma.contains(&x) => vec![],
+            (TypeRule::Plus, AExpInt(gamma, AExp::Plus(a1, a2, _)))
+            | (TypeRule::Divide, AExpInt(gamma, AExp::Divide(a1, a2, _))) => {
+                vec![AExpInt(gamma.clone(), *a1), AExpInt(gamma, *a2)]
+            }
+            (TypeRule::Deref, AExpInt(gamma, AExp::Deref(a, _))) => vec![AExpInt(gamma, *a)],
+            (TypeRule::Bool, BExpBool(_gamma, BExp::Bool(_b, _))) => vec![],
+            (TypeRule::LessThan, BExpBool(gamma, BExp::LessThanEq(a1, a2, _))) => {
+                vec![AExpInt(gamma.clone(), *a1), AExpInt(gamma, *a2)]
+            }
+            (TypeRule::Negate, BExpBool(gamma, BExp::Negation(b, _))) => vec![BExpBool(gamma, *b)],
+            (TypeRule::And, BExpBool(gamma, BExp::And(b1, b2, _))) => {
+                vec![BExpBool(gamma.clone(), *b1), BExpBool(gamma, *b2)]
+            }
+            (TypeRule::EmptyBlock, StmtOk(_gamma, Stmt::StmtBlock(b, _))) => match *b {
+                Block::EmptyBlock => vec![],
+                _ => return None,
+            },
+            (TypeRule::Block, StmtOk(gamma, Stmt::StmtBlock(b, _))) => match *b {
+                Block::BlockStmt(s, _) => vec![StmtOk(gamma, *s)],
+                _ => return None,
+            },
+            (TypeRule::Assignment, StmtOk(gamma, Stmt::Assign(x, a, _))) if gamma.contains(&x) => {
+                vec![AExpInt(gamma, *a)]
+            }
+            (TypeRule::Sequence, StmtOk(gamma, Stmt::Sequence(s1, s2, _))) => {
+                vec![StmtOk(gamma.clone(), *s1), StmtOk(gamma, *s2)]
+            }
+            (TypeRule::Conditional, StmtOk(gamma, Stmt::IfThenElse(b, s1, s2, _))) => vec![
+                BExpBool(gamma.clone(), *b),
+                StmtOk(gamma.clone(), block_stmt(*s1)),
+                StmtOk(gamma, block_stmt(*s2)),
+            ],
+            (TypeRule::IfThen, StmtOk(gamma, Stmt::IfThen(b, s, _)))
+            | (TypeRule::Loop, StmtOk(gamma, Stmt::While(b, s, _))) => {
+                vec![BExpBool(gamma.clone(), *b), StmtOk(gamma, block_stmt(*s))]
+            }
+            (TypeRule::DoWhile, StmtOk(gamma, Stmt::DoWhile(s, b, _))) => {
+                vec![StmtOk(gamma.clone(), block_stmt(*s)), BExpBool(gamma, *b)]
+            }
+            (TypeRule::For, StmtOk(gamma, Stmt::For(s1, b, s2, s, _))) => vec![
+                StmtOk(gamma.clone(), *s1),
+                BExpBool(gamma.clone(), *b),
+                StmtOk(gamma.clone(), *s2),
+                StmtOk(gamma, block_stmt(*s)),
+            ],
+            (TypeRule::Choice, StmtOk(gamma, Stmt::Choice(s1, s2, _))) => vec![
+                StmtOk(gamma.clone(), block_stmt(*s1)),
+                StmtOk(gamma, block_stmt(*s2)),
+            ],
+            (TypeRule::Throw, StmtOk(gamma, Stmt::Throw(a, _))) => vec![AExpInt(gamma, *a)],
+            (TypeRule::TryCatch, StmtOk(gamma, Stmt::TryCatch(s1, x, s2, _)))
+                if gamma.contains(&x) =>
+            {
+                vec![
+                    StmtOk(gamma.clone(), block_stmt(*s1)),
+                    StmtOk(gamma, block_stmt(*s2)),
+                ]
+            }
+            (TypeRule::New, StmtOk(gamma, Stmt::New(x, _))) if gamma.contains(&x) => vec![],
+            (TypeRule::AssignDeref, StmtOk(gamma, Stmt::AssignDeref(a1, a2, _))) => {
+                vec![AExpInt(gamma.clone(), *a1), AExpInt(gamma, *a2)]
+            }
+            (TypeRule::Program, PgmOk(Pgm::Program(xl, _levels, s))) => vec![StmtOk(xl, s)],
+            _ => return None,
+        };
+        Some(premises)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse;
+
+    #[test]
+    fn test_well_typed() {
+        let pgm = parse("int x, y; x = 1; while x <= y { y = y + x; }".to_string()).unwrap();
+        assert!(check(&pgm).is_ok());
+    }
+    #[test]
+    fn test_undeclared() {
+        let pgm = parse("int x; y = 1;".to_string()).unwrap();
+        assert!(check(&pgm).is_err());
+    }
+}
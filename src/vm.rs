@@ -0,0 +1,360 @@
+use std::fmt;
+
+use crate::ast::AExp;
+use crate::ast::BExp;
+use crate::ast::Block;
+use crate::ast::Pgm;
+use crate::ast::Stmt;
+
+// booleans are 1 for true and 0 for false on the operand stack
+#[derive(Clone, PartialEq, Debug)]
+pub enum Instr {
+    Push(i32),
+    Load(String),
+    Store(String),
+    Add,
+    Div,
+    Leq,
+    Jump(usize),
+    // pops the top of the stack and jumps if it is 0
+    JumpZ(usize),
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instr::Push(i) => write!(f, "push {}", i),
+            Instr::Load(x) => write!(f, "load {}", x),
+            Instr::Store(x) => write!(f, "store {}", x),
+            Instr::Add => write!(f, "add"),
+            Instr::Div => write!(f, "div"),
+            Instr::Leq => write!(f, "leq"),
+            Instr::Jump(l) => write!(f, "jump {}", l),
+            Instr::JumpZ(l) => write!(f, "jumpz {}", l),
+        }
+    }
+}
+
+// the code of a program, where each instruction remembers the statement it was compiled from
+struct Compiler {
+    vars: Vec<String>,
+    code: Vec<Instr>,
+    origins: Vec<String>,
+}
+
+impl Compiler {
+    fn emit(&mut self, i: Instr, origin: &str) -> usize {
+        self.code.push(i);
+        self.origins.push(origin.to_string());
+        self.code.len() - 1
+    }
+    // fills in the target of a forward jump
+    fn patch(&mut self, at: usize, target: usize) {
+        self.code[at] = match self.code[at] {
+            Instr::Jump(_l) => Instr::Jump(target),
+            Instr::JumpZ(_l) => Instr::JumpZ(target),
+            ref i => i.clone(),
+        };
+    }
+    fn aexp(&mut self, a: &AExp, origin: &str) -> Result<(), String> {
+        match a {
+            AExp::Plus(a1, a2, _) | AExp::Divide(a1, a2, _) => {
+                self.aexp(a1, origin)?;
+                self.aexp(a2, origin)?;
+                let op = if let AExp::Plus(_a1, _a2, _) = a {
+                    Instr::Add
+                } else {
+                    Instr::Div
+                };
+                self.emit(op, origin);
+            }
+            AExp::Id(x, _) if self.vars.contains(x) => {
+                self.emit(Instr::Load(x.clone()), origin);
+            }
+            AExp::Id(x, _) => return Err(format!("Undeclared variable {}", x)),
+            AExp::Int(i, _) => {
+                self.emit(Instr::Push(*i), origin);
+            }
+            AExp::Deref(_a, _) => return Err("Pointers cannot be compiled".to_string()),
+        }
+        Ok(())
+    }
+    fn bexp(&mut self, b: &BExp, origin: &str) -> Result<(), String> {
+        match b {
+            BExp::LessThanEq(a1, a2, _) => {
+                self.aexp(a1, origin)?;
+                self.aexp(a2, origin)?;
+                self.emit(Instr::Leq, origin);
+            }
+            // !B is if (B) 0 else 1
+            BExp::Negation(b, _) => {
+                self.bexp(b, origin)?;
+                let jumpz = self.emit(Instr::JumpZ(0), origin);
+                self.emit(Instr::Push(0), origin);
+                let jump = self.emit(Instr::Jump(0), origin);
+                self.patch(jumpz, self.code.len());
+                self.emit(Instr::Push(1), origin);
+                self.patch(jump, self.code.len());
+            }
+            // the semantics has no rules for &&, so a program using it gets stuck there
+            BExp::And(_b1, _b2, _) => return Err(format!("&& cannot be compiled: {}", b)),
+            BExp::Bool(b, _) => {
+                self.emit(Instr::Push(*b as i32), origin);
+            }
+        }
+        Ok(())
+    }
+    fn stmt(&mut self, s: &Stmt) -> Result<(), String> {
+        match s {
+            Stmt::StmtBlock(b, _) => self.block(b)?,
+            Stmt::Assign(x, a, _) => {
+                if !self.vars.contains(x) {
+                    return Err(format!("Undeclared variable {}", x));
+                }
+                let origin = s.to_string();
+                self.aexp(a, origin.trim())?;
+                self.emit(Instr::Store(x.clone()), origin.trim());
+            }
+            Stmt::Sequence(s1, s2, _) => {
+                self.stmt(s1)?;
+                self.stmt(s2)?;
+            }
+            Stmt::IfThenElse(b, b1, b2, _) => {
+                let origin = format!("if {}", b);
+                self.bexp(b, &origin)?;
+                let jumpz = self.emit(Instr::JumpZ(0), &origin);
+                self.block(b1)?;
+                let jump = self.emit(Instr::Jump(0), &origin);
+                self.patch(jumpz, self.code.len());
+                self.block(b2)?;
+                self.patch(jump, self.code.len());
+            }
+            Stmt::IfThen(b, b1, _) => {
+                let origin = format!("if {}", b);
+                self.bexp(b, &origin)?;
+                let jumpz = self.emit(Instr::JumpZ(0), &origin);
+                self.block(b1)?;
+                self.patch(jumpz, self.code.len());
+            }
+            Stmt::While(b, b1, _) => {
+                let origin = format!("while {}", b);
+                let start = self.code.len();
+                self.bexp(b, &origin)?;
+                let jumpz = self.emit(Instr::JumpZ(0), &origin);
+                self.block(b1)?;
+                self.emit(Instr::Jump(start), &origin);
+                self.patch(jumpz, self.code.len());
+            }
+            // do S while (B) jumps back while B is true, i.e. past a jump taken when it is false
+            Stmt::DoWhile(b1, b, _) => {
+                let origin = format!("do ... while {}", b);
+                let start = self.code.len();
+                self.block(b1)?;
+                self.bexp(b, &origin)?;
+                let jumpz = self.emit(Instr::JumpZ(0), &origin);
+                self.emit(Instr::Jump(start), &origin);
+                self.patch(jumpz, self.code.len());
+            }
+            Stmt::For(init, b, update, b1, _) => {
+                let origin = format!("for (...; {}; ...)", b);
+                self.stmt(init)?;
+                let start = self.code.len();
+                self.bexp(b, &origin)?;
+                let jumpz = self.emit(Instr::JumpZ(0), &origin);
+                self.block(b1)?;
+                self.stmt(update)?;
+                self.emit(Instr::Jump(start), &origin);
+                self.patch(jumpz, self.code.len());
+            }
+            s => {
+                return Err(format!(
+                "Only deterministic programs without exceptions or pointers can be compiled: {}",
+                s.to_string().trim()
+            ))
+            }
+        }
+        Ok(())
+    }
+    fn block(&mut self, b: &Block) -> Result<(), String> {
+        match b {
+            Block::EmptyBlock => Ok(()),
+            Block::BlockStmt(s, _) => self.stmt(s),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Vm {
+    code: Vec<Instr>,
+    origins: Vec<String>,
+    ip: usize,
+    stack: Vec<i32>,
+    memory: Vec<(String, i32)>,
+    stuck: bool,
+}
+
+impl fmt::Display for Vm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (l, (i, origin)) in self.code.iter().zip(self.origins.iter()).enumerate() {
+            let marker = if l == self.ip { "=>" } else { "  " };
+            writeln!(
+                f,
+                "{} {:>3}: {:<12} // {}",
+                marker,
+                l,
+                i.to_string(),
+                origin
+            )?;
+        }
+        if self.ip == self.code.len() {
+            writeln!(f, "=> {:>3}: halt", self.ip)?;
+        }
+        writeln!(
+            f,
+            "stack: [{}]",
+            self.stack
+                .iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        )?;
+        write!(f, "memory: {}", show_memory(&self.memory))?;
+        if self.stuck {
+            write!(f, "\nstuck")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn show_memory(memory: &[(String, i32)]) -> String {
+    format!(
+        "[{}]",
+        memory
+            .iter()
+            .map(|(x, i)| format!("{} |-> {}", x, i))
+            .collect::<Vec<String>>()
+            .join(", ")
+    )
+}
+
+impl Vm {
+    // the compiled program, with every variable starting at 0 as in the semantics
+    pub fn compile(pgm: &Pgm) -> Result<Vm, String> {
+        let Pgm::Program(xl, _levels, s) = pgm;
+        let mut compiler = Compiler {
+            vars: xl.clone(),
+            code: vec![],
+            origins: vec![],
+        };
+        compiler.stmt(s)?;
+        Ok(Vm {
+            code: compiler.code,
+            origins: compiler.origins,
+            ip: 0,
+            stack: vec![],
+            memory: xl.iter().map(|x| (x.clone(), 0)).collect(),
+            stuck: false,
+        })
+    }
+    pub fn memory(&self) -> &[(String, i32)] {
+        &self.memory
+    }
+    pub fn is_halted(&self) -> bool {
+        self.ip == self.code.len()
+    }
+    pub fn reset(&mut self) {
+        self.ip = 0;
+        self.stack.clear();
+        self.memory.iter_mut().for_each(|(_x, i)| *i = 0);
+        self.stuck = false;
+    }
+    fn binary(&mut self, op: impl Fn(i32, i32) -> Option<i32>) -> Option<()> {
+        let i2 = self.stack.pop()?;
+        let i1 = self.stack.pop()?;
+        self.stack.push(op(i1, i2)?);
+        Some(())
+    }
+    fn execute(&mut self) -> Option<()> {
+        let mut next = self.ip + 1;
+        match self.code.get(self.ip)?.clone() {
+            Instr::Push(i) => self.stack.push(i),
+            Instr::Load(x) => {
+                let (_x, i) = self.memory.iter().find(|(y, _i)| *y == x)?;
+                self.stack.push(*i);
+            }
+            Instr::Store(x) => {
+                let i = self.stack.pop()?;
+                self.memory.iter_mut().find(|(y, _i)| *y == x)?.1 = i;
+            }
+            Instr::Add => self.binary(|i1, i2| i1.checked_add(i2))?,
+            Instr::Div => self.binary(|i1, i2| i1.checked_div(i2))?,
+            Instr::Leq => self.binary(|i1, i2| Some((i1 <= i2) as i32))?,
+            Instr::Jump(l) => next = l,
+            Instr::JumpZ(l) => {
+                if self.stack.pop()? == 0 {
+                    next = l;
+                }
+            }
+        }
+        self.ip = next;
+        Some(())
+    }
+    // executes one instruction, false if the machine has halted or is stuck, e.g. on division by 0
+    pub fn step(&mut self) -> bool {
+        if self.stuck || self.is_halted() {
+            return false;
+        }
+        self.stuck = self.execute().is_none();
+        !self.stuck
+    }
+    pub fn run(&mut self, limit: usize) -> usize {
+        let mut steps = 0;
+        while steps < limit && self.step() {
+            steps += 1;
+        }
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Rule;
+    use crate::parse::parse;
+    use crate::stack::RewriteRule;
+    use crate::stack::Stack;
+    use crate::stack::RUN_LIMIT;
+
+    // the values the rewrite rules finish the program with
+    fn stepped(s: &str) -> Option<Vec<(String, i32)>> {
+        let mut stack = Stack::<Rule>::create_from_string(s.to_string()).unwrap();
+        stack.run(&Rule::list_of_rules(), RUN_LIMIT);
+        stack.top().final_state()
+    }
+
+    fn agree(s: &str) {
+        let pgm = parse(s.to_string()).unwrap();
+        let mut vm = Vm::compile(&pgm).unwrap();
+        vm.run(RUN_LIMIT);
+        assert!(vm.is_halted());
+        assert_eq!(Some(vm.memory().to_vec()), stepped(s));
+    }
+    #[test]
+    fn test_agree() {
+        agree("int x, y; x = 10; while (1 <= x) { y = y + x; x = x / 2; }");
+        agree("int x, y; for (x = 0; x <= 5; x = x + 1) { if (!(x <= 2)) { y = y + 1; } }");
+        agree("int x, y; do { x = x + 1; } while !(3 <= x); y = 12 / (x + 1);");
+        // neither runs &&
+        let and = "int x; if (x <= 0 && 0 <= x) { x = 1; } else { }";
+        assert!(Vm::compile(&parse(and.to_string()).unwrap()).is_err());
+        assert_eq!(stepped(and), None);
+    }
+    #[test]
+    fn test_stuck() {
+        let pgm = parse("int x; x = 1 / x;".to_string()).unwrap();
+        let mut vm = Vm::compile(&pgm).unwrap();
+        vm.run(10000);
+        assert!(!vm.is_halted());
+        assert!(vm.to_string().contains("stuck"));
+    }
+}
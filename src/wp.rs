@@ -0,0 +1,265 @@
+use std::fmt;
+
+use crate::ast::BExp;
+use crate::ast::Block;
+use crate::ast::Span;
+use crate::ast::Stmt;
+use crate::hoare::valid;
+
+// wp(S, Q) together with the program annotated with the intermediate predicates
+// and the verification conditions P ==> Q generated by the loops
+#[derive(Clone, PartialEq, Debug)]
+pub struct Wp {
+    pub pre: BExp,
+    pub post: BExp,
+    pub lines: Vec<String>,
+    pub conditions: Vec<(BExp, BExp)>,
+}
+
+impl fmt::Display for Wp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{{{}}}", self.pre)?;
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        write!(f, "{{{}}}", self.post)?;
+        for (p, q) in &self.conditions {
+            let status = if valid(p, q) { "valid" } else { "INVALID" };
+            write!(f, "\n{} ==> {}  ({})", p, q, status)?;
+        }
+        Ok(())
+    }
+}
+
+fn and(b1: BExp, b2: BExp) -> BExp {
+    BExp::And(Box::new(b1), Box::new(b2), Span::default())
+}
+
+fn not(b: BExp) -> BExp {
+    BExp::Negation(Box::new(b), Span::default())
+}
+
+// B ==> P, written with the connectives of IMP
+fn implies(b: BExp, p: BExp) -> BExp {
+    not(and(b, not(p)))
+}
+
+pub fn indent(lines: Vec<String>) -> Vec<String> {
+    lines.into_iter().map(|l| format!("  {}", l)).collect()
+}
+
+// the number of invariants the statement needs, one per loop in program order
+pub fn loops(s: &Stmt) -> usize {
+    match s {
+        Stmt::StmtBlock(b, _) => loops_block(b),
+        Stmt::Sequence(s1, s2, _) => loops(s1) + loops(s2),
+        Stmt::IfThenElse(_, b1, b2, _) | Stmt::Choice(b1, b2, _) => {
+            loops_block(b1) + loops_block(b2)
+        }
+        Stmt::IfThen(_b, b1, _) => loops_block(b1),
+        Stmt::While(_b, b1, _) | Stmt::DoWhile(b1, _b, _) => 1 + loops_block(b1),
+        Stmt::For(_s1, _b, _s2, b1, _) => 1 + loops_block(b1),
+        _ => 0,
+    }
+}
+
+fn loops_block(b: &Block) -> usize {
+    match b {
+        Block::EmptyBlock => 0,
+        Block::BlockStmt(s, _) => loops(s),
+    }
+}
+
+struct Annotator {
+    conditions: Vec<(BExp, BExp)>,
+}
+
+impl Annotator {
+    // the precondition of s and its annotated lines, without the pre- and postcondition
+    fn stmt(&mut self, s: &Stmt, q: BExp, invs: &[BExp]) -> Result<(BExp, Vec<String>), String> {
+        match s {
+            Stmt::StmtBlock(b, _) => {
+                let (p, lines) = self.block(b, q, invs)?;
+                let mut v = vec!["{".to_string()];
+                v.extend(indent(lines));
+                v.push("}".to_string());
+                Ok((p, v))
+            }
+            Stmt::Assign(x, a, _) => Ok((
+                q.substitute(x, a).simplify(),
+                vec![format!("{} = {} ;", x, a)],
+            )),
+            Stmt::Sequence(s1, s2, _) => {
+                let (invs1, invs2) = invs.split_at(loops(s1));
+                let (p2, l2) = self.stmt(s2, q, invs2)?;
+                let (p1, mut l1) = self.stmt(s1, p2.clone(), invs1)?;
+                l1.push(format!("{{{}}}", p2));
+                l1.extend(l2);
+                Ok((p1, l1))
+            }
+            Stmt::IfThenElse(b, b1, b2, _) => {
+                let (invs1, invs2) = invs.split_at(loops_block(b1));
+                let (p1, l1) = self.block(b1, q.clone(), invs1)?;
+                let (p2, l2) = self.block(b2, q, invs2)?;
+                let mut v = vec![format!("if {} {{", b)];
+                v.extend(indent(annotated(&p1, l1)));
+                v.push("} else {".to_string());
+                v.extend(indent(annotated(&p2, l2)));
+                v.push("}".to_string());
+                let pre = and(implies(*b.clone(), p1), implies(not(*b.clone()), p2));
+                Ok((pre.simplify(), v))
+            }
+            Stmt::IfThen(b, b1, _) => {
+                let (p1, l1) = self.block(b1, q.clone(), invs)?;
+                let mut v = vec![format!("if {} {{", b)];
+                v.extend(indent(annotated(&p1, l1)));
+                v.push("}".to_string());
+                let pre = and(implies(*b.clone(), p1), implies(not(*b.clone()), q));
+                Ok((pre.simplify(), v))
+            }
+            Stmt::Choice(b1, b2, _) => {
+                let (invs1, invs2) = invs.split_at(loops_block(b1));
+                let (p1, l1) = self.block(b1, q.clone(), invs1)?;
+                let (p2, l2) = self.block(b2, q, invs2)?;
+                let mut v = vec!["{".to_string()];
+                v.extend(indent(annotated(&p1, l1)));
+                v.push("} [] {".to_string());
+                v.extend(indent(annotated(&p2, l2)));
+                v.push("}".to_string());
+                Ok((and(p1, p2).simplify(), v))
+            }
+            Stmt::While(b, b1, _) => {
+                let (i, invs) = invs.split_first().ok_or("Missing loop invariant")?;
+                let (p1, l1) = self.block(b1, i.clone(), invs)?;
+                self.conditions
+                    .push((and(i.clone(), *b.clone()).simplify(), p1.clone()));
+                self.conditions
+                    .push((and(i.clone(), not(*b.clone())).simplify(), q));
+                let mut v = vec![format!("while {} {{  // invariant {}", b, i)];
+                v.extend(indent(annotated(&p1, l1)));
+                v.extend(indent(vec![format!("{{{}}}", i)]));
+                v.push("}".to_string());
+                Ok((i.clone(), v))
+            }
+            Stmt::DoWhile(b1, b, _) => {
+                let (i, invs) = invs.split_first().ok_or("Missing loop invariant")?;
+                let (p1, l1) = self.block(b1, i.clone(), invs)?;
+                self.conditions
+                    .push((and(i.clone(), *b.clone()).simplify(), p1.clone()));
+                self.conditions
+                    .push((and(i.clone(), not(*b.clone())).simplify(), q));
+                let mut v = vec!["do {".to_string()];
+                v.extend(indent(l1));
+                v.extend(indent(vec![format!("{{{}}}", i)]));
+                v.push(format!("}} while {} ;  // invariant {}", b, i));
+                Ok((p1, v))
+            }
+            // for (init; B; update) S is init; while (B) { S update }
+            Stmt::For(init, b, update, b1, _) => {
+                let (i, invs) = invs.split_first().ok_or("Missing loop invariant")?;
+                let (pu, lu) = self.stmt(update, i.clone(), &[])?;
+                let (p1, mut l1) = self.block(b1, pu.clone(), invs)?;
+                self.conditions
+                    .push((and(i.clone(), *b.clone()).simplify(), p1.clone()));
+                self.conditions
+                    .push((and(i.clone(), not(*b.clone())).simplify(), q));
+                let (pi, li) = self.stmt(init, i.clone(), &[])?;
+                let mut v = li;
+                v.push(format!("{{{}}}", i));
+                v.push(format!("for (...; {}; ...) {{  // invariant {}", b, i));
+                l1.push(format!("{{{}}}", pu));
+                l1.extend(lu);
+                l1.push(format!("{{{}}}", i));
+                v.extend(indent(annotated(&p1, l1)));
+                v.push("}".to_string());
+                Ok((pi, v))
+            }
+            s => Err(format!(
+                "wp is not defined for exceptions or pointers: {}",
+                s.to_string().trim()
+            )),
+        }
+    }
+    fn block(&mut self, b: &Block, q: BExp, invs: &[BExp]) -> Result<(BExp, Vec<String>), String> {
+        match b {
+            Block::EmptyBlock => Ok((q, vec![])),
+            Block::BlockStmt(s, _) => self.stmt(s, q, invs),
+        }
+    }
+}
+
+pub fn annotated<P: fmt::Display>(p: &P, lines: Vec<String>) -> Vec<String> {
+    let mut v = vec![format!("{{{}}}", p)];
+    v.extend(lines);
+    v
+}
+
+// wp(S, Q), with one invariant for each loop of S in program order
+pub fn wp(s: &Stmt, q: &BExp, invariants: &[BExp]) -> Result<Wp, String> {
+    if loops(s) != invariants.len() {
+        return Err(format!(
+            "Expected {} loop invariants, got {}",
+            loops(s),
+            invariants.len()
+        ));
+    }
+    // substitution for x = a would miss a read of x through a pointer
+    if let Some(p) = std::iter::once(q).chain(invariants).find(|p| p.derefs()) {
+        return Err(format!(
+            "wp is not defined for assertions with pointers: {}",
+            p
+        ));
+    }
+    let mut annotator = Annotator { conditions: vec![] };
+    let (pre, lines) = annotator.stmt(s, q.clone(), invariants)?;
+    Ok(Wp {
+        pre,
+        post: q.clone(),
+        lines,
+        conditions: annotator.conditions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Pgm;
+    use crate::parse::parse;
+    use crate::parse::parse_bexp;
+
+    fn body(s: &str) -> Stmt {
+        match parse(s.to_string()).unwrap() {
+            Pgm::Program(_xl, _levels, s) => s,
+        }
+    }
+    #[test]
+    fn test_assignments() {
+        let s = body("int x, y; x = 1; y = x + 2;");
+        let q = parse_bexp("y <= 3".to_string()).unwrap();
+        let w = wp(&s, &q, &[]).unwrap();
+        assert_eq!(w.pre, BExp::Bool(true, Span::default()));
+        assert_eq!(w.lines.len(), 3);
+        assert!(w.conditions.is_empty());
+    }
+    #[test]
+    fn test_loop() {
+        let s = body("int x; while (x <= 9) { x = x + 1; }");
+        let q = parse_bexp("!(x <= 9) && x <= 10".to_string()).unwrap();
+        let invs = vec![parse_bexp("x <= 10".to_string()).unwrap()];
+        assert!(wp(&s, &q, &[]).is_err());
+        let w = wp(&s, &q, &invs).unwrap();
+        assert_eq!(w.pre, invs[0]);
+        assert!(w.conditions.iter().all(|(p, q)| valid(p, q)));
+    }
+    #[test]
+    fn test_pointers() {
+        // *0 is x, which the assignment changes
+        let s = body("int x; x = 1;");
+        let q = parse_bexp("*0 <= 0".to_string()).unwrap();
+        assert!(wp(&s, &q, &[]).is_err());
+        let s = body("int x; while (x <= 9) { x = x + 1; }");
+        let q = parse_bexp("x <= 10".to_string()).unwrap();
+        let invs = vec![parse_bexp("*0 <= 10".to_string()).unwrap()];
+        assert!(wp(&s, &q, &invs).is_err());
+    }
+}